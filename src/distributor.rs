@@ -1,15 +1,56 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::OsString;
 use std::fs::File;
-use std::io::Read;
-use std::path::Path;
+use std::io::{BufReader, BufWriter, IsTerminal, Read, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use indicatif::{ProgressBar, ProgressStyle};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 
 use crate::distributor::DistributorResultType::{Copied, Same, UpToDate};
-use crate::distributor_cache_db::FileDistributorCache;
-use crate::distributor_config::DistributorItem;
+use crate::distributor_cache_db::{get_file_last_modified_timestamp, FileDistributorCache, HashAlgorithm};
+use crate::distributor_archive::{archive_item_to, is_archive_target};
+use crate::distributor_config::{expand_path, normalize_path, DistributorConfigError, DistributorItem};
+use crate::distributor_manifest::{HashingWriter, Manifest, ManifestEntry};
+use crate::distributor_prompt::OverwritePrompt;
+use crate::distributor_remote;
+use crate::distributor_remote::SftpSessions;
+use crate::distributor_throttle::{RateLimiter, ThrottledWriter};
 
 #[derive(Debug)]
 pub enum DistributorError {
     IoError(std::io::Error),
+    /// 目标路径中应为目录的部分已作为文件存在。
+    TargetParentIsFile(PathBuf),
+    /// 目标文件已存在且不可替换。
+    TargetExists(PathBuf),
+    /// 分发源文件至目标文件失败 (source, target, error)。
+    DistributeFailed(PathBuf, PathBuf, Box<DistributorError>),
+    /// 远程目标格式无效。
+    InvalidRemoteTarget(String),
+    /// 远程操作失败。
+    RemoteError(String),
+    /// 钩子命令以非零状态退出 (command, code)。
+    HookFailed(String, Option<i32>),
+    /// 扁平化时源文件与其他源文件同名。
+    NameCollision(PathBuf),
+    /// root 不存在。
+    RootNotFound(PathBuf),
+    /// 源目录无法读取，已跳过 (path, error)。
+    SourceUnreadable(PathBuf, std::io::Error),
+    /// 路径不是配置条目的本地目标。
+    NotLocalTarget(PathBuf),
+    /// 多个目标中的同一文件内容不同，无法确定拉取哪一个 (target, other)。
+    PullConflict(PathBuf, PathBuf),
+    /// 源文件超过 max_files 或 max_total_bytes 限制，条目未被分发。
+    SourceLimitExceeded(DistributorConfigError),
+    /// 无法收集源文件，例如 ignore glob 无效或多个根目录下存在相同相对路径的文件，条目未被分发。
+    SourceCollectFailed(DistributorConfigError),
 }
 
 impl From<std::io::Error> for DistributorError {
@@ -18,140 +59,799 @@ impl From<std::io::Error> for DistributorError {
     }
 }
 
+impl std::fmt::Display for DistributorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DistributorError::IoError(e) => write!(f, "io error: {}", e),
+            DistributorError::TargetParentIsFile(p) => {
+                write!(f, "target parent {:?} exists as a file, not a directory", p)
+            }
+            DistributorError::TargetExists(p) => {
+                write!(f, "target {:?} already exists, use --force to replace it", p)
+            }
+            DistributorError::DistributeFailed(s, t, e) => write!(f, "{:?} -> {:?}: {}", s, t, e),
+            DistributorError::InvalidRemoteTarget(t) => write!(f, "remote target {:?} is invalid", t),
+            DistributorError::RemoteError(e) => write!(f, "remote error: {}", e),
+            DistributorError::HookFailed(command, Some(code)) => {
+                write!(f, "hook {:?} exited with code {}", command, code)
+            }
+            DistributorError::HookFailed(command, None) => write!(f, "hook {:?} was terminated", command),
+            DistributorError::NameCollision(p) => {
+                write!(f, "source {:?} has the same file name as another source, cannot flatten", p)
+            }
+            DistributorError::RootNotFound(p) => write!(f, "root {:?} does not exist", p),
+            DistributorError::SourceUnreadable(p, e) => write!(f, "cannot read source {:?}, skipped: {}", p, e),
+            DistributorError::NotLocalTarget(p) => write!(f, "{:?} is not a local target of the distributor", p),
+            DistributorError::PullConflict(t, o) => {
+                write!(f, "{:?} conflicts with {:?}, both differ from source", t, o)
+            }
+            DistributorError::SourceLimitExceeded(e) => write!(f, "{}, use --force to ignore", e),
+            DistributorError::SourceCollectFailed(e) => write!(f, "cannot collect source files: {}", e),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum DistributorResultType {
-    Copied(String, String),
-    Same(String, String),
+    Copied(PathBuf, PathBuf),
+    Same(PathBuf, PathBuf),
     Saved,
-    UpToDate(String),
+    UpToDate(PathBuf),
+    Moved(PathBuf),
+    Linked(PathBuf, PathBuf),
+    Removed(PathBuf),
+    Archived(PathBuf, PathBuf),
+    Skipped(PathBuf, PathBuf),
+    /// 源文件大于 max_file_size 而被跳过 (source, size)。
+    TooLarge(PathBuf, u64),
 }
 
 pub type DistributorResult = Result<DistributorResultType, DistributorError>;
 
+/// # 分发结果记录
+/// 用于机器可读的输出。
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct DistributorResultRecord {
+    pub action: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl DistributorResultRecord {
+    fn new(action: &str, source: Option<&PathBuf>, target: Option<&PathBuf>) -> Self {
+        DistributorResultRecord {
+            action: action.to_string(),
+            source: source.map(|source| source.to_string_lossy().to_string()),
+            target: target.map(|target| target.to_string_lossy().to_string()),
+            error: None,
+            reason: None,
+        }
+    }
+}
+
+impl From<&DistributorResult> for DistributorResultRecord {
+    fn from(result: &DistributorResult) -> Self {
+        match result {
+            Ok(Copied(f, t)) => DistributorResultRecord::new("Copied", Some(f), Some(t)),
+            Ok(Same(f, t)) => DistributorResultRecord::new("Same", Some(f), Some(t)),
+            Ok(DistributorResultType::Saved) => DistributorResultRecord::new("Saved", None, None),
+            Ok(UpToDate(f)) => DistributorResultRecord::new("UpToDate", Some(f), None),
+            Ok(DistributorResultType::Moved(f)) => DistributorResultRecord::new("Moved", Some(f), None),
+            Ok(DistributorResultType::Linked(f, t)) => DistributorResultRecord::new("Linked", Some(f), Some(t)),
+            Ok(DistributorResultType::Removed(t)) => DistributorResultRecord::new("Removed", None, Some(t)),
+            Ok(DistributorResultType::Archived(f, t)) => DistributorResultRecord::new("Archived", Some(f), Some(t)),
+            Ok(DistributorResultType::Skipped(f, t)) => DistributorResultRecord::new("Skipped", Some(f), Some(t)),
+            Ok(DistributorResultType::TooLarge(f, size)) => DistributorResultRecord {
+                reason: Some(format!("{} bytes exceeds max file size", size)),
+                ..DistributorResultRecord::new("Skipped", Some(f), None)
+            },
+            Err(DistributorError::DistributeFailed(s, t, e)) => DistributorResultRecord {
+                action: "Error".to_string(),
+                source: Some(s.to_string_lossy().to_string()),
+                target: Some(t.to_string_lossy().to_string()),
+                error: Some(e.to_string()),
+                reason: None,
+            },
+            Err(e) => DistributorResultRecord {
+                error: Some(e.to_string()),
+                ..DistributorResultRecord::new("Error", None, None)
+            },
+        }
+    }
+}
+
+/// # 运行结果汇总
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct RunSummary {
+    pub copied: usize,
+    /// 复制写入的总字节数。
+    pub bytes: u64,
+    pub linked: usize,
+    pub same: usize,
+    pub up_to_date: usize,
+    pub moved: usize,
+    pub removed: usize,
+    pub archived: usize,
+    pub skipped: usize,
+    pub errors: usize,
+}
+
+impl RunSummary {
+    pub fn from_results(results: &[DistributorResult]) -> Self {
+        let mut summary = RunSummary::default();
+        for result in results {
+            match result {
+                Ok(Copied(f, t)) => {
+                    summary.copied += 1;
+                    summary.bytes += get_copied_bytes(f, t);
+                }
+                Ok(DistributorResultType::Linked(_, _)) => summary.linked += 1,
+                Ok(Same(_, _)) => summary.same += 1,
+                Ok(UpToDate(_)) => summary.up_to_date += 1,
+                Ok(DistributorResultType::Moved(_)) => summary.moved += 1,
+                Ok(DistributorResultType::Removed(_)) => summary.removed += 1,
+                Ok(DistributorResultType::Archived(_, _)) => summary.archived += 1,
+                Ok(DistributorResultType::Skipped(_, _) | DistributorResultType::TooLarge(_, _)) => summary.skipped += 1,
+                Ok(DistributorResultType::Saved) => {}
+                Err(_) => summary.errors += 1,
+            }
+        }
+
+        summary
+    }
+}
+
+impl std::fmt::Display for RunSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} files copied", self.copied)?;
+        if self.bytes > 0 {
+            write!(f, " ({})", indicatif::HumanBytes(self.bytes))?;
+        }
+        for (count, action) in [
+            (self.linked, "linked"),
+            (self.same, "same"),
+            (self.up_to_date, "up to date"),
+            (self.moved, "moved"),
+            (self.removed, "removed"),
+            (self.archived, "archived"),
+            (self.skipped, "skipped"),
+        ] {
+            if count > 0 {
+                write!(f, ", {} {}", count, action)?;
+            }
+        }
+        write!(f, ", {} {}", self.errors, if self.errors == 1 { "error" } else { "errors" })
+    }
+}
+
+/// # Distributor 运行选项
+#[derive(Debug, Default, Clone)]
+pub struct RunOptions {
+    /// 忽略缓存，强制复制。
+    pub force: bool,
+
+    /// 输出详细程度。
+    pub verbosity: Verbosity,
+
+    /// 不读写缓存，仅比较源文件与目标文件的修改时间。
+    /// 源文件较新或目标文件不存在时复制。
+    pub mtime_only: bool,
+
+    /// 不读写缓存，仅复制 git 中已修改或未跟踪的文件。
+    /// root 不在 git 仓库中时回退至常规遍历。
+    pub changed_only: bool,
+
+    /// 仅报告将执行的操作，不写入文件，不更新缓存。
+    pub dry_run: bool,
+
+    /// 并行复制的线程数，为 0 时使用 CPU 数量。
+    pub threads: usize,
+
+    /// 复制到所有目标成功后删除源文件。
+    pub move_source: bool,
+
+    /// 以链接代替复制。
+    pub link: Option<LinkMode>,
+
+    /// 复制后删除目标目录中源目录不存在的文件。
+    pub mirror: bool,
+
+    /// 首个错误后停止复制，不再处理剩余的文件、目标与条目。
+    pub fail_fast: bool,
+
+    /// 复制后在目标中创建源中的空目录。
+    pub include_empty_dirs: bool,
+
+    /// 复制后保留源文件的权限与修改时间。
+    pub preserve: bool,
+
+    /// 应用于所有条目的 ignore。
+    pub global_ignore: Vec<String>,
+
+    /// 未配置目标的条目以此目录下与条目同名的子目录为目标。
+    pub default_target: Option<PathBuf>,
+
+    /// 复制时替换文本文件中的 `{{key}}`。
+    pub template: Option<Template>,
+
+    /// 远程目标的 SSH 私钥路径，未指定时使用 SSH agent。
+    pub identity: Option<PathBuf>,
+
+    /// 先写入目标目录下的临时文件，完成后再替换目标文件。
+    pub atomic: bool,
+
+    /// 目标文件已存在且内容不同时的处理方式。
+    pub on_conflict: ConflictStrategy,
+
+    /// 按 on_conflict 覆盖目标文件前询问，拒绝时跳过。
+    pub prompt: Option<Arc<OverwritePrompt>>,
+
+    /// 跳过大于此字节数的源文件。条目的 max_file_size 更小时以条目为准。
+    pub max_file_size: Option<u64>,
+
+    /// 仅分发修改时间晚于此时刻的源文件，不论缓存状态。
+    pub since: Option<SystemTime>,
+
+    /// 不保留源目录结构，以文件名将源文件直接放置于目标目录。
+    /// 同名的源文件均不复制，报告为错误。
+    pub flatten: bool,
+
+    /// 写入速率限制，在进程内所有目标与线程间共享。
+    pub limit: Option<Arc<RateLimiter>>,
+
+    /// 记录写入的目标文件的哈希与大小。
+    pub manifest: Option<Arc<Manifest>>,
+
+    /// 校验清单使用的哈希算法。
+    pub hash_algorithm: HashAlgorithm,
+
+    /// 复制与比较文件时的缓冲区大小，None 时为 DEFAULT_BUFFER_SIZE。
+    pub buffer_size: Option<NonZeroUsize>,
+
+    /// 远程目标的连接，克隆的运行选项间共享。
+    pub sftp_sessions: Arc<SftpSessions>,
+
+    /// 每个文件分发完成或产生其他成功结果时调用。
+    pub on_result: Option<Arc<ResultCallback>>,
+}
+
+/// 复制与比较文件时默认的缓冲区大小。
+pub const DEFAULT_BUFFER_SIZE: usize = 64 * 1024;
+
+/// 获取复制与比较文件时的缓冲区大小。
+fn get_buffer_size(options: &RunOptions) -> usize {
+    options.buffer_size.map_or(DEFAULT_BUFFER_SIZE, NonZeroUsize::get)
+}
+
+/// 获取复制写入的字节数，即目标文件的大小。
+/// 目标文件不可读时（如 dry run 或远程目标）以源文件的大小计。
+fn get_copied_bytes(source: &Path, target: &Path) -> u64 {
+    std::fs::metadata(target)
+        .or_else(|_| std::fs::metadata(source))
+        .map(|meta| meta.len())
+        .unwrap_or(0)
+}
+
+/// # 冲突处理方式
+#[derive(Debug, Default, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum ConflictStrategy {
+    /// 覆盖目标文件。
+    #[default]
+    Overwrite,
+    /// 跳过目标文件。
+    Skip,
+    /// 仅当源文件较目标文件新时覆盖。
+    Newer,
+}
+
+/// # 模板
+#[derive(Debug, Default, Clone)]
+pub struct Template {
+    /// 模板变量。
+    pub variables: BTreeMap<String, String>,
+
+    /// 需替换的文件扩展名，为空时使用默认扩展名。
+    pub extensions: Vec<String>,
+}
+
+/// 默认的模板文件扩展名。
+static DEFAULT_TEMPLATE_EXTENSIONS: [&str; 10] = ["txt", "md", "conf", "cfg", "ini", "json", "toml", "yaml", "yml", "xml"];
+
+impl Template {
+    /// 合并条目中的变量与扩展名，条目中的值优先。
+    pub fn with_item(&self, config_item: &DistributorItem) -> Template {
+        let mut variables = self.variables.clone();
+        variables.extend(config_item.variables.iter().map(|(k, v)| (k.clone(), v.clone())));
+
+        Template {
+            variables,
+            extensions: if config_item.template_extensions.is_empty() {
+                self.extensions.clone()
+            } else {
+                config_item.template_extensions.clone()
+            },
+        }
+    }
+
+    /// 文件是否需要替换。
+    pub fn is_template_file(&self, path: &Path) -> bool {
+        let Some(extension) = path.extension().and_then(|e| e.to_str()) else {
+            return false;
+        };
+
+        if self.extensions.is_empty() {
+            DEFAULT_TEMPLATE_EXTENSIONS.iter().any(|e| e.eq_ignore_ascii_case(extension))
+        } else {
+            self.extensions.iter().any(|e| e.trim_start_matches('.').eq_ignore_ascii_case(extension))
+        }
+    }
+
+    /// 替换文本中的 `{{key}}`，未定义的变量保持不变。
+    pub fn render(&self, content: &str) -> String {
+        let mut rendered = content.to_string();
+        for (key, value) in self.variables.iter() {
+            rendered = rendered.replace(&format!("{{{{{}}}}}", key), value);
+        }
+
+        rendered
+    }
+}
+
+/// # 链接模式
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum LinkMode {
+    /// 符号链接。
+    Symbolic,
+    /// 硬链接，跨设备时回退至复制。
+    Hard,
+}
+
+/// # 输出详细程度
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// 仅输出错误。
+    Quiet,
+    /// 输出发生变化的文件。
+    #[default]
+    Normal,
+    /// 额外输出未变化的文件。
+    Verbose,
+    /// 额外输出每个文件的耗时。
+    Trace,
+}
+
+impl Verbosity {
+    /// 由命令行参数获取输出详细程度。
+    ///
+    /// # Param
+    ///
+    /// - `verbose` - `-v` 的重复次数。
+    /// - `quiet` - 是否仅输出错误。
+    pub fn from_flags(verbose: u8, quiet: bool) -> Self {
+        match (quiet, verbose) {
+            (true, _) => Verbosity::Quiet,
+            (false, 0) => Verbosity::Normal,
+            (false, 1) => Verbosity::Verbose,
+            (false, _) => Verbosity::Trace,
+        }
+    }
+
+    /// 获取对应的 tracing 事件级别。
+    pub fn level_filter(self) -> tracing::level_filters::LevelFilter {
+        match self {
+            Verbosity::Quiet => tracing::level_filters::LevelFilter::ERROR,
+            Verbosity::Normal => tracing::level_filters::LevelFilter::INFO,
+            Verbosity::Verbose => tracing::level_filters::LevelFilter::DEBUG,
+            Verbosity::Trace => tracing::level_filters::LevelFilter::TRACE,
+        }
+    }
+}
+
+/// # 结果回调
+/// 每产生一个成功结果时调用一次，在所有复制线程间共享，同一时刻仅有一个调用。
+pub struct ResultCallback(Mutex<Box<OnResult>>);
+
+/// 结果回调函数。
+type OnResult = dyn FnMut(&DistributorResultType) + Send;
+
+impl ResultCallback {
+    pub fn new(on_result: impl FnMut(&DistributorResultType) + Send + 'static) -> Self {
+        ResultCallback(Mutex::new(Box::new(on_result)))
+    }
+
+    /// 以成功的结果调用回调，错误结果将被忽略。
+    pub fn call(&self, result: &DistributorResult) {
+        if let Ok(result) = result {
+            (self.0.lock().unwrap())(result);
+        }
+    }
+}
+
+impl std::fmt::Debug for ResultCallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResultCallback").finish_non_exhaustive()
+    }
+}
+
 pub struct Distributor {
     pub db_cache: FileDistributorCache,
+    pub verbosity: Verbosity,
+    on_result: Option<Arc<ResultCallback>>,
 }
 
 impl Distributor {
-    pub fn new() -> Self {
+    pub fn new(verbosity: Verbosity) -> Self {
+        Self::from_cache_path(None, verbosity)
+    }
+
+    /// 加载指定路径的缓存并创建 Distributor，路径为 None 时使用默认路径。
+    pub fn from_cache_path(cache_path: Option<&Path>, verbosity: Verbosity) -> Self {
+        Self::with_cache(FileDistributorCache::load(cache_path), verbosity)
+    }
+
+    /// 使用已加载的缓存创建 Distributor。
+    pub fn with_cache(db_cache: FileDistributorCache, verbosity: Verbosity) -> Self {
         Distributor {
-            db_cache: FileDistributorCache::load(None),
+            db_cache,
+            verbosity,
+            on_result: None,
+        }
+    }
+
+    /// 设置每个成功结果的回调，默认不做任何事。
+    /// 回调在每个文件分发完成时调用，并发复制时按完成顺序调用，与是否打印结果无关。
+    /// 设置后将替代运行选项中的 `on_result`。
+    pub fn set_on_result(&mut self, on_result: impl FnMut(&DistributorResultType) + Send + 'static) {
+        self.on_result = Some(Arc::new(ResultCallback::new(on_result)));
+    }
+
+    /// 获取使用 Distributor 回调的运行选项。
+    fn with_on_result<'a>(&self, options: &'a RunOptions) -> std::borrow::Cow<'a, RunOptions> {
+        match &self.on_result {
+            Some(on_result) => {
+                std::borrow::Cow::Owned(RunOptions { on_result: Some(on_result.clone()), ..options.clone() })
+            }
+            None => std::borrow::Cow::Borrowed(options),
+        }
+    }
+
+    /// 分发配置条目，返回所有结果，不输出结果。
+    pub fn do_copy(&mut self, config_item: &DistributorItem, options: &RunOptions) -> Vec<DistributorResult> {
+        let options = &*self.with_on_result(options);
+        let cache = Mutex::new(std::mem::take(&mut self.db_cache));
+        let results = Self::distribute_item(&cache, config_item, options);
+        self.db_cache = cache.into_inner().unwrap();
+
+        results
+    }
+
+    /// 以至多 jobs 个线程并发分发多个配置条目，各条目共享缓存。
+    /// 返回与 items 顺序一致的各条目的结果。fail_fast 时首个错误后不再开始新的条目，未开始的条目结果为空。
+    ///
+    /// # Param
+    ///
+    /// - `items` - 配置条目。
+    /// - `options` - 运行选项。
+    /// - `jobs` - 同时分发的条目数，为 0 时视为 1。
+    pub fn do_copy_items(&mut self,
+                         items: &[&DistributorItem],
+                         options: &RunOptions,
+                         jobs: usize) -> Vec<Vec<DistributorResult>> {
+        let options = &*self.with_on_result(options);
+        let cache = Mutex::new(std::mem::take(&mut self.db_cache));
+        let failed = AtomicBool::new(false);
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs.max(1))
+            .build()
+            .unwrap();
+        let item_results: Vec<Vec<DistributorResult>> = pool.install(|| {
+            items.par_iter()
+                 .map(|config_item| {
+                     if options.fail_fast && failed.load(Ordering::SeqCst) {
+                         return vec![];
+                     }
+                     let results = Self::distribute_item(&cache, config_item, options);
+                     if results.iter().any(|result| result.is_err()) {
+                         failed.store(true, Ordering::SeqCst);
+                     }
+                     results
+                 })
+                 .collect()
+        });
+        self.db_cache = cache.into_inner().unwrap();
+
+        item_results
+    }
+
+    /// 分发配置条目，仅在访问缓存时持有锁。
+    fn distribute_item(cache: &Mutex<FileDistributorCache>,
+                       config_item: &DistributorItem,
+                       options: &RunOptions) -> Vec<DistributorResult> {
+        let mut config_item = config_item.expanded();
+        if let Some(default_target) = options.default_target.as_ref().filter(|_| config_item.to.is_empty()) {
+            config_item.to.push(expand_path(default_target).join(&config_item.name));
+        }
+        let config_item = &config_item;
+        for (outer, inner) in config_item.get_nested_targets() {
+            tracing::warn!(distributor = %config_item.name,
+                           "target {:?} is inside target {:?}, files are copied twice.",
+                           inner,
+                           outer);
+        }
+        let item_options;
+        let options = match &options.template {
+            Some(template) => {
+                item_options = RunOptions {
+                    template: Some(template.with_item(config_item)),
+                    ..options.clone()
+                };
+                &item_options
+            }
+            None => options,
+        };
+        let dry_run = options.dry_run;
+        if !dry_run {
+            if let Err(e) = run_pre_run_hook(config_item) {
+                tracing::error!(distributor = %config_item.name, "aborted. {}.", e);
+                return vec![Err(e)];
+            }
+        }
+        // root 可能由 pre_run 生成，在其后检查。
+        if !config_item.root.exists() {
+            let e = DistributorError::RootNotFound(config_item.root.clone());
+            tracing::error!(distributor = %config_item.name, "aborted. {}.", e);
+            return vec![Err(e)];
+        }
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(options.threads)
+            .build()
+            .unwrap();
+        let (archives, to): (Vec<PathBuf>, Vec<PathBuf>) = config_item.to
+                                                                      .iter()
+                                                                      .cloned()
+                                                                      .partition(|to| is_archive_target(&config_item.root, to));
+        let mut results = vec![];
+        'copy: {
+            for archive in archives.iter() {
+                results.extend(notify_results(archive_item_to(config_item, archive, options), options));
+                if is_failed_fast(&results, options) {
+                    break 'copy;
+                }
+            }
+            let config_item = &DistributorItem { to, ..config_item.clone() };
+            results.extend(pool.install(|| Self::copy_item(cache, config_item, options)));
+            if is_failed_fast(&results, options) {
+                break 'copy;
+            }
+            if options.include_empty_dirs && !options.flatten {
+                results.extend(notify_results(create_empty_target_dir(config_item, options), options));
+            }
+            if options.mirror && !config_item.is_point_to_file() {
+                let removed = remove_orphan_target_file(config_item, options);
+                results.extend(notify_results(removed, options));
+            }
+            if options.move_source && !dry_run {
+                let removed = remove_moved_source(&results, config_item.to.len() + archives.len());
+                results.extend(notify_results(removed, options));
+            }
         }
+        if !dry_run && results.iter().all(|result| result.is_ok()) {
+            cache.lock().unwrap().update_last_run(&config_item.name, SystemTime::now());
+        }
+
+        results
+    }
+
+    /// 分发配置条目，并按输出详细程度打印结果。
+    pub fn do_copy_and_print(&mut self, config_item: &DistributorItem, options: &RunOptions) -> Vec<DistributorResult> {
+        let results = self.do_copy(config_item, options);
+        log_results(&results);
+
+        results
     }
 
-    pub fn do_copy(&mut self, config_item: &DistributorItem, force: bool, debug: bool) {
+    /// 复制配置条目中的文件，返回按源路径排序的结果。
+    /// 按模板替换的文件不读写缓存，每次均与替换后的内容比较。
+    fn copy_item(cache: &Mutex<FileDistributorCache>,
+                 config_item: &DistributorItem,
+                 options: &RunOptions) -> Vec<DistributorResult> {
+        let force = options.force;
+        let dry_run = options.dry_run;
         let mut results = vec![];
-        if config_item.is_point_to_file() {
-            if !force && !self.db_cache.is_file_outdated(&config_item.root) {
-                results.push(
-                    Ok(DistributorResultType::UpToDate(
-                        config_item.root
-                                   .to_str()
-                                   .unwrap()
-                                   .to_string())));
+        let changed = if options.changed_only && !options.mtime_only {
+            let changed = config_item.get_git_changed_source_file(&options.global_ignore);
+            if changed.is_none() {
+                tracing::warn!(root = ?config_item.root, "not in a git repository, fall back to normal walk.");
+            }
+            changed
+        } else {
+            None
+        };
+
+        if options.mtime_only {
+            results = copy_by_mtime(config_item, options);
+        } else if let Some(mut changed) = changed {
+            changed.retain(|source| is_modified_since(source, options.since));
+            if config_item.is_point_to_file() {
+                if changed.contains(&config_item.root) {
+                    let mut root = vec![&config_item.root];
+                    results.extend(notify_results(remove_too_large(&mut root, get_max_file_size(config_item, options)), options));
+                    if root.is_empty() {
+                        return results;
+                    }
+                    let file_name = config_item.root.file_name().unwrap_or_default();
+                    for to in config_item.to.iter() {
+                        results.push(notify_result(copy_file_to_with_default_name(
+                            &config_item.root,
+                            to,
+                            config_item.get_target_file_name(to).map(Path::new).unwrap_or(Path::new(file_name)),
+                            options), options));
+                        if is_failed_fast(&results, options) {
+                            break;
+                        }
+                    }
+                }
             } else {
-                let file_name = config_item.root
-                                           .file_name()
-                                           .and_then(|item| item.to_str())
-                                           .ok_or(DistributorError::IoError(
-                                               std::io::Error::new(std::io::ErrorKind::InvalidInput,
-                                                                   "file name is invalid.")
-                                           ))
-                                           .unwrap();
-                for to in config_item.to.iter() {
-                    results.push(copy_file_to_with_default_name(
-                        &config_item.root.to_path_buf(),
-                        to,
-                        file_name));
+                if !force {
+                    if let Err(e) = config_item.check_source_limit(&changed) {
+                        results.push(Err(DistributorError::SourceLimitExceeded(e)));
+                        return results;
+                    }
                 }
-                self.db_cache.update_file_record(&config_item.root);
-            }
-        } else if let Ok(source_set) = config_item.get_non_root_source_file() {
-            let outdated_source: HashSet<&Path> = source_set
-                .iter()
-                .filter(|source| {
-                    return if force || self.db_cache.is_file_outdated(source) {
-                        true
-                    } else {
-                        results.push(Ok(UpToDate(source.to_str().unwrap().to_string())));
-                        false
-                    };
-                })
-                .map(|item| { item.as_path() })
-                .collect();
-
+                let mut changed: Vec<&PathBuf> = changed.iter().collect();
+                changed.sort();
+                results.extend(copy_sources_to(config_item, changed, &config_item.to, options));
+            }
+        } else if config_item.is_point_to_file() {
+            if !is_modified_since(&config_item.root, options.since) {
+                return results;
+            }
+            let mut root = vec![&config_item.root];
+            results.extend(notify_results(remove_too_large(&mut root, get_max_file_size(config_item, options)), options));
+            if root.is_empty() {
+                return results;
+            }
             for to in config_item.to.iter() {
-                self.copy_by_source_to(&config_item.root, &outdated_source, to)
-                    .into_iter()
-                    .for_each(|r| {
-                        results.push(r);
-                    });
-
-                source_set.iter().for_each(|source| {
-                    self.db_cache.update_file_record(source);
-                });
-            }
-        }
-
-        if debug {
-            for result in results {
-                match result {
-                    Ok(tp) => {
-                        match tp {
-                            Copied(f, t) => {
-                                println!("[Copied]{:?}{:?}", f, t);
-                            }
-                            Same(f, t) => {
-                                println!("[Same]{:?}{:?}", f, t);
-                            }
-                            UpToDate(f) => {
-                                println!("[UpToDate]{:?}", f);
-                            }
-                            DistributorResultType::Saved => {}
-                        }
-                        self.db_cache.update_file_record(&config_item.root);
+                for (source, target) in get_source_target_pairs(config_item, to, &options.global_ignore) {
+                    let rendered = get_file_template(&source, options).is_some();
+                    if !force && !rendered && !cache.lock().unwrap().is_file_outdated(&source, &target) {
+                        results.push(notify_result(Ok(UpToDate(source)), options));
+                        continue;
+                    }
+
+                    let result = notify_result(distribute_file(&source, &target, options), options);
+                    if !dry_run && !rendered && is_record_updated(&result) {
+                        cache.lock().unwrap().update_file_record(&source, &target);
+                    }
+                    results.push(result);
+                    if is_failed_fast(&results, options) {
+                        return results;
                     }
-                    Err(e) => {
-                        println!("[Error {:?}]", e);
+                }
+            }
+        } else {
+            let mut source_set = match config_item.get_non_root_source_file_and_warnings(&options.global_ignore) {
+                Ok((source_set, warnings)) => {
+                    results.extend(warnings.into_iter().map(|w| Err(DistributorError::SourceUnreadable(w.path, w.error))));
+                    source_set
+                }
+                Err(e) => {
+                    results.push(Err(DistributorError::SourceCollectFailed(e)));
+                    return results;
+                }
+            };
+            if is_failed_fast(&results, options) {
+                return results;
+            }
+            source_set.retain(|source| is_modified_since(source, options.since));
+            if !force {
+                if let Err(e) = config_item.check_source_limit(&source_set) {
+                    results.push(Err(DistributorError::SourceLimitExceeded(e)));
+                    return results;
+                }
+            }
+
+            let mut sources: Vec<&PathBuf> = source_set.iter().collect();
+            sources.sort();
+            results.extend(notify_results(remove_too_large(&mut sources, get_max_file_size(config_item, options)), options));
+            if options.flatten {
+                results.extend(remove_name_collision(&mut sources));
+            }
+
+            let mut outdated_pairs: Vec<(PathBuf, PathBuf)> = vec![];
+            {
+                let db_cache = cache.lock().unwrap();
+                for to in config_item.to.iter() {
+                    for source in sources.iter() {
+                        let target = get_target_path(config_item, source, to, options.flatten);
+                        if force || get_file_template(source, options).is_some()
+                            || db_cache.is_file_outdated(source, &target) {
+                            outdated_pairs.push((source.to_path_buf(), target));
+                        } else {
+                            results.push(notify_result(Ok(UpToDate(source.to_path_buf())), options));
+                        }
                     }
                 }
             }
+
+            let copy_results = copy_pairs(&outdated_pairs, options);
+            if !dry_run {
+                let mut db_cache = cache.lock().unwrap();
+                copy_results.iter()
+                            .zip(outdated_pairs.iter())
+                            .filter(|(result, (source, _))| {
+                                is_record_updated(result) && get_file_template(source, options).is_none()
+                            })
+                            .for_each(|(_, (source, target))| {
+                                db_cache.update_file_record(source, target);
+                            });
+            }
+            results.extend(copy_results);
         }
+
+        results
     }
 
-    /// Copy files by source_path to target dir.
+    /// Copy files by source_path to target dirs concurrently.
+    /// 结果按目标、源文件的顺序排列。
     ///
     /// # Param
     ///
-    /// - `root` - 待复制的文件的根路径。
+    /// - `config_item` - 配置条目，目标路径保留源文件相对其所在根目录的路径。
     /// - `source_path` - 待复制的文件的路径。
     /// - `to` - 目标目录。
-    fn copy_by_source_to(&mut self,
-                         root: &Path,
-                         source_paths: impl IntoIterator<Item=impl AsRef<Path>>,
-                         to: &Path) -> Vec<DistributorResult> {
-        let mut successed: Vec<DistributorResult> = Vec::new();
-
-        for source in source_paths {
-            let target_path = to.join(source.as_ref().strip_prefix(root).unwrap());
-
-            successed.push(copy_file_with_full_target_path(source.as_ref(), &target_path));
-        }
-
-        successed
+    /// - `options` - 运行选项。
+    pub fn copy_by_source_to(&mut self,
+                             config_item: &DistributorItem,
+                             source_paths: impl IntoIterator<Item=impl AsRef<Path>>,
+                             to: &[PathBuf],
+                             options: &RunOptions) -> Vec<DistributorResult> {
+        copy_sources_to(config_item, source_paths, to, &self.with_on_result(options))
     }
 
     pub fn clear_cache(&mut self) {
-        let _ = FileDistributorCache::clear(None);
+        let _ = FileDistributorCache::clear(Some(self.db_cache.loaded_path()));
         self.db_cache = FileDistributorCache::default();
     }
 }
 
+/// [`Distributor::copy_by_source_to`] 的实现，不读写缓存。
+fn copy_sources_to(config_item: &DistributorItem,
+                   source_paths: impl IntoIterator<Item=impl AsRef<Path>>,
+                   to: &[PathBuf],
+                   options: &RunOptions) -> Vec<DistributorResult> {
+    let mut source_paths: Vec<_> = source_paths.into_iter().collect();
+    let mut results = notify_results(remove_too_large(&mut source_paths, get_max_file_size(config_item, options)), options);
+    if options.flatten {
+        results.extend(remove_name_collision(&mut source_paths));
+    }
+
+    let mut pairs: Vec<(PathBuf, PathBuf)> = Vec::new();
+    for target in to {
+        for source in source_paths.iter() {
+            let target_path = get_target_path(config_item, source.as_ref(), target, options.flatten);
+            pairs.push((source.as_ref().to_path_buf(), target_path));
+        }
+    }
+
+    results.extend(copy_pairs(&pairs, options));
+    results
+}
+
 impl Drop for Distributor {
     fn drop(&mut self) {
         if !self.db_cache.is_empty() {
-            println!("save cache.");
-            let _ = self.db_cache.save(None);
+            tracing::debug!("save cache.");
+            if let Err(e) = self.db_cache.save(None) {
+                tracing::error!("save cache failed. {}.", e);
+            }
         }
     }
 }
@@ -162,92 +862,2080 @@ impl Drop for Distributor {
 ///
 /// - `source_file_path` - 待复制的文件的路径。
 /// - `target_file_path` - 目标文件的路径，包括文件名。如果路径中的目录不存在，将会被创建。
+/// - `options` - 运行选项。
 pub fn copy_file_with_full_target_path(source_file_path: &Path,
-                                       target_file_path: &Path) -> DistributorResult {
+                                       target_file_path: &Path,
+                                       options: &RunOptions) -> DistributorResult {
+    let dry_run = options.dry_run;
     if target_file_path.is_file() {
-        if let Ok(cmp_result) = compare_file(source_file_path, target_file_path) {
+        if let Ok(cmp_result) = compare_file(source_file_path, target_file_path, get_buffer_size(options)) {
             if cmp_result {
-                return Ok(Same(source_file_path.to_str().unwrap().to_string(),
-                               target_file_path.to_str().unwrap().to_string()));
+                return Ok(Same(source_file_path.to_path_buf(),
+                               target_file_path.to_path_buf()));
             }
         }
     }
-    return match std::fs::read(source_file_path) {
-        Ok(content) => {
-            if let Some(parent_path) = target_file_path.parent() {
-                if !parent_path.exists() {
-                    std::fs::create_dir_all(parent_path)?;
-                }
-            }
-            return match std::fs::write(target_file_path, content) {
-                Ok(_) => {
-                    Ok(Copied(source_file_path.to_str().unwrap().to_string(),
-                              target_file_path.to_str().unwrap().to_string()))
-                }
-                Err(e) => { Err(DistributorError::IoError(e)) }
-            };
-        }
-        Err(e) => {
-            Err(DistributorError::IoError(e))
-        }
-    };
+    prepare_target_parent(target_file_path, dry_run)?;
+    if dry_run {
+        return Ok(Copied(source_file_path.to_path_buf(),
+                         target_file_path.to_path_buf()));
+    }
+    let mut reader = BufReader::with_capacity(get_buffer_size(options), File::open(source_file_path)?);
+    write_target_file(target_file_path, options, |writer| {
+        std::io::copy(&mut reader, writer)?;
+        Ok(())
+    })?;
+
+    Ok(Copied(source_file_path.to_path_buf(),
+              target_file_path.to_path_buf()))
 }
 
-/// Copy file to target path with default name.
+/// 目标文件已存在且与分发结果不同时，是否按冲突处理方式或覆盖确认跳过。
+/// 模板文件以替换后的内容比较。
 ///
 /// # Param
 ///
-/// - `source_file_path` - 待复制的文件的路径。
-/// - `target_path` - 目标文件的路径，如果是文件夹，将会在文件夹中创建一个与源文件同名的文件。
-/// - `default_name` - 如果目标路径是文件夹，将会使用此默认文件名。
-pub fn copy_file_to_with_default_name(source_file_path: &Path,
-                                      target_path: &Path,
-                                      default_name: &str) -> DistributorResult {
-    if target_path.is_file() {
-        copy_file_with_full_target_path(source_file_path, target_path)
-    } else {
-        copy_file_with_full_target_path(source_file_path, &target_path.join(default_name))
+/// - `source_file_path` - 源文件的路径。
+/// - `target_file_path` - 目标文件的路径。
+/// - `options` - 运行选项。
+fn is_conflict_skipped(source_file_path: &Path, target_file_path: &Path, options: &RunOptions) -> bool {
+    if options.on_conflict == ConflictStrategy::Overwrite && options.prompt.is_none() {
+        return false;
     }
+    if !target_file_path.is_file() || is_same_file(source_file_path, target_file_path) {
+        return false;
+    }
+    if is_target_same(source_file_path,
+                      target_file_path,
+                      get_file_template(source_file_path, options),
+                      get_buffer_size(options)) {
+        return false;
+    }
+
+    is_strategy_skipped(source_file_path, target_file_path, options.on_conflict)
+        || options.prompt.as_ref().is_some_and(|prompt| !options.dry_run && !prompt.confirm(target_file_path))
 }
 
-#[derive(Debug)]
-pub enum FileCompareError {
-    IoError(std::io::Error),
+/// 获取替换源文件时使用的模板，链接或源文件不是模板文件时返回 None。
+fn get_file_template<'a>(source_file_path: &Path, options: &'a RunOptions) -> Option<&'a Template> {
+    options.template
+           .as_ref()
+           .filter(|template| options.link.is_none() && template.is_template_file(source_file_path))
 }
 
-impl From<std::io::Error> for FileCompareError {
-    fn from(e: std::io::Error) -> Self {
-        FileCompareError::IoError(e)
+/// 目标文件是否与分发结果相同。指定模板时与替换后的内容比较，非 UTF-8 文件直接比较。
+///
+/// # Param
+///
+/// - `source_file_path` - 源文件的路径。
+/// - `target_file_path` - 目标文件的路径。
+/// - `template` - 源文件使用的模板。
+/// - `buffer_size` - 比较时使用的缓冲区大小。
+fn is_target_same(source_file_path: &Path,
+                  target_file_path: &Path,
+                  template: Option<&Template>,
+                  buffer_size: usize) -> bool {
+    match template.and_then(|template| render_template_file(source_file_path, template).ok().flatten()) {
+        Some(rendered) => std::fs::read(target_file_path).is_ok_and(|content| content == rendered.as_bytes()),
+        None => compare_file(source_file_path, target_file_path, buffer_size).unwrap_or(false),
     }
 }
 
-pub type FileCompareResult = Result<bool, FileCompareError>;
+/// 已存在且内容不同的目标文件是否按冲突处理方式跳过。
+///
+/// # Param
+///
+/// - `source_file_path` - 源文件的路径。
+/// - `target_file_path` - 已存在的目标文件的路径。
+/// - `on_conflict` - 冲突处理方式。
+fn is_strategy_skipped(source_file_path: &Path, target_file_path: &Path, on_conflict: ConflictStrategy) -> bool {
+    match on_conflict {
+        ConflictStrategy::Overwrite => false,
+        ConflictStrategy::Skip => true,
+        ConflictStrategy::Newer => {
+            match (get_file_last_modified_timestamp(source_file_path),
+                   get_file_last_modified_timestamp(target_file_path)) {
+                (Ok(source), Ok(target)) => source <= target,
+                _ => false,
+            }
+        }
+    }
+}
 
-/// 比较文件内容。
+/// Render template file to full target paths.
+/// 非 UTF-8 文件将直接复制。替换后的内容与目标文件相同时返回 Same。
 ///
 /// # Param
 ///
-/// - source_path - 源文件路径
-/// - target_path - 目标文件路径
-fn compare_file(source_path: &Path, target_path: &Path) -> FileCompareResult {
-    let mut file_source_result = File::open(source_path)?;
-    let mut file_target_result = File::open(target_path)?;
+/// - `source_file_path` - 模板文件的路径。
+/// - `target_file_path` - 目标文件的路径，包括文件名。如果路径中的目录不存在，将会被创建。
+/// - `template` - 模板。
+/// - `options` - 运行选项。
+pub fn render_file_with_full_target_path(source_file_path: &Path,
+                                         target_file_path: &Path,
+                                         template: &Template,
+                                         options: &RunOptions) -> DistributorResult {
+    let dry_run = options.dry_run;
+    let Some(rendered) = render_template_file(source_file_path, template)? else {
+        return copy_file_with_full_target_path(source_file_path, target_file_path, options);
+    };
 
-    let mut buffer_1 = [0u8; 1024];
-    let mut buffer_2 = [0u8; 1024];
-    loop {
-        let size_1 = file_source_result.read(&mut buffer_1)?;
-        let size_2 = file_target_result.read(&mut buffer_2)?;
-        if size_1 != size_2 || buffer_1[..size_1] != buffer_2[..size_2] { return Ok(false); }
-        if size_1 == size_2 && size_1 == 0 { return Ok(true); }
+    if target_file_path.is_file() && std::fs::read(target_file_path)? == rendered.as_bytes() {
+        return Ok(Same(source_file_path.to_path_buf(),
+                       target_file_path.to_path_buf()));
     }
+    prepare_target_parent(target_file_path, dry_run)?;
+    if !dry_run {
+        write_target_file(target_file_path, options, |writer| writer.write_all(rendered.as_bytes()))?;
+    }
+
+    Ok(Copied(source_file_path.to_path_buf(),
+              target_file_path.to_path_buf()))
 }
 
-#[cfg(test)]
-mod tests {
-    use std::path::PathBuf;
+/// 读取并替换模板文件，非 UTF-8 文件返回 None。
+fn render_template_file(source_file_path: &Path, template: &Template) -> std::io::Result<Option<String>> {
+    Ok(String::from_utf8(std::fs::read(source_file_path)?).ok().map(|content| template.render(&content)))
+}
+
+/// 写入目标文件。
+/// atomic 时先写入同目录下的临时文件再替换目标，仅在跨设备无法替换时回退为复制临时文件。
+/// 写入受 limit 限速，指定 manifest 时记录写入内容的哈希与大小。
+///
+/// # Param
+///
+/// - `target_file_path` - 目标文件的路径，其所在目录应已存在。
+/// - `options` - 运行选项。
+/// - `write` - 写入内容。
+fn write_target_file(target_file_path: &Path,
+                     options: &RunOptions,
+                     mut write: impl FnMut(&mut dyn Write) -> std::io::Result<()>) -> std::io::Result<()> {
+    let mut write_to = |writer: &mut BufWriter<File>| -> std::io::Result<Option<ManifestEntry>> {
+        let mut writer = HashingWriter::new(ThrottledWriter::new(writer, options.limit.as_deref()),
+                                            options.manifest.as_ref().map(|_| options.hash_algorithm));
+        write(&mut writer)?;
+        Ok(writer.finish())
+    };
+    let record = |entry: Option<ManifestEntry>| {
+        if let (Some(manifest), Some(entry)) = (&options.manifest, entry) {
+            manifest.record(target_file_path, entry);
+        }
+    };
+
+    if !options.atomic {
+        let mut writer = BufWriter::with_capacity(get_buffer_size(options), File::create(target_file_path)?);
+        let entry = write_to(&mut writer)?;
+        writer.flush()?;
+        record(entry);
+        return Ok(());
+    }
+
+    let (temp_path, file) = create_temp_file_beside(target_file_path)?;
+    let result = (|| {
+        let mut writer = BufWriter::with_capacity(get_buffer_size(options), file);
+        let entry = write_to(&mut writer)?;
+        writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        match std::fs::rename(&temp_path, target_file_path) {
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+                std::fs::copy(&temp_path, target_file_path)?;
+                std::fs::remove_file(&temp_path)?;
+            }
+            Err(e) => return Err(e),
+        }
+        Ok(entry)
+    })();
+    match result {
+        Ok(entry) => {
+            record(entry);
+            Ok(())
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(&temp_path);
+            Err(e)
+        }
+    }
+}
+
+/// 临时文件名中的序号，使同一进程内的临时文件名互不相同。
+static TEMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// 在文件所在目录独占创建隐藏的临时文件 `.<name>.<pid>.<序号>.tmp`，不会覆盖已存在的文件。
+///
+/// # Param
+///
+/// - `path` - 临时文件将替换的文件路径。
+pub(crate) fn create_temp_file_beside(path: &Path) -> std::io::Result<(PathBuf, File)> {
+    loop {
+        let mut temp_name = OsString::from(".");
+        temp_name.push(path.file_name().unwrap_or_default());
+        let counter = TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed);
+        temp_name.push(format!(".{}.{}.tmp", std::process::id(), counter));
+        let temp_path = path.with_file_name(temp_name);
+        match File::options().write(true).create_new(true).open(&temp_path) {
+            Ok(file) => return Ok((temp_path, file)),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// 创建目标文件所在的目录。
+/// 目标路径中应为目录的部分已作为文件存在时返回错误。
+///
+/// # Param
+///
+/// - `target_file_path` - 目标文件的路径。
+/// - `dry_run` - 仅检查，不创建目录。
+fn prepare_target_parent(target_file_path: &Path, dry_run: bool) -> Result<(), DistributorError> {
+    if let Some(parent_path) = target_file_path.parent() {
+        if !parent_path.is_dir() {
+            if let Some(file) = parent_path.ancestors().find(|p| p.is_file()) {
+                return Err(DistributorError::TargetParentIsFile(file.to_path_buf()));
+            }
+            if !dry_run {
+                std::fs::create_dir_all(parent_path)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Link file to full target path.
+/// 已存在的符号链接将被替换，其他已存在的文件仅在 `force` 时被替换。
+///
+/// # Param
+///
+/// - `source_file_path` - 源文件的路径。
+/// - `target_file_path` - 目标文件的路径，包括文件名。如果路径中的目录不存在，将会被创建。
+/// - `force` - 替换已存在的非链接文件。
+/// - `dry_run` - 仅报告结果，不写入文件。
+pub fn link_file_to(source_file_path: &Path,
+                    target_file_path: &Path,
+                    force: bool,
+                    dry_run: bool) -> DistributorResult {
+    let source_str = source_file_path.to_path_buf();
+    let target_str = target_file_path.to_path_buf();
+    let absolute_source = source_file_path.canonicalize()?;
+
+    if let Ok(meta) = std::fs::symlink_metadata(target_file_path) {
+        if meta.file_type().is_symlink() {
+            if std::fs::read_link(target_file_path)? == absolute_source {
+                return Ok(Same(source_str, target_str));
+            }
+        } else if !force {
+            return Err(DistributorError::TargetExists(target_file_path.to_path_buf()));
+        }
+        if !dry_run {
+            std::fs::remove_file(target_file_path)?;
+        }
+    }
+
+    prepare_target_parent(target_file_path, dry_run)?;
+    if !dry_run {
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&absolute_source, target_file_path)?;
+        #[cfg(windows)]
+        std::os::windows::fs::symlink_file(&absolute_source, target_file_path)?;
+    }
+
+    Ok(DistributorResultType::Linked(source_str, target_str))
+}
+
+/// Hard link file to full target path.
+/// 已存在的符号链接将被替换，其他已存在的文件仅在 `force` 时被替换。
+/// 跨设备无法链接时输出警告并回退至复制，其他链接错误直接返回。
+///
+/// # Param
+///
+/// - `source_file_path` - 源文件的路径。
+/// - `target_file_path` - 目标文件的路径，包括文件名。如果路径中的目录不存在，将会被创建。
+/// - `options` - 运行选项。
+pub fn hard_link_file_to(source_file_path: &Path,
+                         target_file_path: &Path,
+                         options: &RunOptions) -> DistributorResult {
+    hard_link_file_to_with(source_file_path, target_file_path, options, |s, t| std::fs::hard_link(s, t))
+}
+
+fn hard_link_file_to_with(source_file_path: &Path,
+                          target_file_path: &Path,
+                          options: &RunOptions,
+                          hard_link: impl Fn(&Path, &Path) -> std::io::Result<()>) -> DistributorResult {
+    let dry_run = options.dry_run;
+    let source_str = source_file_path.to_path_buf();
+    let target_str = target_file_path.to_path_buf();
+
+    if is_same_file(source_file_path, target_file_path) {
+        return Ok(Same(source_str, target_str));
+    }
+
+    let target_meta = std::fs::symlink_metadata(target_file_path).ok();
+    if target_meta.as_ref().is_some_and(|meta| !meta.file_type().is_symlink()) && !options.force {
+        return Err(DistributorError::TargetExists(target_file_path.to_path_buf()));
+    }
+
+    prepare_target_parent(target_file_path, dry_run)?;
+    if dry_run {
+        return Ok(DistributorResultType::Linked(source_str, target_str));
+    }
+
+    if target_meta.is_some() {
+        std::fs::remove_file(target_file_path)?;
+    }
+
+    match hard_link(source_file_path, target_file_path) {
+        Ok(_) => Ok(DistributorResultType::Linked(source_str, target_str)),
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+            tracing::warn!(target = ?target_str, "hard link failed ({}), fall back to copy.", e);
+            copy_file_with_full_target_path(source_file_path, target_file_path, options)
+        }
+        Err(e) => Err(DistributorError::IoError(e)),
+    }
+}
+
+/// 两个路径是否指向同一文件。
+#[cfg(unix)]
+fn is_same_file(path_1: &Path, path_2: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (std::fs::metadata(path_1), std::fs::metadata(path_2)) {
+        (Ok(meta_1), Ok(meta_2)) => meta_1.dev() == meta_2.dev() && meta_1.ino() == meta_2.ino(),
+        _ => false,
+    }
+}
+
+/// 两个路径是否指向同一文件。
+#[cfg(not(unix))]
+fn is_same_file(path_1: &Path, path_2: &Path) -> bool {
+    match (path_1.canonicalize(), path_2.canonicalize()) {
+        (Ok(path_1), Ok(path_2)) => path_1 == path_2,
+        _ => false,
+    }
+}
+
+/// 按运行选项复制或链接文件至完整目标路径。
+/// 本地目标文件已存在且与分发结果不同时，先按冲突处理方式与覆盖确认决定是否跳过。
+///
+/// # Param
+///
+/// - `source_file_path` - 源文件的路径。
+/// - `target_file_path` - 目标文件的路径，包括文件名。
+/// - `options` - 运行选项。
+pub fn distribute_file(source_file_path: &Path,
+                       target_file_path: &Path,
+                       options: &RunOptions) -> DistributorResult {
+    let start = Instant::now();
+    let result = match options.link {
+        _ if distributor_remote::is_remote_target(target_file_path) => {
+            distributor_remote::distribute_remote_file(source_file_path, target_file_path, options)
+        }
+        _ if is_conflict_skipped(source_file_path, target_file_path, options) => {
+            Ok(DistributorResultType::Skipped(source_file_path.to_path_buf(), target_file_path.to_path_buf()))
+        }
+        Some(LinkMode::Symbolic) => {
+            link_file_to(source_file_path, target_file_path, options.force, options.dry_run)
+        }
+        Some(LinkMode::Hard) => hard_link_file_to(source_file_path, target_file_path, options),
+        None => {
+            let result = match &options.template {
+                Some(template) if template.is_template_file(source_file_path) => {
+                    render_file_with_full_target_path(source_file_path, target_file_path, template, options)
+                }
+                _ => copy_file_with_full_target_path(source_file_path, target_file_path, options),
+            };
+            if options.preserve && !options.dry_run && matches!(result, Ok(Copied(_, _))) {
+                preserve_metadata(source_file_path, target_file_path).and(result)
+            } else {
+                result
+            }
+        }
+    };
+
+    tracing::trace!(target = ?target_file_path, elapsed = ?start.elapsed(), "distributed.");
+
+    result.map_err(|e| DistributorError::DistributeFailed(source_file_path.to_path_buf(),
+                                                          target_file_path.to_path_buf(),
+                                                          Box::new(e)))
+}
+
+/// 执行条目的 pre_run 命令，工作目录为 root，root 指向文件时为其所在目录。
+/// 命令的标准输出被重定向至 stderr，以免混入 json 等结果输出。未配置时直接返回。
+///
+/// # Param
+///
+/// - `config_item` - 配置条目。
+pub fn run_pre_run_hook(config_item: &DistributorItem) -> Result<(), DistributorError> {
+    let Some(command) = &config_item.pre_run else {
+        return Ok(());
+    };
+
+    let work_dir = if config_item.is_point_to_file() {
+        config_item.root.parent().unwrap_or(Path::new("."))
+    } else {
+        config_item.root.as_path()
+    };
+    let work_dir = if work_dir.as_os_str().is_empty() { Path::new(".") } else { work_dir };
+
+    let mut shell = if cfg!(windows) {
+        let mut shell = std::process::Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = std::process::Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    let status = shell.arg(command)
+                      .current_dir(work_dir)
+                      .stdout(std::io::stderr())
+                      .status()?;
+    if !status.success() {
+        return Err(DistributorError::HookFailed(command.clone(), status.code()));
+    }
+
+    Ok(())
+}
+
+/// 以 tracing 事件记录结果。
+/// 发生变化的文件为 info，未变化的文件为 debug，跳过的文件为 warn，错误为 error。
+pub fn log_results(results: &[DistributorResult]) {
+    for result in results {
+        match result {
+            Ok(Copied(f, t)) => tracing::info!(source = ?f, target = ?t, "copied"),
+            Ok(Same(f, t)) => tracing::debug!(source = ?f, target = ?t, "same"),
+            Ok(UpToDate(f)) => tracing::debug!(source = ?f, "up to date"),
+            Ok(DistributorResultType::Moved(f)) => tracing::info!(source = ?f, "moved"),
+            Ok(DistributorResultType::Linked(f, t)) => tracing::info!(source = ?f, target = ?t, "linked"),
+            Ok(DistributorResultType::Removed(t)) => tracing::info!(target = ?t, "removed"),
+            Ok(DistributorResultType::Archived(f, t)) => tracing::info!(source = ?f, target = ?t, "archived"),
+            Ok(DistributorResultType::Skipped(f, t)) => tracing::warn!(source = ?f, target = ?t, "skipped"),
+            Ok(DistributorResultType::TooLarge(f, size)) => {
+                tracing::warn!(source = ?f, size, "skipped, exceeds max file size")
+            }
+            Ok(DistributorResultType::Saved) => {}
+            Err(e) => tracing::error!("{}", e),
+        }
+    }
+}
+
+/// 按输出详细程度写出结果。
+///
+/// # Param
+///
+/// - `results` - 分发结果。
+/// - `verbosity` - 输出详细程度。
+/// - `out` - 常规结果的输出。
+/// - `err` - 错误的输出。
+pub fn write_results(results: &[DistributorResult],
+                     verbosity: Verbosity,
+                     out: &mut impl Write,
+                     err: &mut impl Write) -> std::io::Result<()> {
+    for result in results {
+        match result {
+            Ok(tp) => {
+                if verbosity < Verbosity::Normal {
+                    continue;
+                }
+                match tp {
+                    Copied(f, t) => {
+                        writeln!(out, "[Copied]{:?}{:?}", f, t)?;
+                    }
+                    Same(f, t) => {
+                        if verbosity >= Verbosity::Verbose {
+                            writeln!(out, "[Same]{:?}{:?}", f, t)?;
+                        }
+                    }
+                    UpToDate(f) => {
+                        if verbosity >= Verbosity::Verbose {
+                            writeln!(out, "[UpToDate]{:?}", f)?;
+                        }
+                    }
+                    DistributorResultType::Moved(f) => {
+                        writeln!(out, "[Moved]{:?}", f)?;
+                    }
+                    DistributorResultType::Linked(f, t) => {
+                        writeln!(out, "[Linked]{:?}{:?}", f, t)?;
+                    }
+                    DistributorResultType::Removed(t) => {
+                        writeln!(out, "[Removed]{:?}", t)?;
+                    }
+                    DistributorResultType::Archived(f, t) => {
+                        writeln!(out, "[Archived]{:?}{:?}", f, t)?;
+                    }
+                    DistributorResultType::Skipped(f, t) => {
+                        writeln!(out, "[Skipped]{:?}{:?}", f, t)?;
+                    }
+                    DistributorResultType::TooLarge(f, size) => {
+                        writeln!(out, "[Skipped]{:?} {} exceeds max file size", f, indicatif::HumanBytes(*size))?;
+                    }
+                    DistributorResultType::Saved => {}
+                }
+            }
+            Err(e) => {
+                writeln!(err, "[Error] {}", e)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// 将源文件的权限与修改时间应用至目标文件。
+///
+/// # Param
+///
+/// - `source_file_path` - 源文件的路径。
+/// - `target_file_path` - 目标文件的路径。
+pub fn preserve_metadata(source_file_path: &Path, target_file_path: &Path) -> Result<(), DistributorError> {
+    let meta = std::fs::metadata(source_file_path)?;
+    File::options()
+        .write(true)
+        .open(target_file_path)?
+        .set_modified(meta.modified()?)?;
+    std::fs::set_permissions(target_file_path, meta.permissions())?;
+
+    Ok(())
+}
+
+/// # 目标状态
+#[derive(Debug, PartialEq)]
+pub struct TargetStatus {
+    /// 目标目录。
+    pub target: PathBuf,
+
+    /// 缓存中已过期或目标文件被修改的源文件，不包括目标中不存在的源文件。
+    pub outdated: Vec<PathBuf>,
+
+    /// 目标中不存在的源文件。
+    pub missing: Vec<PathBuf>,
+}
+
+/// 获取配置条目各目标的状态，不修改缓存。
+///
+/// # Param
+///
+/// - `config_item` - 配置条目。
+/// - `db_cache` - 缓存。
+/// - `global` - 应用于所有条目的 ignore。
+pub fn get_item_status(config_item: &DistributorItem,
+                       db_cache: &FileDistributorCache,
+                       global: &[String]) -> Vec<TargetStatus> {
+    let config_item = &config_item.expanded();
+    let mut status = vec![];
+    for to in config_item.to.iter() {
+        let pairs = get_source_target_pairs(config_item, to, global);
+        status.push(TargetStatus {
+            target: to.clone(),
+            outdated: pairs.iter()
+                           .filter(|(source, target)| target.exists() && db_cache.is_file_outdated(source, target))
+                           .map(|(source, _)| source.clone())
+                           .collect(),
+            missing: pairs.iter()
+                          .filter(|(_, target)| !target.exists())
+                          .map(|(source, _)| source.clone())
+                          .collect(),
+        });
+    }
+
+    status
+}
+
+/// # 目标校验结果
+#[derive(Debug, PartialEq)]
+pub struct TargetVerification {
+    /// 目标目录。
+    pub target: PathBuf,
+
+    /// 内容与源文件不同或无法读取的目标文件。
+    pub mismatched: Vec<PathBuf>,
+
+    /// 不存在的目标文件。
+    pub missing: Vec<PathBuf>,
+}
+
+impl TargetVerification {
+    /// 目标文件是否均与源文件一致。
+    pub fn is_ok(&self) -> bool {
+        self.mismatched.is_empty() && self.missing.is_empty()
+    }
+}
+
+/// 逐字节比较配置条目各目标与源文件，不读写缓存，不修改任何文件。
+/// 归档与远程目标将被跳过。指定模板时，模板文件与替换后的内容比较。
+///
+/// # Param
+///
+/// - `config_item` - 配置条目。
+/// - `global` - 应用于所有条目的 ignore。
+/// - `template` - 分发时使用的模板，将合并条目中的变量。
+pub fn verify_item(config_item: &DistributorItem,
+                   global: &[String],
+                   template: Option<&Template>) -> Vec<TargetVerification> {
+    let config_item = &config_item.expanded();
+    let template = template.map(|template| template.with_item(config_item));
+    let mut verifications = vec![];
+    for to in config_item.to.iter() {
+        if is_archive_target(&config_item.root, to) || distributor_remote::is_remote_target(to) {
+            continue;
+        }
+
+        let pairs = get_source_target_pairs(config_item, to, global);
+        let (existing, missing): (Vec<_>, Vec<_>) = pairs.into_iter().partition(|(_, target)| target.exists());
+        verifications.push(TargetVerification {
+            target: to.clone(),
+            mismatched: existing.into_iter()
+                                .filter(|(source, target)| {
+                                    let template = template.as_ref().filter(|t| t.is_template_file(source));
+                                    !is_target_same(source, target, template, DEFAULT_BUFFER_SIZE)
+                                })
+                                .map(|(_, target)| target)
+                                .collect(),
+            missing: missing.into_iter().map(|(_, target)| target).collect(),
+        });
+    }
+
+    verifications
+}
+
+/// 将目标中与源文件内容不同的文件复制回源文件，不读写缓存。
+/// 仅拉取源中已存在的文件。其他本地目标中的同一文件也与源文件及该目标文件不同时视为冲突，不拉取任何文件。
+///
+/// # Param
+///
+/// - `config_item` - 配置条目。
+/// - `target` - 拉取的目标目录，应为配置条目的本地目标。
+/// - `options` - 运行选项。使用 `dry_run` 与 `global_ignore`。
+pub fn pull_item(config_item: &DistributorItem, target: &Path, options: &RunOptions) -> Vec<DistributorResult> {
+    let config_item = &config_item.expanded();
+    let is_local = |to: &PathBuf| !is_archive_target(&config_item.root, to) && !distributor_remote::is_remote_target(to);
+    if !config_item.to.iter().any(|to| to == target && is_local(to)) {
+        return vec![Err(DistributorError::NotLocalTarget(target.to_path_buf()))];
+    }
+
+    let buffer_size = get_buffer_size(options);
+    let is_same = |a: &Path, b: &Path| compare_file(a, b, buffer_size).unwrap_or(false);
+    let pairs: Vec<(PathBuf, PathBuf)> = get_source_target_pairs(config_item, target, &options.global_ignore)
+        .into_iter()
+        .filter(|(source, target)| target.is_file() && !is_same(source, target))
+        .collect();
+
+    let mut conflicts = vec![];
+    for to in config_item.to.iter().filter(|to| *to != target && is_local(to)) {
+        let others: HashMap<PathBuf, PathBuf> = get_source_target_pairs(config_item, to, &options.global_ignore)
+            .into_iter()
+            .collect();
+        for (source, target) in pairs.iter() {
+            let Some(other) = others.get(source).filter(|other| other.is_file()) else { continue; };
+            if !is_same(source, other) && !is_same(target, other) {
+                conflicts.push(Err(DistributorError::PullConflict(target.clone(), other.clone())));
+            }
+        }
+    }
+    if !conflicts.is_empty() {
+        return conflicts;
+    }
+
+    pairs.iter()
+         .map(|(source, target)| copy_file_with_full_target_path(target, source, options))
+         .collect()
+}
+
+/// 获取配置条目在指定目标下的源文件与目标文件路径，按源路径排序。
+///
+/// # Param
+///
+/// - `config_item` - 配置条目。
+/// - `to` - 目标目录。
+/// - `global` - 应用于所有条目的 ignore。
+pub fn get_source_target_pairs(config_item: &DistributorItem,
+                               to: &Path,
+                               global: &[String]) -> Vec<(PathBuf, PathBuf)> {
+    let mut pairs: Vec<(PathBuf, PathBuf)> = Vec::new();
+    if config_item.is_point_to_file() {
+        let file_name = config_item.root.file_name().unwrap_or_default();
+        let target = if to.is_file() {
+            to.to_path_buf()
+        } else {
+            to.join(config_item.get_target_file_name(to).map(Path::new).unwrap_or(Path::new(file_name)))
+        };
+        pairs.push((config_item.root.clone(), target));
+    } else if let Ok(source_set) = config_item.get_non_root_source_file_with(global) {
+        for source in source_set.iter() {
+            let target = get_target_path(config_item, source, to, false);
+            pairs.push((source.clone(), target));
+        }
+    }
+
+    pairs.sort();
+    pairs
+}
+
+/// Copy files of config item by comparing modified time of source and target.
+/// 不读写缓存，源文件较新或目标文件不存在时复制。
+///
+/// # Param
+///
+/// - `config_item` - 配置条目。
+/// - `options` - 运行选项。`force` 忽略修改时间，强制复制。
+pub fn copy_by_mtime(config_item: &DistributorItem, options: &RunOptions) -> Vec<DistributorResult> {
+    let force = options.force;
+    let mut results = vec![];
+    let mut pairs: Vec<(PathBuf, PathBuf)> = Vec::new();
+    if config_item.is_point_to_file() {
+        let mut root = vec![&config_item.root];
+        results.extend(notify_results(remove_too_large(&mut root, get_max_file_size(config_item, options)), options));
+        let file_name = config_item.root.file_name().unwrap_or_default();
+        for to in config_item.to.iter().filter(|_| !root.is_empty()) {
+            let target = if to.is_file() {
+                to.clone()
+            } else {
+                to.join(config_item.get_target_file_name(to).map(Path::new).unwrap_or(Path::new(file_name)))
+            };
+            pairs.push((config_item.root.clone(), target));
+        }
+    } else {
+        let source_set = match config_item.get_non_root_source_file_and_warnings(&options.global_ignore) {
+            Ok((source_set, warnings)) => {
+                results.extend(warnings.into_iter().map(|w| Err(DistributorError::SourceUnreadable(w.path, w.error))));
+                source_set
+            }
+            Err(e) => {
+                results.push(Err(DistributorError::SourceCollectFailed(e)));
+                return results;
+            }
+        };
+        if !force {
+            if let Err(e) = config_item.check_source_limit(&source_set) {
+                results.push(Err(DistributorError::SourceLimitExceeded(e)));
+                return results;
+            }
+        }
+
+        let mut sources: Vec<&PathBuf> = source_set.iter().collect();
+        sources.sort();
+        results.extend(notify_results(remove_too_large(&mut sources, get_max_file_size(config_item, options)), options));
+        if options.flatten {
+            results.extend(remove_name_collision(&mut sources));
+        }
+
+        for to in config_item.to.iter() {
+            for source in sources.iter() {
+                let target = get_target_path(config_item, source, to, options.flatten);
+                pairs.push((source.to_path_buf(), target));
+            }
+        }
+    }
+
+    if is_failed_fast(&results, options) {
+        return results;
+    }
+    pairs.retain(|(source, _)| is_modified_since(source, options.since));
+    pairs.sort();
+    results.extend(run_pairs(&pairs, options, |source, target| {
+        if force || is_source_newer_than_target(source, target) {
+            distribute_file(source, target, options)
+        } else {
+            Ok(UpToDate(source.to_path_buf()))
+        }
+    }));
+
+    results
+}
+
+/// 获取源文件在目标目录下的路径。
+///
+/// # Param
+///
+/// - `config_item` - 配置条目，source 应位于其 root 或 roots 下。
+/// - `source` - 源文件路径。
+/// - `to` - 目标目录。
+/// - `flatten` - 为 true 时仅以文件名放置于目标目录，否则保留相对其所在根目录的路径。
+pub fn get_target_path(config_item: &DistributorItem, source: &Path, to: &Path, flatten: bool) -> PathBuf {
+    if flatten {
+        to.join(source.file_name().unwrap_or_default())
+    } else {
+        to.join(config_item.get_relative_path(source).unwrap())
+    }
+}
+
+/// 获取生效的单个源文件大小上限，取运行选项与配置条目中较小者。
+fn get_max_file_size(config_item: &DistributorItem, options: &RunOptions) -> Option<u64> {
+    match (options.max_file_size, config_item.max_file_size) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// 移除大于 max_file_size 的源文件，为每个被移除的源文件返回 TooLarge。
+fn remove_too_large<P: AsRef<Path>>(sources: &mut Vec<P>, max_file_size: Option<u64>) -> Vec<DistributorResult> {
+    let Some(max_file_size) = max_file_size else {
+        return vec![];
+    };
+
+    let mut results = vec![];
+    sources.retain(|source| {
+        let source = source.as_ref();
+        match std::fs::metadata(source) {
+            Ok(meta) if meta.len() > max_file_size => {
+                results.push(Ok(DistributorResultType::TooLarge(source.to_path_buf(), meta.len())));
+                false
+            }
+            _ => true,
+        }
+    });
+
+    results
+}
+
+/// 移除文件名与其他源文件相同的源文件，为每个被移除的源文件返回错误。
+fn remove_name_collision<P: AsRef<Path>>(sources: &mut Vec<P>) -> Vec<DistributorResult> {
+    let mut counts: HashMap<OsString, usize> = HashMap::new();
+    for source in sources.iter() {
+        *counts.entry(source.as_ref().file_name().unwrap_or_default().to_os_string()).or_default() += 1;
+    }
+
+    let mut results = vec![];
+    sources.retain(|source| {
+        let source = source.as_ref();
+        if counts[source.file_name().unwrap_or_default()] > 1 {
+            results.push(Err(DistributorError::NameCollision(source.to_path_buf())));
+            return false;
+        }
+        true
+    });
+
+    results
+}
+
+/// 在各目标中创建源中的空目录，已存在的目录保持不变。仅返回创建失败的结果。
+///
+/// # Param
+///
+/// - `config_item` - 配置条目。
+/// - `options` - 运行选项。`dry_run` 时不创建目录。
+pub fn create_empty_target_dir(config_item: &DistributorItem, options: &RunOptions) -> Vec<DistributorResult> {
+    let empty_dirs = config_item.get_empty_source_dir(&options.global_ignore);
+    if options.dry_run {
+        return vec![];
+    }
+
+    let mut results = vec![];
+    for to in config_item.to.iter().filter(|to| !distributor_remote::is_remote_target(to)) {
+        for dir in empty_dirs.iter() {
+            let target = get_target_path(config_item, dir, to, false);
+            if let Err(e) = std::fs::create_dir_all(&target) {
+                results.push(Err(DistributorError::DistributeFailed(dir.clone(), target, Box::new(e.into()))));
+            }
+        }
+    }
+
+    results
+}
+
+/// 删除目标目录中没有对应源文件的文件，被 ignore 匹配的文件将被保留。
+/// 仅遍历目标目录本身，不跟随符号链接目录，也不进入位于其中的其他目标。因删除而变空的子目录随之删除，目标目录本身保留。
+///
+/// # Param
+///
+/// - `config_item` - 配置条目，root 应指向一个目录。
+/// - `options` - 运行选项。`dry_run` 时仅报告结果，不删除文件。
+pub fn remove_orphan_target_file(config_item: &DistributorItem, options: &RunOptions) -> Vec<DistributorResult> {
+    let dry_run = options.dry_run;
+    let mut results = vec![];
+    // 源目录无法完整读取时，其下的目标文件无法判断是否为孤立文件，不移除任何文件。
+    let Ok((source_set, warnings)) = config_item.get_non_root_source_file_and_warnings(&options.global_ignore) else {
+        return results;
+    };
+    if !warnings.is_empty() {
+        return results;
+    }
+    let expected: HashSet<PathBuf> =
+        source_set.iter()
+                  .map(|source| get_target_path(config_item, source, Path::new(""), options.flatten))
+                  .collect();
+    let ignores = config_item.get_ignore_globs(&options.global_ignore).unwrap_or_default();
+    let nested_targets = config_item.get_nested_targets();
+
+    for to in config_item.to.iter() {
+        let inner_targets: Vec<PathBuf> = nested_targets.iter()
+                                                        .filter(|(outer, _)| outer == to)
+                                                        .map(|(_, inner)| normalize_path(inner))
+                                                        .collect();
+        let mut orphans = vec![];
+        let mut candidates = vec![to.clone()];
+        while let Some(candidate) = candidates.pop() {
+            let Ok(entries) = std::fs::read_dir(&candidate) else { continue; };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let Ok(file_type) = entry.file_type() else { continue; };
+                if file_type.is_dir() {
+                    if !inner_targets.contains(&normalize_path(&path)) {
+                        candidates.push(path);
+                    }
+                    continue;
+                }
+
+                let Ok(relative) = path.strip_prefix(to) else { continue; };
+                let source = config_item.root.join(relative);
+                if !expected.contains(relative) && !config_item.is_matched_by(&ignores, &source) {
+                    orphans.push(path);
+                }
+            }
+        }
+
+        orphans.sort();
+        for orphan in orphans {
+            if !dry_run {
+                if let Err(e) = std::fs::remove_file(&orphan) {
+                    results.push(Err(DistributorError::IoError(e)));
+                    continue;
+                }
+                remove_empty_parent_dir(&orphan, to);
+            }
+            results.push(Ok(DistributorResultType::Removed(orphan.to_path_buf())));
+        }
+    }
+
+    results
+}
+
+/// 自下而上删除文件所在的空目录，直至遇到非空目录或到达 root，root 本身保留。
+///
+/// # Param
+///
+/// - `file` - 已删除的文件路径。
+/// - `root` - 停止的目录。
+fn remove_empty_parent_dir(file: &Path, root: &Path) {
+    for dir in file.ancestors().skip(1).take_while(|dir| *dir != root && dir.starts_with(root)) {
+        // 目录非空时删除失败，其上层目录同样非空。
+        if std::fs::remove_dir(dir).is_err() {
+            break;
+        }
+    }
+}
+
+/// 删除已成功复制到全部目标的源文件。
+///
+/// # Param
+///
+/// - `results` - 复制结果。
+/// - `target_count` - 目标数量，源文件的 `Copied` 或 `Same` 结果达到此数量时删除。
+pub fn remove_moved_source(results: &[DistributorResult], target_count: usize) -> Vec<DistributorResult> {
+    let mut succeeded: HashMap<&Path, usize> = HashMap::new();
+    for result in results {
+        if let Ok(Copied(f, _)) | Ok(Same(f, _)) | Ok(DistributorResultType::Archived(f, _)) = result {
+            *succeeded.entry(f.as_path()).or_default() += 1;
+        }
+    }
+
+    let mut sources: Vec<&Path> = succeeded.into_iter()
+                                          .filter(|(_, count)| target_count > 0 && *count >= target_count)
+                                          .map(|(source, _)| source)
+                                          .collect();
+    sources.sort();
+
+    sources.into_iter()
+           .map(|source| {
+               std::fs::remove_file(source)?;
+               Ok(DistributorResultType::Moved(source.to_path_buf()))
+           })
+           .collect()
+}
+
+/// Copy each source file to its full target path concurrently.
+/// 结果顺序与 pairs 一致。
+///
+/// # Param
+///
+/// - `pairs` - 源文件路径与目标文件路径。
+/// - `options` - 运行选项。
+pub fn copy_pairs(pairs: &[(PathBuf, PathBuf)], options: &RunOptions) -> Vec<DistributorResult> {
+    run_pairs(pairs, options, |source, target| distribute_file(source, target, options))
+}
+
+/// 对每对源文件与目标文件执行 run，结果顺序与 pairs 一致。
+/// fail_fast 时按顺序逐个执行并在首个错误后停止，结果为 pairs 对应的前缀；否则并发执行。
+fn run_pairs(pairs: &[(PathBuf, PathBuf)],
+             options: &RunOptions,
+             run: impl Fn(&Path, &Path) -> DistributorResult + Sync) -> Vec<DistributorResult> {
+    let progress = new_progress_bar(pairs.len(), options);
+    let results = if options.fail_fast {
+        let mut results = vec![];
+        for (source, target) in pairs.iter() {
+            results.push(notify_result(run(source, target), options));
+            progress.inc(1);
+            if results.last().is_some_and(|result| result.is_err()) {
+                break;
+            }
+        }
+        results
+    } else {
+        pairs.par_iter()
+             .map(|(source, target)| {
+                 let result = notify_result(run(source, target), options);
+                 progress.inc(1);
+                 result
+             })
+             .collect()
+    };
+    progress.finish_and_clear();
+
+    results
+}
+
+/// 以结果调用运行选项中的回调，返回原结果。
+fn notify_result(result: DistributorResult, options: &RunOptions) -> DistributorResult {
+    if let Some(on_result) = &options.on_result {
+        on_result.call(&result);
+    }
+
+    result
+}
+
+/// 以各结果调用运行选项中的回调，返回原结果。
+fn notify_results(results: Vec<DistributorResult>, options: &RunOptions) -> Vec<DistributorResult> {
+    if let Some(on_result) = &options.on_result {
+        results.iter().for_each(|result| on_result.call(result));
+    }
+
+    results
+}
+
+/// 结果是否应更新缓存记录。跳过的目标未被写入，不更新记录以便下次运行重新比较。
+fn is_record_updated(result: &DistributorResult) -> bool {
+    matches!(result, Ok(result) if !matches!(result, DistributorResultType::Skipped(_, _)))
+}
+
+/// fail_fast 时结果中是否已有错误。
+fn is_failed_fast(results: &[DistributorResult], options: &RunOptions) -> bool {
+    options.fail_fast && results.iter().any(|result| result.is_err())
+}
+
+/// 创建复制进度条，显示已处理与总文件数量。
+/// 输出不是终端、仅输出错误、输出每个文件耗时或需要询问覆盖时返回隐藏的进度条。
+///
+/// # Param
+///
+/// - `total` - 待处理的文件数量。
+/// - `options` - 运行选项。
+fn new_progress_bar(total: usize, options: &RunOptions) -> ProgressBar {
+    let visible = matches!(options.verbosity, Verbosity::Normal | Verbosity::Verbose)
+        && options.prompt.is_none()
+        && std::io::stdout().is_terminal()
+        && std::io::stderr().is_terminal();
+    if !visible || total == 0 {
+        return ProgressBar::hidden();
+    }
+
+    let progress = ProgressBar::new(total as u64);
+    if let Ok(style) = ProgressStyle::with_template("[{elapsed_precise}] {wide_bar} {pos}/{len}") {
+        progress.set_style(style);
+    }
+
+    progress
+}
+
+/// 源文件是否比目标文件新。目标文件不存在时视为较新。
+///
+/// # Param
+///
+/// - `source_path` - 源文件路径
+/// - `target_path` - 目标文件路径
+pub fn is_source_newer_than_target(source_path: &Path, target_path: &Path) -> bool {
+    match (get_file_last_modified_timestamp(source_path),
+           get_file_last_modified_timestamp(target_path)) {
+        (Ok(source_time), Ok(target_time)) => source_time > target_time,
+        _ => true,
+    }
+}
+
+/// 源文件是否在 since 之后修改。未指定 since 时总是返回 true。
+///
+/// # Param
+///
+/// - `source_path` - 源文件路径。
+/// - `since` - 截止时刻。
+fn is_modified_since(source_path: &Path, since: Option<SystemTime>) -> bool {
+    let Some(since) = since else {
+        return true;
+    };
+
+    std::fs::metadata(source_path)
+        .and_then(|meta| meta.modified())
+        .map(|modified| modified > since)
+        .unwrap_or(false)
+}
+
+/// 解析 `--since` 的值，可为 RFC 3339 时刻，如 `2024-01-02T03:04:05Z`，
+/// 或相对当前时刻的时长，如 `45s` `30m` `2h` `7d`。
+///
+/// # Param
+///
+/// - `value` - 待解析的值。
+pub fn parse_since(value: &str) -> Result<SystemTime, String> {
+    let value = value.trim();
+    if let Some(duration) = parse_duration(value) {
+        return SystemTime::now()
+            .checked_sub(duration)
+            .ok_or_else(|| format!("duration {} is too long", value));
+    }
+
+    parse_rfc3339(value).ok_or_else(|| format!("{} is neither a duration nor an RFC 3339 time", value))
+}
+
+/// 解析文件大小，如 `1024`、`100KB`、`1.5MB`、`2GiB`。单位不区分大小写，均以 1024 进制计算。
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        "t" | "tb" | "tib" => 1 << 40,
+        _ => return Err(format!("invalid size unit {:?}", unit)),
+    };
+    let number: f64 = number.parse().map_err(|_| format!("invalid size {:?}", value))?;
+
+    Ok((number * multiplier as f64) as u64)
+}
+
+/// 解析以 `s` `m` `h` `d` 结尾的时长。
+fn parse_duration(value: &str) -> Option<Duration> {
+    let unit = match value.chars().last()? {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        _ => return None,
+    };
+    let count: u64 = value[..value.len() - 1].parse().ok()?;
+
+    Some(Duration::from_secs(count.checked_mul(unit)?))
+}
+
+/// 解析 `YYYY-MM-DDTHH:MM:SS[.fraction](Z|±HH:MM)` 形式的 RFC 3339 时刻，不支持 1970 年之前的时刻。
+fn parse_rfc3339(value: &str) -> Option<SystemTime> {
+    let (date, time) = value.split_once(['T', 't', ' '])?;
+    let mut date_parts = date.splitn(3, '-').map(|part| part.parse::<i64>().ok());
+    let (year, month, day) = (date_parts.next()??, date_parts.next()??, date_parts.next()??);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let (clock, offset) = if let Some(clock) = time.strip_suffix(['Z', 'z']) {
+        (clock, 0)
+    } else {
+        let index = time.rfind(['+', '-'])?;
+        let (clock, offset) = time.split_at(index);
+        let (hours, minutes) = offset[1..].split_once(':')?;
+        let offset = hours.parse::<i64>().ok()? * 3600 + minutes.parse::<i64>().ok()? * 60;
+        (clock, if time[index..].starts_with('-') { -offset } else { offset })
+    };
+    let (clock, nanos) = match clock.split_once('.') {
+        Some((clock, fraction)) => {
+            let digits: String = fraction.chars().chain(std::iter::repeat('0')).take(9).collect();
+            (clock, digits.parse::<u32>().ok()?)
+        }
+        None => (clock, 0),
+    };
+    let mut clock_parts = clock.splitn(3, ':').map(|part| part.parse::<i64>().ok());
+    let (hour, minute, second) = (clock_parts.next()??, clock_parts.next()??, clock_parts.next()??);
+    if !(0..24).contains(&hour) || !(0..60).contains(&minute) || !(0..=60).contains(&second) {
+        return None;
+    }
+
+    // 由公历日期计算距 1970-01-01 的天数。
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let year_of_era = y - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+
+    let secs = days * 86400 + hour * 3600 + minute * 60 + second - offset;
+    let secs = u64::try_from(secs).ok()?;
+
+    Some(SystemTime::UNIX_EPOCH + Duration::new(secs, nanos))
+}
+
+/// 以 `YYYY-MM-DDTHH:MM:SSZ` 形式的 RFC 3339 UTC 时刻表示 time，舍去秒以下的部分，1970 年之前的时刻视为 1970 年。
+pub fn format_rfc3339(time: SystemTime) -> String {
+    let secs = time.duration_since(SystemTime::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0) as i64;
+    let (days, secs_of_day) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+
+    // 由距 1970-01-01 的天数计算公历日期。
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            year,
+            month,
+            day,
+            secs_of_day / 3600,
+            secs_of_day % 3600 / 60,
+            secs_of_day % 60)
+}
+
+/// Copy file to target path with default name.
+///
+/// # Param
+///
+/// - `source_file_path` - 待复制的文件的路径。
+/// - `target_path` - 目标文件的路径，如果是文件夹，将会在文件夹中创建一个与源文件同名的文件。
+/// - `default_name` - 如果目标路径是文件夹，将会使用此默认文件名。
+/// - `options` - 运行选项。
+pub fn copy_file_to_with_default_name(source_file_path: &Path,
+                                      target_path: &Path,
+                                      default_name: impl AsRef<Path>,
+                                      options: &RunOptions) -> DistributorResult {
+    if target_path.is_file() {
+        distribute_file(source_file_path, target_path, options)
+    } else {
+        distribute_file(source_file_path, &target_path.join(default_name), options)
+    }
+}
+
+#[derive(Debug)]
+pub enum FileCompareError {
+    IoError(std::io::Error),
+}
+
+impl From<std::io::Error> for FileCompareError {
+    fn from(e: std::io::Error) -> Self {
+        FileCompareError::IoError(e)
+    }
+}
+
+pub type FileCompareResult = Result<bool, FileCompareError>;
+
+/// 比较文件内容。
+///
+/// # Param
+///
+/// - source_path - 源文件路径
+/// - target_path - 目标文件路径
+fn compare_file(source_path: &Path, target_path: &Path, buffer_size: usize) -> FileCompareResult {
+    if std::fs::metadata(source_path)?.len() != std::fs::metadata(target_path)?.len() {
+        return Ok(false);
+    }
+
+    let mut file_source_result = File::open(source_path)?;
+    let mut file_target_result = File::open(target_path)?;
+
+    let mut buffer_1 = vec![0u8; buffer_size.max(1)];
+    let mut buffer_2 = vec![0u8; buffer_size.max(1)];
+    loop {
+        let size_1 = read_full(&mut file_source_result, &mut buffer_1)?;
+        let size_2 = read_full(&mut file_target_result, &mut buffer_2)?;
+        if size_1 != size_2 || buffer_1[..size_1] != buffer_2[..size_2] { return Ok(false); }
+        if size_1 == size_2 && size_1 == 0 { return Ok(true); }
+    }
+}
+
+/// 读取直至填满 buffer 或到达文件末尾，返回读取的字节数。
+fn read_full(reader: &mut impl Read, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut size = 0;
+    while size < buffer.len() {
+        match reader.read(&mut buffer[size..]) {
+            Ok(0) => break,
+            Ok(n) => size += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(size)
+}
+
+/// 解析 `--buffer-size` 的值，格式同 parse_size，不能为 0。
+pub fn parse_buffer_size(value: &str) -> Result<NonZeroUsize, String> {
+    let size = usize::try_from(parse_size(value)?).map_err(|_| format!("buffer size {} is too large", value))?;
+
+    NonZeroUsize::new(size).ok_or_else(|| "buffer size must not be zero".to_string())
+}
+
+/// 创建测试用的 Distributor，缓存位于临时目录下的 cache.db。
+/// 返回临时目录、已创建的源目录 resource 与尚未创建的目标目录 target。
+#[cfg(test)]
+pub(crate) fn new_test_distributor(verbosity: Verbosity) -> (tempfile::TempDir, PathBuf, PathBuf, Distributor) {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let root = temp_dir.path().join("resource");
+    let target = temp_dir.path().join("target");
+    std::fs::create_dir_all(&root).unwrap();
+    let distributor = Distributor::with_cache(FileDistributorCache::load(Some(&temp_dir.path().join("cache.db"))),
+                                              verbosity);
+    (temp_dir, root, target, distributor)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::fs;
+    use std::path::PathBuf;
+
+    use tempfile::tempdir;
 
     use super::*;
+    use crate::distributor_cache_db::CacheStrategy;
+
+    #[test]
+    fn test_result_record_json() {
+        let results: Vec<DistributorResult> = vec![
+            Ok(Copied(PathBuf::from("a"), PathBuf::from("b"))),
+            Ok(UpToDate(PathBuf::from("c"))),
+            Err(DistributorError::TargetExists(PathBuf::from("d"))),
+        ];
+        let records: Vec<DistributorResultRecord> = results.iter().map(|result| result.into()).collect();
+
+        let json = serde_json::to_string(&records).unwrap();
+        assert!(json.starts_with(r#"[{"action":"Copied","source":"a","target":"b"}"#));
+
+        let parsed: Vec<DistributorResultRecord> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, records);
+        assert_eq!(parsed[1].source.as_deref(), Some("c"));
+        assert_eq!(parsed[1].target, None);
+        assert_eq!(parsed[2].action, "Error");
+        assert!(parsed[2].error.is_some());
+    }
+
+    #[test]
+    fn test_do_copy_results() {
+        let (_temp_dir, root, target, mut distributor) = new_test_distributor(Verbosity::default());
+        fs::write(root.join("a.txt"), "a").unwrap();
+        fs::write(root.join("b.txt"), "b").unwrap();
+
+        let item = DistributorItem::builder("test", &root).target(&target).build();
+
+        let results = distributor.do_copy(&item, &RunOptions::default());
+        let copied: Vec<_> = results.iter()
+                                    .map(|result| match result {
+                                        Ok(Copied(f, t)) => (f.clone(), t.clone()),
+                                        other => panic!("unexpected result: {:?}", other),
+                                    })
+                                    .collect();
+        assert_eq!(copied, vec![
+            (root.join("a.txt"), target.join("a.txt")),
+            (root.join("b.txt"), target.join("b.txt")),
+        ]);
+
+        println!("second run is up to date");
+        let results = distributor.do_copy(&item, &RunOptions::default());
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|result| matches!(result, Ok(UpToDate(_)))));
+    }
+
+    #[test]
+    fn test_do_copy_added_target() {
+        let (temp_dir, root, _, mut distributor) = new_test_distributor(Verbosity::default());
+        let temp_path = temp_dir.path().to_path_buf();
+        fs::write(root.join("a.txt"), "a").unwrap();
+        let item = DistributorItem::builder("test", &root).target(temp_path.join("tar1")).build();
+        distributor.do_copy(&item, &RunOptions::default());
+
+        println!("add a second target after the first run");
+        let item = DistributorItem::builder("test", &root)
+            .target(temp_path.join("tar1"))
+            .target(temp_path.join("tar2"))
+            .build();
+        let results = distributor.do_copy(&item, &RunOptions::default());
+
+        assert_eq!(results.len(), 2);
+        assert!(matches!(&results[0], Ok(UpToDate(_))));
+        assert!(matches!(&results[1], Ok(Copied(_, t)) if t == &temp_path.join("tar2/a.txt")));
+        assert_eq!(fs::read_to_string(temp_path.join("tar2/a.txt")).unwrap(), "a");
+
+        let file_item = DistributorItem::builder("file", root.join("a.txt"))
+            .target(temp_path.join("tar1"))
+            .target(temp_path.join("tar3"))
+            .build();
+        fs::create_dir_all(temp_path.join("tar3")).unwrap();
+        let results = distributor.do_copy(&file_item, &RunOptions::default());
+        assert!(matches!(&results[0], Ok(UpToDate(_))));
+        assert!(matches!(&results[1], Ok(Copied(_, t)) if t == &temp_path.join("tar3/a.txt")));
+    }
+
+    #[test]
+    fn test_do_copy_restores_changed_target() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let root = temp_path.join("resource");
+        let target = temp_path.join("target");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a.txt"), "a").unwrap();
+        fs::write(root.join("b.txt"), "b").unwrap();
+
+        for strategy in [CacheStrategy::Mtime, CacheStrategy::Hash] {
+            let db_cache = FileDistributorCache::load(Some(&temp_path.join(format!("cache-{:?}.db", strategy))));
+            let mut distributor = Distributor::with_cache(db_cache, Verbosity::default());
+            distributor.db_cache.set_strategy(strategy);
+            let item = DistributorItem::builder("test", &root).target(&target).build();
+            distributor.do_copy(&item, &RunOptions::default());
+
+            println!("delete a target file");
+            fs::remove_file(target.join("a.txt")).unwrap();
+            let results = distributor.do_copy(&item, &RunOptions::default());
+            assert!(matches!(&results[0], Ok(UpToDate(f)) if f == &root.join("b.txt")));
+            assert!(matches!(&results[1], Ok(Copied(_, t)) if t == &target.join("a.txt")));
+
+            println!("edit a target file");
+            fs::write(target.join("b.txt"), "edited").unwrap();
+            set_file_mtime(&target.join("b.txt"), 4_000_000_000);
+            let results = distributor.do_copy(&item, &RunOptions::default());
+            assert!(matches!(&results[0], Ok(UpToDate(f)) if f == &root.join("a.txt")));
+            assert!(matches!(&results[1], Ok(Copied(_, t)) if t == &target.join("b.txt")));
+            assert_eq!(fs::read_to_string(target.join("b.txt")).unwrap(), "b");
+
+            let results = distributor.do_copy(&item, &RunOptions::default());
+            assert!(results.iter().all(|result| matches!(result, Ok(UpToDate(_)))));
+
+            fs::remove_dir_all(&target).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_do_copy_root_not_found() {
+        let (temp_dir, _, target, mut distributor) = new_test_distributor(Verbosity::default());
+        let temp_path = temp_dir.path().to_path_buf();
+        let root = temp_path.join("missing");
+        let item = DistributorItem::builder("test", &root).target(&target).build();
+
+        let results = distributor.do_copy(&item, &RunOptions::default());
+        assert!(matches!(&results[..], [Err(DistributorError::RootNotFound(p))] if *p == root));
+        assert!(!target.exists());
+    }
+
+    #[test]
+    fn test_do_copy_items_with_jobs() {
+        let (temp_dir, _, _, mut distributor) = new_test_distributor(Verbosity::Quiet);
+        let temp_path = temp_dir.path().to_path_buf();
+        let mut items = vec![];
+        for name in ["first", "second"] {
+            let root = temp_path.join(name);
+            fs::create_dir_all(&root).unwrap();
+            for i in 0..20 {
+                fs::write(root.join(format!("{}.txt", i)), format!("{} {}", name, i)).unwrap();
+            }
+            items.push(DistributorItem::builder(name, &root).target(temp_path.join("target").join(name)).build());
+        }
+        let items: Vec<&DistributorItem> = items.iter().collect();
+        let item_results = distributor.do_copy_items(&items, &RunOptions::default(), 2);
+
+        assert_eq!(item_results.len(), 2);
+        for (item, results) in items.iter().zip(item_results.iter()) {
+            assert_eq!(results.len(), 20);
+            assert!(results.iter().all(|result| matches!(result, Ok(Copied(f, _)) if f.starts_with(&item.root))));
+            for i in 0..20 {
+                let source = item.root.join(format!("{}.txt", i));
+                let target = temp_path.join("target").join(&item.name).join(format!("{}.txt", i));
+                assert_eq!(fs::read_to_string(&target).unwrap(), format!("{} {}", item.name, i));
+                assert!(!distributor.db_cache.is_file_outdated(&source, &target));
+            }
+            assert!(distributor.db_cache.get_last_run(&item.name).is_some());
+        }
+
+        println!("second run is up to date");
+        let item_results = distributor.do_copy_items(&items, &RunOptions::default(), 2);
+        assert!(item_results.iter().flatten().all(|result| matches!(result, Ok(UpToDate(_)))));
+    }
+
+    #[test]
+    fn test_do_copy_on_result() {
+        let (temp_dir, root, target, mut distributor) = new_test_distributor(Verbosity::Quiet);
+        let temp_path = temp_dir.path().to_path_buf();
+        fs::write(root.join("a.txt"), "a").unwrap();
+        fs::write(root.join("b.txt"), "b").unwrap();
+
+        let collected = Arc::new(std::sync::Mutex::new(vec![]));
+        let sink = collected.clone();
+        distributor.set_on_result(move |result| sink.lock().unwrap().push(format!("{:?}", result)));
+
+        let item = DistributorItem::builder("test", &root).target(&target).build();
+        distributor.do_copy(&item, &RunOptions::default());
+        collected.lock().unwrap().sort();
+        assert_eq!(*collected.lock().unwrap(), vec![
+            format!("{:?}", Copied(root.join("a.txt"), target.join("a.txt"))),
+            format!("{:?}", Copied(root.join("b.txt"), target.join("b.txt"))),
+        ]);
+
+        println!("the callback is called as each file is copied, before the next file");
+        let sink = collected.clone();
+        let target_b = target.join("b.txt");
+        distributor.set_on_result(move |result| {
+            sink.lock().unwrap().push(format!("{:?} {}", result, target_b.exists()));
+        });
+        collected.lock().unwrap().clear();
+        fs::remove_file(target.join("b.txt")).unwrap();
+        distributor.do_copy(&item, &RunOptions { force: true, fail_fast: true, ..Default::default() });
+        assert_eq!(*collected.lock().unwrap(), vec![
+            format!("{:?} false", Same(root.join("a.txt"), target.join("a.txt"))),
+            format!("{:?} true", Copied(root.join("b.txt"), target.join("b.txt"))),
+        ]);
+
+        println!("do_copy_items calls the callback for every item");
+        let other_root = temp_path.join("other");
+        fs::create_dir_all(&other_root).unwrap();
+        fs::write(other_root.join("c.txt"), "c").unwrap();
+        let other = DistributorItem::builder("other", &other_root).target(&target).build();
+        let sink = collected.clone();
+        distributor.set_on_result(move |result| sink.lock().unwrap().push(format!("{:?}", result)));
+        collected.lock().unwrap().clear();
+        distributor.do_copy_items(&[&item, &other], &RunOptions::default(), 2);
+        collected.lock().unwrap().sort();
+        let mut expected = vec![
+            format!("{:?}", Copied(other_root.join("c.txt"), target.join("c.txt"))),
+            format!("{:?}", UpToDate(root.join("a.txt"))),
+            format!("{:?}", UpToDate(root.join("b.txt"))),
+        ];
+        expected.sort();
+        assert_eq!(*collected.lock().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_do_copy_last_run() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let root = temp_path.join("resource");
+        let cache_path = temp_path.join("cache.json");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a.txt"), "a").unwrap();
+
+        let item = DistributorItem::builder("test", &root).target(temp_path.join("target")).build();
+        let mut distributor = Distributor::from_cache_path(Some(&cache_path), Verbosity::Quiet);
+        assert_eq!(distributor.db_cache.get_last_run("test"), None);
+
+        let before = SystemTime::now() - Duration::from_secs(1);
+        distributor.do_copy(&item, &RunOptions::default());
+        let last_run = parse_since(distributor.db_cache.get_last_run("test").unwrap()).unwrap();
+        assert!(last_run >= before && last_run <= SystemTime::now());
+
+        println!("dry run and failed run keep the last run");
+        let epoch = format_rfc3339(SystemTime::UNIX_EPOCH);
+        distributor.db_cache.update_last_run("test", SystemTime::UNIX_EPOCH);
+        distributor.do_copy(&item, &RunOptions { dry_run: true, ..Default::default() });
+        let blocker = temp_path.join("blocker");
+        fs::write(&blocker, "blocks directory creation").unwrap();
+        let failed_item = DistributorItem::builder("test", &root).target(blocker.join("bad")).build();
+        distributor.do_copy(&failed_item, &RunOptions::default());
+        assert_eq!(distributor.db_cache.get_last_run("test"), Some(epoch.as_str()));
+
+        distributor.do_copy(&item, &RunOptions::default());
+        assert_ne!(distributor.db_cache.get_last_run("test"), Some(epoch.as_str()));
+
+        distributor.db_cache.save(None).unwrap();
+        let loaded = FileDistributorCache::load(Some(&cache_path));
+        assert_eq!(loaded.get_last_run("test"), distributor.db_cache.get_last_run("test"));
+    }
+
+    #[test]
+    fn test_drop_saves_last_run_of_archive_item() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let root = temp_path.join("resource");
+        let cache_path = temp_path.join("cache.db");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a.txt"), "a").unwrap();
+
+        let item = DistributorItem::builder("test", &root).target(temp_path.join("out/bundle.zip")).build();
+        {
+            let mut distributor = Distributor::from_cache_path(Some(&cache_path), Verbosity::Quiet);
+            distributor.do_copy(&item, &RunOptions::default());
+            assert!(temp_path.join("out/bundle.zip").is_file());
+        }
+
+        println!("the last run of an item without file records is saved on drop");
+        assert!(FileDistributorCache::load(Some(&cache_path)).get_last_run("test").is_some());
+    }
+
+    #[test]
+    fn test_do_copy_with_cache_path() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let root = temp_path.join("resource");
+        let cache_path = temp_path.join("shared/cache.json");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a.txt"), "a").unwrap();
+
+        let item = DistributorItem::builder("test", &root).target(temp_path.join("target")).build();
+        let mut distributor = Distributor::with_cache(FileDistributorCache::load(Some(&cache_path)), Verbosity::Quiet);
+        distributor.do_copy(&item, &RunOptions::default());
+        assert_eq!(distributor.db_cache.loaded_path(), cache_path);
+        drop(distributor);
+
+        let saved: serde_json::Value = serde_json::from_str(&fs::read_to_string(&cache_path).unwrap()).unwrap();
+        assert!(saved.to_string().contains("a.txt"));
+        let reloaded = FileDistributorCache::load(Some(&cache_path));
+        assert!(!reloaded.is_file_outdated(&root.join("a.txt"), &temp_path.join("target/a.txt")));
+    }
+
+    #[test]
+    fn test_from_cache_path() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let root = temp_path.join("resource");
+        let target = temp_path.join("target");
+        let cache_path = temp_path.join("cache.db");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a.txt"), "a").unwrap();
+
+        let item = DistributorItem::builder("test", &root).target(&target).build();
+        let mut distributor = Distributor::from_cache_path(Some(&cache_path), Verbosity::Quiet);
+        distributor.do_copy(&item, &RunOptions::default());
+        drop(distributor);
+
+        assert!(cache_path.is_file());
+        let mut distributor = Distributor::from_cache_path(Some(&cache_path), Verbosity::Quiet);
+        let results = distributor.do_copy(&item, &RunOptions::default());
+        assert!(matches!(&results[..], [Ok(UpToDate(_))]));
+    }
+
+    #[test]
+    fn test_do_copy_max_file_size() {
+        let (_temp_dir, root, target, mut distributor) = new_test_distributor(Verbosity::Quiet);
+        fs::write(root.join("small.txt"), "a".repeat(1024)).unwrap();
+        fs::write(root.join("large.bin"), "b".repeat(2048)).unwrap();
+        let item = DistributorItem::builder("test", &root).target(&target).build();
+        let options = RunOptions { max_file_size: Some(parse_size("1KB").unwrap()), ..Default::default() };
+        let results = distributor.do_copy(&item, &options);
+
+        assert!(matches!(&results[..], [Ok(DistributorResultType::TooLarge(f, 2048)), Ok(Copied(_, _))]
+                         if *f == root.join("large.bin")));
+        assert!(target.join("small.txt").is_file());
+        assert!(!target.join("large.bin").exists());
+        assert_eq!(RunSummary::from_results(&results).skipped, 1);
+        let record = DistributorResultRecord::from(&results[0]);
+        assert_eq!(record.action, "Skipped");
+        assert!(record.reason.is_some());
+
+        println!("the smaller of the option and the item field applies");
+        let item = DistributorItem { max_file_size: Some(1000), ..item };
+        let results = distributor.do_copy(&item, &RunOptions { force: true, ..options });
+        assert_eq!(RunSummary::from_results(&results).skipped, 2);
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert_eq!(parse_size("100KB"), Ok(100 * 1024));
+        assert_eq!(parse_size("1.5mb"), Ok(1536 * 1024));
+        assert_eq!(parse_size("2 GiB"), Ok(2 << 30));
+        assert!(parse_size("10XB").is_err());
+        assert!(parse_size("MB").is_err());
+
+        assert_eq!(parse_buffer_size("64KB").map(NonZeroUsize::get), Ok(64 * 1024));
+        assert!(parse_buffer_size("0").is_err());
+    }
+
+    #[test]
+    fn test_do_copy_include_empty_dirs() {
+        let (_temp_dir, root, target, mut distributor) = new_test_distributor(Verbosity::Quiet);
+        fs::create_dir_all(root.join("logs")).unwrap();
+        fs::create_dir_all(root.join("cache/tmp")).unwrap();
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("sub/a.txt"), "a").unwrap();
+        let item = DistributorItem::builder("test", &root).target(&target).ignore("cache/**").build();
+        distributor.do_copy(&item, &RunOptions::default());
+        assert!(!target.join("logs").exists());
+
+        let results = distributor.do_copy(&item, &RunOptions { include_empty_dirs: true, ..Default::default() });
+        assert!(results.iter().all(|result| result.is_ok()));
+        assert!(target.join("logs").is_dir());
+        assert!(target.join("sub/a.txt").is_file());
+        assert!(!target.join("cache").exists());
+    }
+
+    #[test]
+    fn test_do_copy_default_target() {
+        let (temp_dir, root, _, mut distributor) = new_test_distributor(Verbosity::default());
+        let temp_path = temp_dir.path().to_path_buf();
+        let default_target = temp_path.join("deploy");
+        fs::write(root.join("a.txt"), "a").unwrap();
+        let options = RunOptions { default_target: Some(default_target.clone()), ..Default::default() };
+        let item = DistributorItem::builder("test", &root).build();
+        distributor.do_copy(&item, &options);
+        assert!(default_target.join("test/a.txt").is_file());
+
+        println!("explicit targets take precedence");
+        let explicit = temp_path.join("explicit");
+        let item = DistributorItem::builder("other", &root).target(&explicit).build();
+        distributor.do_copy(&item, &options);
+        assert!(explicit.join("a.txt").is_file());
+        assert!(!default_target.join("other").exists());
+    }
+
+    #[test]
+    fn test_do_copy_since() {
+        let (_temp_dir, root, target, mut distributor) = new_test_distributor(Verbosity::default());
+        fs::write(root.join("old.txt"), "old").unwrap();
+        fs::write(root.join("new.txt"), "new").unwrap();
+        set_file_mtime(&root.join("old.txt"), 1_000_000_000);
+        let item = DistributorItem::builder("test", &root).target(&target).build();
+        let options = RunOptions { since: Some(parse_since("2h").unwrap()), ..Default::default() };
+        let results = distributor.do_copy(&item, &options);
+
+        assert_eq!(results.len(), 1);
+        assert!(matches!(&results[0], Ok(Copied(s, _)) if s == &root.join("new.txt")));
+        assert!(target.join("new.txt").exists());
+        assert!(!target.join("old.txt").exists());
+    }
+
+    #[test]
+    fn test_do_copy_multiple_roots() {
+        let (temp_dir, _, target, mut distributor) = new_test_distributor(Verbosity::default());
+        let temp_path = temp_dir.path().to_path_buf();
+        let root_a = temp_path.join("resource-a");
+        let root_b = temp_path.join("resource-b");
+        fs::create_dir_all(root_a.join("sub")).unwrap();
+        fs::create_dir_all(root_b.join("other")).unwrap();
+        fs::write(root_a.join("sub/a.txt"), "a").unwrap();
+        fs::write(root_b.join("other/b.txt"), "b").unwrap();
+        let mut item = DistributorItem::builder("test", &root_a).target(&target).build();
+        item.roots.push(root_b.clone());
+        let results = distributor.do_copy(&item, &RunOptions { mirror: true, ..Default::default() });
+
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|result| matches!(result, Ok(Copied(_, _)))));
+        assert_eq!(fs::read_to_string(target.join("sub/a.txt")).unwrap(), "a");
+        assert_eq!(fs::read_to_string(target.join("other/b.txt")).unwrap(), "b");
+
+        println!("the same relative path in two roots aborts the distributor");
+        fs::create_dir_all(root_b.join("sub")).unwrap();
+        fs::write(root_b.join("sub/a.txt"), "another").unwrap();
+        let results = distributor.do_copy(&item, &RunOptions { force: true, ..Default::default() });
+        assert!(matches!(&results[..],
+                         [Err(DistributorError::SourceCollectFailed(DistributorConfigError::RootCollision(p)))]
+                         if p == Path::new("sub/a.txt")));
+        assert_eq!(fs::read_to_string(target.join("sub/a.txt")).unwrap(), "a");
+    }
+
+    #[test]
+    fn test_do_copy_exceed_source_limit() {
+        let (_temp_dir, root, target, mut distributor) = new_test_distributor(Verbosity::default());
+        fs::write(root.join("a.txt"), "a").unwrap();
+        fs::write(root.join("b.txt"), "b").unwrap();
+        let mut item = DistributorItem::builder("test", &root).target(&target).build();
+        item.max_files = Some(1);
+        for options in [RunOptions::default(), RunOptions { mtime_only: true, ..Default::default() }] {
+            let results = distributor.do_copy(&item, &options);
+            assert!(matches!(results[..],
+                             [Err(DistributorError::SourceLimitExceeded(DistributorConfigError::ExceedMaxFiles(2, 1)))]));
+            assert!(!target.exists());
+        }
+
+        println!("force ignores the limit");
+        let results = distributor.do_copy(&item, &RunOptions { force: true, ..Default::default() });
+        assert_eq!(results.len(), 2);
+        assert!(target.join("a.txt").is_file());
+    }
+
+    #[test]
+    fn test_do_copy_flatten() {
+        let (_temp_dir, root, target, mut distributor) = new_test_distributor(Verbosity::default());
+        fs::create_dir_all(root.join("a/deep")).unwrap();
+        fs::create_dir_all(root.join("b")).unwrap();
+        fs::write(root.join("a/deep/one.txt"), "one").unwrap();
+        fs::write(root.join("b/two.txt"), "two").unwrap();
+        let item = DistributorItem::builder("test", &root).target(&target).build();
+        let options = RunOptions { flatten: true, mirror: true, ..Default::default() };
+        let results = distributor.do_copy(&item, &options);
+
+        assert!(results.iter().all(|result| matches!(result, Ok(Copied(_, _)))));
+        assert_eq!(fs::read_to_string(target.join("one.txt")).unwrap(), "one");
+        assert_eq!(fs::read_to_string(target.join("two.txt")).unwrap(), "two");
+        assert!(!target.join("a").exists());
+
+        println!("sources with the same file name are reported instead of overwritten");
+        fs::write(root.join("b/one.txt"), "another").unwrap();
+        let results = distributor.do_copy(&item, &options);
+
+        assert_eq!(results.len(), 3);
+        assert!(matches!(&results[0], Err(DistributorError::NameCollision(p)) if p == &root.join("a/deep/one.txt")));
+        assert!(matches!(&results[1], Err(DistributorError::NameCollision(p)) if p == &root.join("b/one.txt")));
+        assert!(matches!(&results[2], Ok(UpToDate(s)) if s == &root.join("b/two.txt")));
+        assert_eq!(fs::read_to_string(target.join("one.txt")).unwrap(), "one");
+    }
+
+    #[test]
+    fn test_progress_bar_hidden_when_quiet() {
+        let options = RunOptions { verbosity: Verbosity::Quiet, ..Default::default() };
+        assert!(new_progress_bar(10, &options).is_hidden());
+        assert!(new_progress_bar(0, &RunOptions::default()).is_hidden());
+    }
+
+    #[test]
+    fn test_parse_since() {
+        let since = parse_since("30m").unwrap();
+        let now = SystemTime::now();
+        assert!(now.duration_since(since).unwrap() >= Duration::from_secs(30 * 60));
+
+        assert_eq!(parse_since("1970-01-02T00:00:00Z").unwrap(),
+                   SystemTime::UNIX_EPOCH + Duration::from_secs(86400));
+        assert_eq!(parse_since("2024-03-01T08:30:00.5+08:00").unwrap(),
+                   SystemTime::UNIX_EPOCH + Duration::new(1_709_253_000, 500_000_000));
+        assert_eq!(parse_since("2000-02-29T23:59:59-01:00").unwrap(),
+                   SystemTime::UNIX_EPOCH + Duration::from_secs(951_872_399));
+
+        for time in ["1970-01-01T00:00:00Z", "2000-02-29T23:59:59Z", "2024-12-31T08:30:00Z"] {
+            assert_eq!(format_rfc3339(parse_since(time).unwrap()), time);
+        }
+
+        assert!(parse_since("yesterday").is_err());
+        assert!(parse_since("2024-13-01T00:00:00Z").is_err());
+        assert!(parse_since("1960-01-01T00:00:00Z").is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_do_copy_non_utf8_path() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let (_temp_dir, root, target, mut distributor) = new_test_distributor(Verbosity::default());
+        let file_name = OsStr::from_bytes(b"invalid-\xff.txt");
+        fs::write(root.join(file_name), "a").unwrap();
+
+        let item = DistributorItem::builder("test", &root).target(&target).build();
+
+        let results = distributor.do_copy(&item, &RunOptions {
+            move_source: true,
+            ..Default::default()
+        });
+        assert!(matches!(&results[0], Ok(Copied(f, _)) if f == &root.join(file_name)));
+        assert!(matches!(&results[1], Ok(DistributorResultType::Moved(_))));
+        assert_eq!(fs::read(target.join(file_name)).unwrap(), b"a");
+        assert!(!root.join(file_name).exists());
+
+        let record = DistributorResultRecord::from(&results[0]);
+        assert_eq!(record.source, Some(root.join("invalid-\u{fffd}.txt").to_string_lossy().to_string()));
+    }
+
+    #[test]
+    fn test_template() {
+        let (_temp_dir, root, target, mut distributor) = new_test_distributor(Verbosity::default());
+        fs::write(root.join("app.conf"), "version={{version}}\nenv={{env}}\nname={{name}}").unwrap();
+        fs::write(root.join("app.bin"), "version={{version}}").unwrap();
+
+        let item = DistributorItem {
+            name: "test".to_string(),
+            root: root.clone(),
+            to: vec![target.clone()],
+            variables: BTreeMap::from([
+                ("version".to_string(), "1.2.0".to_string()),
+                ("env".to_string(), "prod".to_string()),
+            ]),
+            ..Default::default()
+        };
+        let options = RunOptions {
+            force: true,
+            template: Some(Template {
+                variables: BTreeMap::from([("env".to_string(), "dev".to_string())]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        distributor.do_copy(&item, &options);
+
+        assert_eq!(fs::read_to_string(target.join("app.conf")).unwrap(), "version=1.2.0\nenv=prod\nname={{name}}");
+        assert_eq!(fs::read_to_string(target.join("app.bin")).unwrap(), "version={{version}}");
+
+        println!("unchanged render is not rewritten");
+        let results = distributor.do_copy(&item, &options);
+        assert!(results.iter().all(|result| matches!(result, Ok(Same(_, _)))));
+
+        println!("changed variables render again without force");
+        let item = DistributorItem {
+            variables: BTreeMap::from([("version".to_string(), "1.3.0".to_string())]),
+            ..item
+        };
+        let options = RunOptions { force: false, ..options };
+        distributor.do_copy(&item, &options);
+        assert_eq!(fs::read_to_string(target.join("app.conf")).unwrap(), "version=1.3.0\nenv=dev\nname={{name}}");
+
+        println!("verify compares templated files with the render");
+        assert!(verify_item(&item, &[], options.template.as_ref()).iter().all(|verification| verification.is_ok()));
+        assert_eq!(verify_item(&item, &[], None)[0].mismatched, vec![target.join("app.conf")]);
+
+        println!("a run without template copies the source over the render");
+        distributor.do_copy(&item, &RunOptions::default());
+        assert_eq!(fs::read_to_string(target.join("app.conf")).unwrap(),
+                   "version={{version}}\nenv={{env}}\nname={{name}}");
+    }
+
+    #[test]
+    fn test_pre_run_hook() {
+        let (_temp_dir, root, target, mut distributor) = new_test_distributor(Verbosity::default());
+
+        let mut item = DistributorItem {
+            name: "test".to_string(),
+            root: root.clone(),
+            to: vec![target.clone()],
+            pre_run: Some("echo generated> generated.txt".to_string()),
+            ..Default::default()
+        };
+
+        let results = distributor.do_copy(&item, &RunOptions::default());
+
+        assert!(results.iter().all(|result| result.is_ok()));
+        assert!(target.join("generated.txt").is_file());
+
+        println!("failed hook aborts the distributor");
+        fs::remove_file(target.join("generated.txt")).unwrap();
+        item.pre_run = Some("exit 3".to_string());
+        let results = distributor.do_copy(&item, &RunOptions { force: true, ..Default::default() });
+
+        assert!(matches!(results[..], [Err(DistributorError::HookFailed(_, Some(3)))]));
+        assert!(!target.join("generated.txt").exists());
+    }
+
+    #[test]
+    fn test_write_results_quiet() {
+        let results: Vec<DistributorResult> = vec![
+            Ok(UpToDate(PathBuf::from("a"))),
+            Ok(Same(PathBuf::from("b"), PathBuf::from("c"))),
+        ];
+
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        write_results(&results, Verbosity::Quiet, &mut out, &mut err).unwrap();
+        assert!(out.is_empty());
+        assert!(err.is_empty());
+
+        println!("up to date results are shown only when verbose");
+        write_results(&results, Verbosity::Normal, &mut out, &mut err).unwrap();
+        assert!(out.is_empty());
+        write_results(&results, Verbosity::Verbose, &mut out, &mut err).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "[UpToDate]\"a\"\n[Same]\"b\"\"c\"\n");
+
+        println!("errors are written even when quiet");
+        let mut out = Vec::new();
+        let results: Vec<DistributorResult> = vec![Err(DistributorError::TargetExists(PathBuf::from("d")))];
+        write_results(&results, Verbosity::Quiet, &mut out, &mut err).unwrap();
+        assert!(out.is_empty());
+        assert!(!err.is_empty());
+    }
+
+    #[test]
+    fn test_do_copy_quiet_up_to_date() {
+        let (_temp_dir, root, target, mut distributor) = new_test_distributor(Verbosity::Quiet);
+        fs::write(root.join("a.txt"), "a").unwrap();
+
+        let item = DistributorItem::builder("test", &root).target(&target).build();
+        let options = RunOptions { verbosity: Verbosity::Quiet, ..Default::default() };
+        distributor.do_copy(&item, &options);
+
+        let (results, log) = capture_log(Verbosity::Quiet, || {
+            let results = distributor.do_copy(&item, &options);
+            log_results(&results);
+            results
+        });
+        assert!(matches!(results[..], [Ok(UpToDate(_))]));
+        assert!(log.is_empty(), "{}", log);
+
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+        write_results(&results, Verbosity::Quiet, &mut out, &mut err).unwrap();
+        assert!(out.is_empty());
+        assert!(err.is_empty());
+    }
+
+    #[derive(Clone, Default)]
+    struct CapturedLog(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl Write for CapturedLog {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// 以 verbosity 对应的级别捕获 f 执行期间的 tracing 输出。
+    fn capture_log<T>(verbosity: Verbosity, f: impl FnOnce() -> T) -> (T, String) {
+        let log = CapturedLog::default();
+        let writer = log.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_max_level(verbosity.level_filter())
+            .with_ansi(false)
+            .with_target(false)
+            .without_time()
+            .finish();
+        let value = tracing::subscriber::with_default(subscriber, f);
+        let output = String::from_utf8(log.0.lock().unwrap().clone()).unwrap();
+
+        (value, output)
+    }
+
+    #[test]
+    fn test_log_results() {
+        let results: Vec<DistributorResult> = vec![
+            Ok(Copied(PathBuf::from("a"), PathBuf::from("b"))),
+            Ok(UpToDate(PathBuf::from("c"))),
+            Ok(DistributorResultType::Skipped(PathBuf::from("d"), PathBuf::from("e"))),
+            Err(DistributorError::TargetExists(PathBuf::from("f"))),
+        ];
+        let capture = |verbosity: Verbosity| {
+            let (_, output) = capture_log(verbosity, || log_results(&results));
+            output.lines().map(|line| line.trim().to_string()).collect::<Vec<_>>()
+        };
+
+        let lines = capture(Verbosity::Normal);
+        assert_eq!(lines.len(), 3, "{:?}", lines);
+        assert!(lines[0].starts_with("INFO copied") && lines[0].contains(r#"target="b""#), "{}", lines[0]);
+        assert!(lines[1].starts_with("WARN skipped"), "{}", lines[1]);
+        assert!(lines[2].starts_with("ERROR") && lines[2].contains("\"f\""), "{}", lines[2]);
+
+        println!("up to date is a debug event");
+        assert!(capture(Verbosity::Verbose).iter().any(|line| line.starts_with("DEBUG up to date")));
+        assert_eq!(capture(Verbosity::Quiet).len(), 1);
+    }
+
+    #[test]
+    fn test_run_summary() {
+        let results: Vec<DistributorResult> = vec![
+            Ok(Copied(PathBuf::from("a"), PathBuf::from("b"))),
+            Ok(Copied(PathBuf::from("c"), PathBuf::from("d"))),
+            Ok(Copied(PathBuf::from("e"), PathBuf::from("f"))),
+            Ok(UpToDate(PathBuf::from("g"))),
+            Err(DistributorError::TargetExists(PathBuf::from("h"))),
+        ];
+
+        let summary = RunSummary::from_results(&results);
+
+        assert_eq!(summary, RunSummary { copied: 3, up_to_date: 1, errors: 1, ..Default::default() });
+        assert_eq!(summary.to_string(), "3 files copied, 1 up to date, 1 error");
+    }
+
+    #[test]
+    fn test_run_summary_of_mixed_run() {
+        let (_temp_dir, root, target, mut distributor) = new_test_distributor(Verbosity::default());
+        fs::create_dir_all(&target).unwrap();
+        fs::write(root.join("a.txt"), "a").unwrap();
+        fs::write(root.join("b.txt"), "bb").unwrap();
+        fs::write(root.join("c.txt"), "ccc").unwrap();
+        fs::write(target.join("c.txt"), "ccc").unwrap();
+        let item = DistributorItem::builder("test", &root).target(&target).build();
+        let summary = RunSummary::from_results(&distributor.do_copy(&item, &RunOptions::default()));
+        assert_eq!(summary, RunSummary { copied: 2, bytes: 3, same: 1, ..Default::default() });
+
+        fs::write(root.join("a.txt"), "aaaa").unwrap();
+        set_file_mtime(&root.join("a.txt"), 4_000_000_000);
+        let summary = RunSummary::from_results(&distributor.do_copy(&item, &RunOptions::default()));
+        assert_eq!(summary, RunSummary { copied: 1, bytes: 4, up_to_date: 2, ..Default::default() });
+        assert_eq!(summary.to_string(), "1 files copied (4 B), 2 up to date, 0 errors");
+    }
 
     #[test]
     fn test_copy_to() {
@@ -275,7 +2963,7 @@ mod tests {
         let source_path = Path::new("resource/template.txt");
         let target_path = Path::new("test-target/copy_file_all_full/test.txt");
 
-        let _ = copy_file_with_full_target_path(source_path, target_path);
+        let _ = copy_file_with_full_target_path(source_path, target_path, &RunOptions::default());
 
         assert_eq!(
             std::fs::read_to_string(source_path).unwrap(),
@@ -283,12 +2971,149 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_copy_large_file() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let source = temp_path.join("large.bin");
+        let target = temp_path.join("target/large.bin");
+        let content: Vec<u8> = (0..(1024 * 1024 + 7)).map(|i| (i % 251) as u8).collect();
+        fs::write(&source, &content).unwrap();
+
+        let result = copy_file_with_full_target_path(&source, &target, &RunOptions::default());
+
+        assert!(matches!(result, Ok(Copied(_, _))));
+        assert_eq!(fs::read(&target).unwrap(), content);
+        assert!(matches!(copy_file_with_full_target_path(&source, &target, &RunOptions::default()), Ok(Same(_, _))));
+    }
+
+    #[test]
+    fn test_copy_file_atomic() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let source = temp_path.join("source.txt");
+        let target = temp_path.join("target/atomic.txt");
+        fs::write(&source, "atomic").unwrap();
+        fs::create_dir_all(temp_path.join("target")).unwrap();
+        fs::write(&target, "old content").unwrap();
+        fs::write(temp_path.join("target/atomic.txt.tmp"), "unrelated").unwrap();
+
+        let options = RunOptions { atomic: true, ..Default::default() };
+        let result = copy_file_with_full_target_path(&source, &target, &options);
+
+        assert!(matches!(result, Ok(Copied(_, _))));
+        assert_eq!(fs::read_to_string(&target).unwrap(), "atomic");
+        println!("an existing file with the old temp name is kept and no temp file is left");
+        assert_eq!(fs::read_to_string(temp_path.join("target/atomic.txt.tmp")).unwrap(), "unrelated");
+        assert_eq!(fs::read_dir(temp_path.join("target")).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_distribute_file_on_conflict() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let source = temp_path.join("source.txt");
+        let target = temp_path.join("target.txt");
+        fs::write(&source, "source").unwrap();
+        let reset_target = |secs: u64| {
+            fs::write(&target, "target").unwrap();
+            set_file_mtime(&target, secs);
+        };
+        let copy = |on_conflict| {
+            distribute_file(&source, &target, &RunOptions { on_conflict, ..Default::default() })
+        };
+        set_file_mtime(&source, 1000);
+
+        reset_target(990);
+        assert!(matches!(copy(ConflictStrategy::Overwrite), Ok(Copied(_, _))));
+        assert_eq!(fs::read_to_string(&target).unwrap(), "source");
+
+        reset_target(990);
+        assert!(matches!(copy(ConflictStrategy::Skip), Ok(DistributorResultType::Skipped(_, _))));
+        assert_eq!(fs::read_to_string(&target).unwrap(), "target");
+
+        println!("newer overwrites only an older target");
+        reset_target(1010);
+        assert!(matches!(copy(ConflictStrategy::Newer), Ok(DistributorResultType::Skipped(_, _))));
+        assert_eq!(fs::read_to_string(&target).unwrap(), "target");
+        reset_target(990);
+        assert!(matches!(copy(ConflictStrategy::Newer), Ok(Copied(_, _))));
+        assert_eq!(fs::read_to_string(&target).unwrap(), "source");
+
+        println!("missing target is always copied");
+        fs::remove_file(&target).unwrap();
+        assert!(matches!(copy(ConflictStrategy::Skip), Ok(Copied(_, _))));
+
+        println!("rendered template is compared with the target");
+        let template = Template {
+            variables: BTreeMap::from([("name".to_string(), "world".to_string())]),
+            ..Default::default()
+        };
+        let render = |on_conflict| {
+            distribute_file(&source,
+                            &target,
+                            &RunOptions { on_conflict, template: Some(template.clone()), ..Default::default() })
+        };
+        fs::write(&source, "hello {{name}}").unwrap();
+        fs::write(&target, "hello world").unwrap();
+        assert!(matches!(render(ConflictStrategy::Skip), Ok(Same(_, _))));
+        fs::write(&target, "hello").unwrap();
+        assert!(matches!(render(ConflictStrategy::Skip), Ok(DistributorResultType::Skipped(_, _))));
+        assert_eq!(fs::read_to_string(&target).unwrap(), "hello");
+
+        println!("hard link does not replace a skipped target");
+        let options = RunOptions {
+            on_conflict: ConflictStrategy::Skip,
+            link: Some(LinkMode::Hard),
+            force: true,
+            ..Default::default()
+        };
+        assert!(matches!(distribute_file(&source, &target, &options), Ok(DistributorResultType::Skipped(_, _))));
+        assert_eq!(fs::read_to_string(&target).unwrap(), "hello");
+    }
+
+    #[test]
+    fn test_do_copy_skipped_not_cached() {
+        let (_temp_dir, root, target, mut distributor) = new_test_distributor(Verbosity::Quiet);
+        fs::create_dir_all(&target).unwrap();
+        fs::write(root.join("a.txt"), "source").unwrap();
+        fs::write(target.join("a.txt"), "target").unwrap();
+
+        let item = DistributorItem::builder("test", &root).target(&target).build();
+        let options = RunOptions { on_conflict: ConflictStrategy::Skip, ..Default::default() };
+        let results = distributor.do_copy(&item, &options);
+        assert!(matches!(results[..], [Ok(DistributorResultType::Skipped(_, _))]));
+
+        println!("the skipped target is compared again by a default run");
+        let results = distributor.do_copy(&item, &RunOptions::default());
+        assert!(matches!(results[..], [Ok(Copied(_, _))]));
+        assert_eq!(fs::read_to_string(target.join("a.txt")).unwrap(), "source");
+    }
+
+    #[test]
+    fn test_copy_file_with_target_parent_is_file() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let blocker = temp_path.join("blocker");
+        fs::write(&blocker, "not a directory").unwrap();
+
+        let result = copy_file_with_full_target_path(
+            Path::new("resource/template.txt"),
+            &blocker.join("sub/test.txt"),
+            &RunOptions::default());
+
+        match result {
+            Err(DistributorError::TargetParentIsFile(p)) => assert_eq!(p, blocker),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
     #[test]
     fn test_copy_file_with_no_target_file_name() {
         let source_path = Path::new("resource/template.txt");
         let target_path = Path::new("test-target/copy_file_with_no_target_file_name/");
 
-        let _ = copy_file_to_with_default_name(source_path, target_path, "template.txt");
+        let _ = copy_file_to_with_default_name(source_path, target_path, "template.txt", &RunOptions::default());
 
         assert_eq!(
             std::fs::read_to_string(source_path).unwrap(),
@@ -297,12 +3122,13 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_compare_file() {
         let source_path = Path::new("resource/sub-resource-dir-a/template-a.txt");
         let target_path = Path::new("resource/sub-resource-dir-b/template-b.txt");
 
         assert_eq!(
-            compare_file(source_path, target_path).unwrap(),
+            compare_file(source_path, target_path, DEFAULT_BUFFER_SIZE).unwrap(),
             false,
         );
 
@@ -310,11 +3136,538 @@ mod tests {
         let target_path = Path::new("resource/sub-resource-dir-a/template-c.txt");
 
         assert_eq!(
-            compare_file(source_path, target_path).unwrap(),
+            compare_file(source_path, target_path, DEFAULT_BUFFER_SIZE).unwrap(),
             true,
         );
     }
 
+    #[test]
+    fn test_copy_with_buffer_size() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let source = temp_path.join("large.bin");
+        let content: Vec<u8> = (0..4 * 1024 * 1024u32).map(|i| (i.wrapping_mul(2654435761) >> 24) as u8).collect();
+        fs::write(&source, &content).unwrap();
+
+        let mut targets = vec![];
+        for buffer_size in [1024, 1024 * 1024] {
+            let target = temp_path.join(format!("target_{}.bin", buffer_size));
+            let options = RunOptions { buffer_size: NonZeroUsize::new(buffer_size), ..Default::default() };
+            let result = copy_file_with_full_target_path(&source, &target, &options);
+
+            assert!(matches!(result, Ok(Copied(_, _))));
+            assert!(compare_file(&source, &target, buffer_size).unwrap());
+            targets.push(target);
+        }
+        assert_eq!(fs::read(&targets[0]).unwrap(), content);
+        assert_eq!(fs::read(&targets[1]).unwrap(), content);
+
+        println!("a difference in the last byte is found with a small buffer");
+        let mut changed = content;
+        *changed.last_mut().unwrap() ^= 1;
+        fs::write(&targets[0], &changed).unwrap();
+        assert!(!compare_file(&source, &targets[0], 1000).unwrap());
+    }
+
+    #[test]
+    fn test_copy_file_with_rename() {
+        let (_temp_dir, _, target, mut distributor) = new_test_distributor(Verbosity::default());
+
+        let item = DistributorItem {
+            name: "test".to_string(),
+            root: PathBuf::from("resource/template.txt"),
+            to: vec![target.clone()],
+            rename: BTreeMap::from([(target.clone(), "config.toml".to_string())]),
+            ..Default::default()
+        };
+        distributor.do_copy(&item, &RunOptions { force: true, ..Default::default() });
+
+        assert_eq!(
+            fs::read_to_string("resource/template.txt").unwrap(),
+            fs::read_to_string(target.join("config.toml")).unwrap(),
+        );
+        assert!(!target.join("template.txt").exists());
+    }
+
+    #[test]
+    fn test_do_copy_dry_run() {
+        let (_temp_dir, _, target, mut distributor) = new_test_distributor(Verbosity::default());
+
+        let item = DistributorItem {
+            name: "test".to_string(),
+            root: PathBuf::from("resource"),
+            to: vec![target.clone()],
+            ..Default::default()
+        };
+        distributor.do_copy(&item, &RunOptions { dry_run: true, verbosity: Verbosity::Verbose, ..Default::default() });
+
+        assert!(!target.exists());
+        assert!(distributor.db_cache.is_empty());
+        assert!(copy_by_mtime(&item, &RunOptions { dry_run: true, ..Default::default() })
+            .iter()
+            .all(|r| matches!(r, Ok(Copied(_, _)))));
+        assert!(!target.exists());
+    }
+
+    #[test]
+    fn test_copy_pairs_keeps_order() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let pairs: Vec<(PathBuf, PathBuf)> = (0..64)
+            .map(|i| (PathBuf::from("resource/template.txt"), temp_path.join(format!("{:02}.txt", i))))
+            .collect();
+
+        let results = copy_pairs(&pairs, &RunOptions::default());
+
+        assert_eq!(results.len(), pairs.len());
+        for (result, (_, target)) in results.iter().zip(pairs.iter()) {
+            match result {
+                Ok(Copied(_, t)) => assert_eq!(t, target),
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_remove_moved_source() {
+        let (temp_dir, root, _, mut distributor) = new_test_distributor(Verbosity::default());
+        let temp_path = temp_dir.path().to_path_buf();
+        let good_target = temp_path.join("good");
+        let bad_target = temp_path.join("bad");
+        fs::write(root.join("template.txt"), "test").unwrap();
+        fs::write(&bad_target, "blocks directory creation").unwrap();
+
+        let mut item = DistributorItem {
+            name: "test".to_string(),
+            root: root.clone(),
+            to: vec![good_target.clone(), bad_target.clone()],
+            ..Default::default()
+        };
+        let options = RunOptions { force: true, move_source: true, ..Default::default() };
+
+        println!("one target fails, source is kept");
+        distributor.do_copy(&item, &options);
+        assert!(root.join("template.txt").is_file());
+        assert!(good_target.join("template.txt").is_file());
+
+        println!("all targets succeed, source is removed");
+        item.to = vec![good_target.clone(), temp_path.join("good2")];
+        distributor.do_copy(&item, &options);
+        assert!(!root.join("template.txt").exists());
+        assert!(temp_path.join("good2/template.txt").is_file());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_link_file_to() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let source = Path::new("resource/template.txt");
+        let target = temp_path.join("sub/link.txt");
+
+        let result = link_file_to(source, &target, false, false);
+        assert!(matches!(result, Ok(DistributorResultType::Linked(_, _))));
+        assert!(fs::symlink_metadata(&target).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_link(&target).unwrap(), source.canonicalize().unwrap());
+
+        assert!(matches!(link_file_to(source, &target, false, false), Ok(Same(_, _))));
+
+        println!("non-symlink target is replaced only with force");
+        let plain = temp_path.join("plain.txt");
+        fs::write(&plain, "plain").unwrap();
+        assert!(matches!(
+            link_file_to(source, &plain, false, false),
+            Err(DistributorError::TargetExists(_))
+        ));
+        assert!(matches!(
+            link_file_to(source, &plain, true, false),
+            Ok(DistributorResultType::Linked(_, _))
+        ));
+        assert!(fs::symlink_metadata(&plain).unwrap().file_type().is_symlink());
+    }
+
+    #[test]
+    fn test_hard_link_file_to() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let source = temp_path.join("source.txt");
+        let target = temp_path.join("sub/hard.txt");
+        fs::write(&source, "hard").unwrap();
+
+        let result = hard_link_file_to(&source, &target, &RunOptions::default());
+        assert!(matches!(result, Ok(DistributorResultType::Linked(_, _))));
+        assert!(is_same_file(&source, &target));
+        assert!(matches!(hard_link_file_to(&source, &target, &RunOptions::default()), Ok(Same(_, _))));
+
+        println!("cross device link falls back to copy");
+        let fallback = temp_path.join("sub/fallback.txt");
+        let result = hard_link_file_to_with(&source, &fallback, &RunOptions::default(), |_, _| {
+            Err(std::io::Error::from(std::io::ErrorKind::CrossesDevices))
+        });
+        assert!(matches!(result, Ok(Copied(_, _))));
+        assert!(!is_same_file(&source, &fallback));
+        assert_eq!(fs::read_to_string(&fallback).unwrap(), "hard");
+
+        println!("other link errors are returned");
+        let failed = temp_path.join("sub/failed.txt");
+        let result = hard_link_file_to_with(&source, &failed, &RunOptions::default(), |_, _| {
+            Err(std::io::Error::from(std::io::ErrorKind::PermissionDenied))
+        });
+        assert!(matches!(result, Err(DistributorError::IoError(_))));
+        assert!(!failed.exists());
+
+        println!("existing file is replaced only with force");
+        let existing = temp_path.join("sub/existing.txt");
+        fs::write(&existing, "existing").unwrap();
+        assert!(matches!(hard_link_file_to(&source, &existing, &RunOptions::default()),
+                         Err(DistributorError::TargetExists(_))));
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "existing");
+        let result = hard_link_file_to(&source, &existing, &RunOptions { force: true, ..Default::default() });
+        assert!(matches!(result, Ok(DistributorResultType::Linked(_, _))));
+        assert!(is_same_file(&source, &existing));
+    }
+
+    #[test]
+    fn test_remove_orphan_target_file() {
+        let (_temp_dir, root, target, mut distributor) = new_test_distributor(Verbosity::default());
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::create_dir_all(target.join("sub")).unwrap();
+        fs::write(root.join("sub/kept.txt"), "kept").unwrap();
+        fs::write(target.join("sub/orphan.txt"), "orphan").unwrap();
+        fs::write(target.join("ignored.log"), "ignored").unwrap();
+
+        let item = DistributorItem {
+            name: "test".to_string(),
+            root: root.clone(),
+            ignore: vec!["*.log".to_string()],
+            to: vec![target.clone()],
+            ..Default::default()
+        };
+        distributor.do_copy(&item, &RunOptions { mirror: true, ..Default::default() });
+
+        assert!(target.join("sub/kept.txt").is_file());
+        assert!(!target.join("sub/orphan.txt").exists());
+        assert!(target.join("ignored.log").is_file());
+        assert!(root.join("sub/kept.txt").is_file());
+    }
+
+    #[test]
+    fn test_mirror_with_nested_target() {
+        let (_temp_dir, root, target, mut distributor) = new_test_distributor(Verbosity::default());
+        let inner = target.join("inner");
+        fs::create_dir_all(&inner).unwrap();
+        fs::write(root.join("a.txt"), "a").unwrap();
+        fs::write(target.join("orphan.txt"), "orphan").unwrap();
+        fs::write(inner.join("orphan.txt"), "orphan").unwrap();
+
+        let item = DistributorItem::builder("test", &root).target(&target).target(&inner).build();
+        distributor.do_copy(&item, &RunOptions { mirror: true, ..Default::default() });
+
+        println!("files of the inner target are not orphans of the outer target");
+        assert!(target.join("a.txt").is_file());
+        assert!(inner.join("a.txt").is_file());
+        assert!(!target.join("orphan.txt").exists());
+        assert!(!inner.join("orphan.txt").exists());
+    }
+
+    #[test]
+    fn test_remove_orphan_target_file_prunes_empty_dir() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let root = temp_path.join("resource");
+        let target = temp_path.join("target");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::create_dir_all(target.join("sub")).unwrap();
+        fs::create_dir_all(target.join("gone/deep")).unwrap();
+        fs::write(root.join("sub/kept.txt"), "kept").unwrap();
+        fs::write(target.join("sub/kept.txt"), "kept").unwrap();
+        fs::write(target.join("sub/orphan.txt"), "orphan").unwrap();
+        fs::write(target.join("gone/deep/orphan.txt"), "orphan").unwrap();
+
+        let item = DistributorItem::builder("test", &root).target(&target).build();
+        let results = remove_orphan_target_file(&item, &RunOptions { mirror: true, ..Default::default() });
+
+        assert_eq!(results.len(), 2);
+        assert!(!target.join("gone").exists());
+        assert!(target.join("sub").is_dir());
+
+        println!("the target root is kept even if it becomes empty");
+        fs::remove_dir_all(&root).unwrap();
+        fs::create_dir_all(&root).unwrap();
+        fs::write(target.join("only.txt"), "only").unwrap();
+        fs::remove_dir_all(target.join("sub")).unwrap();
+        remove_orphan_target_file(&item, &RunOptions { mirror: true, ..Default::default() });
+        assert!(target.is_dir());
+        assert!(!target.join("only.txt").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_copy_with_preserve() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let source = temp_path.join("run.sh");
+        let target = temp_path.join("target/run.sh");
+        fs::write(&source, "#!/bin/sh").unwrap();
+        fs::set_permissions(&source, fs::Permissions::from_mode(0o755)).unwrap();
+        set_file_mtime(&source, 1_000_000);
+
+        let result = distribute_file(&source, &target, &RunOptions { preserve: true, ..Default::default() });
+        assert!(matches!(result, Ok(Copied(_, _))));
+
+        let meta = fs::metadata(&target).unwrap();
+        assert_eq!(meta.permissions().mode() & 0o777, 0o755);
+        assert_eq!(meta.modified().unwrap(), fs::metadata(&source).unwrap().modified().unwrap());
+    }
+
+    #[test]
+    fn test_failed_copy_not_cached() {
+        let (temp_dir, root, _, mut distributor) = new_test_distributor(Verbosity::default());
+        let temp_path = temp_dir.path().to_path_buf();
+        let blocker = temp_path.join("blocker");
+        fs::write(root.join("template.txt"), "test").unwrap();
+        fs::write(&blocker, "blocks directory creation").unwrap();
+
+        let item = DistributorItem {
+            name: "test".to_string(),
+            root: root.clone(),
+            to: vec![temp_path.join("good"), blocker.join("bad")],
+            ..Default::default()
+        };
+        let results = distributor.do_copy(&item, &RunOptions::default());
+
+        assert_eq!(results.iter().filter(|result| result.is_err()).count(), 1);
+        assert!(matches!(
+            results.iter().find(|result| result.is_err()),
+            Some(Err(DistributorError::DistributeFailed(_, t, _))) if t == &blocker.join("bad/template.txt")
+        ));
+        assert!(temp_path.join("good/template.txt").is_file());
+        assert!(!distributor.db_cache.is_file_outdated(&root.join("template.txt"), &temp_path.join("good/template.txt")));
+        assert!(distributor.db_cache.is_file_outdated(&root.join("template.txt"), &blocker.join("bad/template.txt")));
+
+        let file_item = DistributorItem {
+            name: "file".to_string(),
+            root: root.join("template.txt"),
+            to: vec![temp_path.join("good"), blocker.join("bad")],
+            ..Default::default()
+        };
+        let results = distributor.do_copy(&file_item, &RunOptions::default());
+        assert_eq!(results.iter().filter(|result| result.is_err()).count(), 1);
+        assert!(distributor.db_cache.is_file_outdated(&root.join("template.txt"), &blocker.join("bad/template.txt")));
+    }
+
+    #[test]
+    fn test_do_copy_fail_fast() {
+        let (temp_dir, root, _, mut distributor) = new_test_distributor(Verbosity::default());
+        let temp_path = temp_dir.path().to_path_buf();
+        let blocker = temp_path.join("blocker");
+        fs::write(root.join("a.txt"), "a").unwrap();
+        fs::write(root.join("b.txt"), "b").unwrap();
+        fs::write(&blocker, "blocks directory creation").unwrap();
+
+        let item = DistributorItem::builder("test", &root)
+            .target(blocker.join("bad"))
+            .target(temp_path.join("good"))
+            .build();
+        let options = RunOptions { fail_fast: true, ..Default::default() };
+        let results = distributor.do_copy(&item, &options);
+
+        assert_eq!(results.len(), 1);
+        assert!(results[0].is_err());
+        assert!(!temp_path.join("good").exists());
+
+        println!("copy continues on error by default");
+        let results = distributor.do_copy(&item, &RunOptions::default());
+        assert_eq!(results.iter().filter(|result| result.is_err()).count(), 2);
+        assert!(temp_path.join("good/a.txt").is_file());
+        assert!(temp_path.join("good/b.txt").is_file());
+    }
+
+    #[test]
+    fn test_cache_independent_of_verbosity() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let root = temp_path.join("resource");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("template.txt"), "test").unwrap();
+        fs::write(root.join("template2.txt"), "test2").unwrap();
+
+        let item = DistributorItem {
+            name: "test".to_string(),
+            root: root.clone(),
+            to: vec![temp_path.join("target")],
+            ..Default::default()
+        };
+
+        let mut caches = vec![];
+        for verbosity in [Verbosity::Quiet, Verbosity::Trace] {
+            let db_cache = FileDistributorCache::load(Some(&temp_path.join(format!("cache-{:?}.db", verbosity))));
+            let mut distributor = Distributor::with_cache(db_cache, Verbosity::default());
+            distributor.do_copy(&item, &RunOptions { force: true, verbosity, ..Default::default() });
+            caches.push((
+                distributor.db_cache.is_file_outdated(&root, &temp_path.join("target")),
+                distributor.db_cache.is_file_outdated(&root.join("template.txt"), &temp_path.join("target/template.txt")),
+            ));
+        }
+
+        assert_eq!(caches[0], caches[1]);
+        assert_eq!(caches[0], (true, false));
+    }
+
+    #[test]
+    fn test_get_item_status() {
+        let (_temp_dir, root, target, mut distributor) = new_test_distributor(Verbosity::default());
+        fs::write(root.join("template.txt"), "test").unwrap();
+        fs::write(root.join("template2.txt"), "test2").unwrap();
+
+        let item = DistributorItem {
+            name: "test".to_string(),
+            root: root.clone(),
+            to: vec![target.clone()],
+            ..Default::default()
+        };
+        distributor.do_copy(&item, &RunOptions::default());
+
+        assert_eq!(
+            get_item_status(&item, &distributor.db_cache, &[]),
+            vec![TargetStatus { target: target.clone(), outdated: vec![], missing: vec![] }],
+        );
+
+        println!("modify a source and remove a target file");
+        fs::write(root.join("template.txt"), "modified").unwrap();
+        set_file_mtime(&root.join("template.txt"), 4_000_000_000);
+        fs::remove_file(target.join("template2.txt")).unwrap();
+
+        assert_eq!(
+            get_item_status(&item, &distributor.db_cache, &[]),
+            vec![TargetStatus {
+                target: target.clone(),
+                outdated: vec![root.join("template.txt")],
+                missing: vec![root.join("template2.txt")],
+            }],
+        );
+    }
+
+    #[test]
+    fn test_verify_item() {
+        let (_temp_dir, root, target, mut distributor) = new_test_distributor(Verbosity::default());
+        fs::write(root.join("a.txt"), "a").unwrap();
+        fs::write(root.join("b.txt"), "b").unwrap();
+        fs::write(root.join("c.txt"), "c").unwrap();
+
+        let item = DistributorItem::builder("test", &root).target(&target).build();
+        distributor.do_copy(&item, &RunOptions::default());
+        assert!(verify_item(&item, &[], None).iter().all(|verification| verification.is_ok()));
+
+        println!("tamper a target file without changing its size, and remove another");
+        fs::write(target.join("a.txt"), "x").unwrap();
+        fs::remove_file(target.join("b.txt")).unwrap();
+        let modified = fs::metadata(target.join("c.txt")).unwrap().modified().unwrap();
+
+        assert_eq!(
+            verify_item(&item, &[], None),
+            vec![TargetVerification {
+                target: target.clone(),
+                mismatched: vec![target.join("a.txt")],
+                missing: vec![target.join("b.txt")],
+            }],
+        );
+        assert!(!target.join("b.txt").exists());
+        assert_eq!(fs::metadata(target.join("c.txt")).unwrap().modified().unwrap(), modified);
+    }
+
+    #[test]
+    fn test_pull_item() {
+        let (temp_dir, root, _, mut distributor) = new_test_distributor(Verbosity::default());
+        let temp_path = temp_dir.path().to_path_buf();
+        let target_1 = temp_path.join("target_1");
+        let target_2 = temp_path.join("target_2");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("a.txt"), "a").unwrap();
+        fs::write(root.join("sub/b.txt"), "b").unwrap();
+
+        let item = DistributorItem::builder("test", &root).target(&target_1).target(&target_2).build();
+        distributor.do_copy(&item, &RunOptions::default());
+
+        println!("pull a changed target file back to source");
+        fs::write(target_1.join("sub/b.txt"), "changed").unwrap();
+        let results = pull_item(&item, &target_1, &RunOptions::default());
+        assert_eq!(results.len(), 1);
+        assert!(matches!(&results[0], Ok(Copied(f, t)) if *f == target_1.join("sub/b.txt") && *t == root.join("sub/b.txt")));
+        assert_eq!(fs::read_to_string(root.join("sub/b.txt")).unwrap(), "changed");
+        assert_eq!(fs::read_to_string(root.join("a.txt")).unwrap(), "a");
+
+        println!("refuse when another target has a different change");
+        fs::write(target_1.join("a.txt"), "from 1").unwrap();
+        fs::write(target_2.join("a.txt"), "from 2").unwrap();
+        let results = pull_item(&item, &target_1, &RunOptions::default());
+        assert!(matches!(&results[..], [Err(DistributorError::PullConflict(..))]));
+        assert_eq!(fs::read_to_string(root.join("a.txt")).unwrap(), "a");
+
+        assert!(matches!(&pull_item(&item, &temp_path.join("other"), &RunOptions::default())[..],
+                         [Err(DistributorError::NotLocalTarget(_))]));
+    }
+
+    fn set_file_mtime(path: &Path, secs: u64) {
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_copy_by_mtime() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let root = temp_path.join("resource");
+        let target = temp_path.join("target");
+        fs::create_dir_all(&root).unwrap();
+        fs::create_dir_all(&target).unwrap();
+
+        fs::write(root.join("newer.txt"), "source newer").unwrap();
+        fs::write(root.join("older.txt"), "source older").unwrap();
+        fs::write(root.join("missing.txt"), "target missing").unwrap();
+        fs::write(target.join("newer.txt"), "stale").unwrap();
+        fs::write(target.join("older.txt"), "kept").unwrap();
+
+        set_file_mtime(&root.join("newer.txt"), 2_000_000);
+        set_file_mtime(&target.join("newer.txt"), 1_000_000);
+        set_file_mtime(&root.join("older.txt"), 1_000_000);
+        set_file_mtime(&target.join("older.txt"), 2_000_000);
+
+        let item = DistributorItem {
+            name: "test".to_string(),
+            root: root.clone(),
+            ignore: vec![],
+            to: vec![target.clone()],
+            ..Default::default()
+        };
+
+        let results = copy_by_mtime(&item, &RunOptions::default());
+        assert_eq!(results.len(), 3);
+
+        assert_eq!(fs::read_to_string(target.join("newer.txt")).unwrap(), "source newer");
+        assert_eq!(fs::read_to_string(target.join("older.txt")).unwrap(), "kept");
+        assert_eq!(fs::read_to_string(target.join("missing.txt")).unwrap(), "target missing");
+    }
+
+    #[test]
+    fn test_compare_file_with_different_size() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let source_path = temp_path.join("large.bin");
+        let target_path = temp_path.join("small.bin");
+        fs::write(&source_path, vec![0u8; 8 * 1024 * 1024]).unwrap();
+        fs::write(&target_path, [0u8; 16]).unwrap();
+
+        assert!(!compare_file(&source_path, &target_path, DEFAULT_BUFFER_SIZE).unwrap());
+        assert!(!compare_file(&target_path, &source_path, DEFAULT_BUFFER_SIZE).unwrap());
+    }
+
     #[test]
     fn lab() {
         println!("{:?}", std::env::current_dir().unwrap());
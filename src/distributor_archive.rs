@@ -0,0 +1,220 @@
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::distributor::{create_temp_file_beside, DistributorError, DistributorResult, DistributorResultType, RunOptions};
+use crate::distributor_config::DistributorItem;
+
+/// 目标是否为 zip 归档。仅当 root 为目录且目标不是已存在的目录时，以 `.zip` 结尾的目标视为归档。
+///
+/// # Param
+///
+/// - `root` - 配置条目的 root。
+/// - `target` - 目标路径。
+pub fn is_archive_target(root: &Path, target: &Path) -> bool {
+    root.is_dir()
+        && !target.is_dir()
+        && target.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("zip"))
+}
+
+/// 将条目的源文件打包至 zip 归档，条目名为源文件相对 root 的路径。
+/// 归档不存在、条目变化或存在较归档更新的源文件时重建归档，否则返回 Same。
+/// 归档先写入临时文件，完成后再替换目标。
+///
+/// # Param
+///
+/// - `config_item` - 配置条目。
+/// - `archive` - 归档路径。
+/// - `options` - 运行选项。
+pub fn archive_item_to(config_item: &DistributorItem,
+                       archive: &Path,
+                       options: &RunOptions) -> Vec<DistributorResult> {
+    let entries = match get_archive_entries(config_item, archive, options) {
+        Ok(entries) => entries,
+        Err(e) => return vec![Err(e)],
+    };
+    let archive_str = archive.to_path_buf();
+
+    if !options.force && !is_archive_outdated(archive, &entries) {
+        return entries.into_iter()
+                      .map(|(source, _)| Ok(DistributorResultType::Same(source.to_path_buf(),
+                                                                         archive_str.clone())))
+                      .collect();
+    }
+
+    if !options.dry_run {
+        if let Err(e) = write_archive(archive, &entries) {
+            return vec![Err(DistributorError::DistributeFailed(config_item.root.clone(),
+                                                               archive.to_path_buf(),
+                                                               Box::new(e)))];
+        }
+    }
+
+    entries.into_iter()
+           .map(|(source, _)| Ok(DistributorResultType::Archived(source.to_path_buf(),
+                                                                  archive_str.clone())))
+           .collect()
+}
+
+/// 获取按源路径排序的源文件与条目名。
+fn get_archive_entries(config_item: &DistributorItem,
+                       archive: &Path,
+                       options: &RunOptions) -> Result<Vec<(PathBuf, String)>, DistributorError> {
+    if config_item.is_point_to_file() {
+        let file_name = config_item.root.file_name().unwrap_or_default().to_string_lossy();
+        let name = config_item.get_target_file_name(archive).unwrap_or(&file_name);
+        return Ok(vec![(config_item.root.clone(), name.to_string())]);
+    }
+
+    let sources = config_item.get_non_root_source_file_with(&options.global_ignore)
+                             .map_err(DistributorError::SourceCollectFailed)?;
+    let mut entries: Vec<(PathBuf, String)> = sources.into_iter()
+                                                     .filter_map(|source| {
+                                                         let name = get_entry_name(config_item, &source)?;
+                                                         Some((source, name))
+                                                     })
+                                                     .collect();
+    entries.sort();
+
+    Ok(entries)
+}
+
+/// 获取源文件相对其所在根目录的条目名，以 `/` 分隔。非 UTF-8 字符将被替换。
+fn get_entry_name(config_item: &DistributorItem, source: &Path) -> Option<String> {
+    let relative = config_item.get_relative_path(source)?;
+    let components: Vec<_> = relative.components()
+                                     .map(|component| component.as_os_str().to_string_lossy())
+                                     .collect();
+
+    Some(components.join("/"))
+}
+
+/// 归档是否需要重建。
+fn is_archive_outdated(archive: &Path, entries: &[(PathBuf, String)]) -> bool {
+    let Ok(archive_modified) = std::fs::metadata(archive).and_then(|meta| meta.modified()) else {
+        return true;
+    };
+    let newer = entries.iter().any(|(source, _)| {
+        std::fs::metadata(source)
+            .and_then(|meta| meta.modified())
+            .map(|modified| modified > archive_modified)
+            .unwrap_or(true)
+    });
+    if newer {
+        return true;
+    }
+
+    let Ok(zip) = File::open(archive).map_err(zip::result::ZipError::from).and_then(ZipArchive::new) else {
+        return true;
+    };
+    let Ok(archived) = zip.file_names().collect::<Result<BTreeSet<_>, _>>() else {
+        return true;
+    };
+    let expected: BTreeSet<_> = entries.iter().map(|(_, name)| name.as_str().into()).collect();
+
+    archived != expected
+}
+
+/// 写入归档，先写入同目录下的临时文件再替换目标。
+fn write_archive(archive: &Path, entries: &[(PathBuf, String)]) -> Result<(), DistributorError> {
+    if let Some(parent) = archive.parent() {
+        if !parent.as_os_str().is_empty() {
+            std::fs::create_dir_all(parent)?;
+        }
+    }
+
+    let (temp_path, file) = create_temp_file_beside(archive)?;
+    let result = write_archive_entries(file, entries).and_then(|_| {
+        std::fs::rename(&temp_path, archive)?;
+        Ok(())
+    });
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+
+    result
+}
+
+fn write_archive_entries(file: File, entries: &[(PathBuf, String)]) -> Result<(), DistributorError> {
+    let mut writer = ZipWriter::new(BufWriter::new(file));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (source, name) in entries {
+        writer.start_file(name.as_str(), options).map_err(std::io::Error::from)?;
+        std::io::copy(&mut BufReader::new(File::open(source)?), &mut writer)?;
+    }
+    writer.finish().map_err(std::io::Error::from)?.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Read;
+
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn test_is_archive_target() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let root = temp_path.join("resource");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a.zip"), "a").unwrap();
+        fs::create_dir_all(temp_path.join("out/release.zip")).unwrap();
+
+        assert!(is_archive_target(&root, &temp_path.join("out/bundle.zip")));
+        assert!(is_archive_target(&root, &temp_path.join("out/bundle.ZIP")));
+        assert!(!is_archive_target(&root, &temp_path.join("out/bundle")));
+
+        println!("an existing directory ending with .zip is not an archive");
+        assert!(!is_archive_target(&root, &temp_path.join("out/release.zip")));
+
+        println!("a file root is copied to a .zip target instead of archived");
+        assert!(!is_archive_target(&root.join("a.zip"), &temp_path.join("out/a.zip")));
+    }
+
+    #[test]
+    fn test_archive_item_to() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let root = temp_path.join("resource");
+        let archive = temp_path.join("out/bundle.zip");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("a.txt"), "a").unwrap();
+        fs::write(root.join("sub/b.txt"), "b").unwrap();
+
+        let item = DistributorItem::builder("test", &root).target(&archive).build();
+        fs::create_dir_all(temp_path.join("out")).unwrap();
+        fs::write(temp_path.join("out/bundle.zip.tmp"), "unrelated").unwrap();
+        let results = archive_item_to(&item, &archive, &RunOptions::default());
+
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|result| matches!(result, Ok(DistributorResultType::Archived(_, _)))));
+        println!("an existing file with the old temp name is kept and no temp file is left");
+        assert_eq!(fs::read_to_string(temp_path.join("out/bundle.zip.tmp")).unwrap(), "unrelated");
+        assert_eq!(fs::read_dir(temp_path.join("out")).unwrap().count(), 2);
+
+        let mut zip = ZipArchive::new(File::open(&archive).unwrap()).unwrap();
+        let mut content = String::new();
+        zip.by_name("sub/b.txt").unwrap().read_to_string(&mut content).unwrap();
+        assert_eq!(content, "b");
+
+        println!("unchanged sources do not rebuild the archive");
+        let results = archive_item_to(&item, &archive, &RunOptions::default());
+        assert!(results.iter().all(|result| matches!(result, Ok(DistributorResultType::Same(_, _)))));
+
+        println!("removed source rebuilds the archive");
+        fs::remove_file(root.join("a.txt")).unwrap();
+        let results = archive_item_to(&item, &archive, &RunOptions::default());
+        assert_eq!(results.len(), 1);
+        assert!(matches!(results[0], Ok(DistributorResultType::Archived(_, _))));
+        assert_eq!(ZipArchive::new(File::open(&archive).unwrap()).unwrap().len(), 1);
+    }
+}
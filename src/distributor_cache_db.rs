@@ -1,9 +1,14 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use xxhash_rust::xxh3::Xxh3;
 
-use crate::distributor::{DistributorResult, DistributorResultType};
+use crate::distributor::{format_rfc3339, DistributorResult, DistributorResultType};
 
 #[derive(Debug)]
 pub enum QueryMetaError {
@@ -18,25 +23,218 @@ impl From<std::io::Error> for QueryMetaError {
 
 static DEFAULT_DB_PATH: &str = ".distributor/distributor_cache.db";
 
+/// bincode 缓存的文件头，其后为 4 字节小端序的格式版本。
+static BINCODE_MAGIC: &[u8; 8] = b"DTBCACHE";
+
+/// bincode 缓存的当前格式版本。
+const BINCODE_VERSION: u32 = 1;
+
 pub type QueryMetaResult<T> = Result<T, QueryMetaError>;
 
+/// # 过期判断策略
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum CacheStrategy {
+    /// 比较文件修改时间。
+    #[default]
+    Mtime,
+    /// 比较文件内容哈希。
+    Hash,
+}
+
+/// # 哈希算法
+/// 内容哈希比较与校验清单所用的算法。
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    /// sha256。
+    Sha256,
+    /// blake3，速度较快。
+    #[default]
+    Blake3,
+    /// 64 位 xxh3，非加密哈希，速度最快。
+    Xxhash,
+}
+
+impl HashAlgorithm {
+    pub fn hasher(self) -> Hasher {
+        match self {
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            HashAlgorithm::Blake3 => Hasher::Blake3(Box::default()),
+            HashAlgorithm::Xxhash => Hasher::Xxhash(Box::default()),
+        }
+    }
+}
+
+/// # 哈希计算器
+/// 以 Write 写入内容，finish 获取十六进制小写的摘要。
+pub enum Hasher {
+    Sha256(Sha256),
+    Blake3(Box<blake3::Hasher>),
+    Xxhash(Box<Xxh3>),
+}
+
+impl Hasher {
+    pub fn finish(self) -> String {
+        match self {
+            Hasher::Sha256(hasher) => hasher.finalize()
+                                            .iter()
+                                            .map(|b| format!("{:02x}", b))
+                                            .collect(),
+            Hasher::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+            Hasher::Xxhash(hasher) => format!("{:016x}", hasher.digest()),
+        }
+    }
+}
+
+impl Write for Hasher {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Hasher::Sha256(hasher) => hasher.update(buf),
+            Hasher::Blake3(hasher) => {
+                hasher.update(buf);
+            }
+            Hasher::Xxhash(hasher) => hasher.update(buf),
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// # 缓存文件格式
+#[derive(Debug, Default, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum CacheFormat {
+    /// 二进制格式。
+    #[default]
+    Bincode,
+    /// 人类易读的 JSON 格式。
+    Json,
+}
+
+impl CacheFormat {
+    /// 由文件扩展名推断格式。未知扩展名返回 None。
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "db" => Some(CacheFormat::Bincode),
+            "json" => Some(CacheFormat::Json),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            CacheFormat::Bincode => "db",
+            CacheFormat::Json => "json",
+        }
+    }
+
+    /// 获取此格式的默认缓存路径。
+    pub fn default_path(&self) -> PathBuf {
+        Path::new(DEFAULT_DB_PATH).with_extension(self.extension())
+    }
+
+    /// 反序列化缓存。bincode 缓存需以文件头开始且版本为当前版本，否则返回 None。
+    /// 旧版本写入的无文件头缓存仅按源文件记录修改时间，无法转换为按目标的记录，同样返回 None。
+    fn deserialize(&self, cache_bytes: &[u8]) -> Option<FileDistributorCache> {
+        match self {
+            CacheFormat::Bincode => {
+                let (version, payload) = cache_bytes.strip_prefix(BINCODE_MAGIC)?.split_first_chunk::<4>()?;
+                match u32::from_le_bytes(*version) {
+                    BINCODE_VERSION => bincode::deserialize(payload).ok(),
+                    _ => None,
+                }
+            }
+            CacheFormat::Json => serde_json::from_slice(cache_bytes).ok(),
+        }
+    }
+
+    fn serialize(&self, cache: &FileDistributorCache) -> std::io::Result<Vec<u8>> {
+        match self {
+            CacheFormat::Bincode => {
+                let payload = bincode::serialize(cache)
+                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+                let mut cache_bytes = BINCODE_MAGIC.to_vec();
+                cache_bytes.extend_from_slice(&BINCODE_VERSION.to_le_bytes());
+                cache_bytes.extend_from_slice(&payload);
+                Ok(cache_bytes)
+            }
+            CacheFormat::Json => serde_json::to_vec_pretty(cache).map_err(std::io::Error::from),
+        }
+    }
+}
+
+/// # 缓存路径模式
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+pub enum CachePathMode {
+    /// 按分发时的路径记录。
+    #[default]
+    Absolute,
+    /// 记录相对 base 的路径，移动目录或共享缓存后仍然有效。
+    Relative,
+}
+
+/// # 分发记录
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+struct DistributeRecord {
+    /// 分发时源文件的记录。
+    source: String,
+    /// 分发后目标文件的记录，无法获取时为空，例如远程目标。
+    target: String,
+}
+
+/// 目标文件路径至分发记录的映射。
+type TargetRecord = HashMap<PathBuf, DistributeRecord>;
+
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct FileDistributorCache {
-    files_touch_time_record: HashMap<PathBuf, String>,
+    /// 源文件路径至各目标的分发时间记录。
+    #[serde(serialize_with = "serialize_utf8_path_map")]
+    files_touch_time_record: HashMap<PathBuf, TargetRecord>,
+
+    /// 源文件路径至各目标的分发时哈希记录。
+    #[serde(serialize_with = "serialize_utf8_path_map")]
+    files_hash_record: HashMap<PathBuf, TargetRecord>,
 
     loaded_path: PathBuf,
+
+    /// 记录中路径的模式。
+    path_mode: CachePathMode,
+
+    /// 相对模式下记录路径的基准目录。
+    #[serde(skip)]
+    base: Option<PathBuf>,
+
+    #[serde(skip)]
+    strategy: CacheStrategy,
+
+    /// Hash 策略下使用的哈希算法。
+    #[serde(skip)]
+    hash_algorithm: HashAlgorithm,
+
+    /// 配置条目名称至其上次成功运行的 RFC 3339 时刻。
+    #[serde(default)]
+    last_run: HashMap<String, String>,
 }
 
 impl FileDistributorCache {
     pub fn load(path: Option<&Path>) -> Self {
         let path = path.unwrap_or(Path::new(DEFAULT_DB_PATH));
         let mut dtb_cache: Self;
-        match std::fs::read_to_string(path) {
-            Ok(cache_str) => {
-                dtb_cache = bincode::deserialize(cache_str.as_bytes()).unwrap_or_default();
+        match std::fs::read(path) {
+            Ok(cache_bytes) => {
+                dtb_cache = CacheFormat::from_path(path)
+                    .unwrap_or_default()
+                    .deserialize(&cache_bytes)
+                    .unwrap_or_else(|| {
+                        tracing::warn!(path = ?path, "cache format is not supported, reset the cache.");
+                        FileDistributorCache::default()
+                    });
             }
             Err(_) => {
-                println!("cached file not exist.");
+                tracing::info!(path = ?path, "cached file not exist.");
                 dtb_cache = FileDistributorCache::default();
             }
         }
@@ -47,7 +245,7 @@ impl FileDistributorCache {
 
     pub fn save(&self, path: Option<&Path>) -> DistributorResult {
         let path = path.unwrap_or(self.loaded_path.as_path());
-        let cache_str = bincode::serialize(self).unwrap();
+        let cache_str = CacheFormat::from_path(path).unwrap_or_default().serialize(self)?;
 
         if let Some(parent) = path.parent() {
             if !parent.exists() {
@@ -55,41 +253,285 @@ impl FileDistributorCache {
             }
         }
 
-        std::fs::write(path, cache_str)?;
+        // 先写入临时文件再替换，中断时原缓存保持完整。
+        let temp_path = get_temp_path(path);
+        let result = File::create(&temp_path).and_then(|mut file| {
+            file.write_all(&cache_str)?;
+            file.sync_all()?;
+            std::fs::rename(&temp_path, path)
+        });
+        if result.is_err() {
+            let _ = std::fs::remove_file(&temp_path);
+        }
+
+        result?;
         Ok(DistributorResultType::Saved)
     }
 
-    pub fn update_file_record(&mut self, file_path: &Path) {
+    pub fn loaded_path(&self) -> &Path {
+        &self.loaded_path
+    }
+
+    pub fn set_strategy(&mut self, strategy: CacheStrategy) {
+        self.strategy = strategy;
+    }
+
+    /// 设置 Hash 策略下使用的哈希算法。以其他算法记录的文件将被视为过期。
+    pub fn set_hash_algorithm(&mut self, algorithm: HashAlgorithm) {
+        self.hash_algorithm = algorithm;
+    }
+
+    /// 以相对 base 的路径记录，已记录的位于 base 下的路径将被转换。
+    /// 不在 base 下的路径仍按原路径记录。
+    ///
+    /// # Param
+    ///
+    /// - `base` - 基准目录，应为绝对路径。
+    pub fn set_relative_base(&mut self, base: &Path) {
+        self.base = Some(base.to_path_buf());
+        if self.path_mode == CachePathMode::Relative {
+            return;
+        }
+
+        self.path_mode = CachePathMode::Relative;
+        for record in [&mut self.files_touch_time_record, &mut self.files_hash_record] {
+            *record = std::mem::take(record)
+                .into_iter()
+                .map(|(file_path, targets)| {
+                    let targets = targets.into_iter()
+                                         .map(|(target_path, target)| (strip_base(base, &target_path), target))
+                                         .collect();
+                    (strip_base(base, &file_path), targets)
+                })
+                .collect();
+        }
+    }
+
+    /// 获取路径在记录中的键。
+    fn get_key(&self, path: &Path) -> PathBuf {
+        match (&self.base, self.path_mode) {
+            (Some(base), CachePathMode::Relative) => strip_base(base, path),
+            _ => path.to_path_buf(),
+        }
+    }
+
+    /// 获取记录中的键对应的路径。
+    fn resolve_key(&self, key: &Path) -> PathBuf {
+        match (&self.base, self.path_mode) {
+            (Some(base), CachePathMode::Relative) => base.join(key),
+            _ => key.to_path_buf(),
+        }
+    }
+
+    /// 记录源文件已分发至目标文件。
+    ///
+    /// # Param
+    ///
+    /// - `file_path` - 源文件路径。
+    /// - `target_path` - 目标文件路径。
+    pub fn update_file_record(&mut self, file_path: &Path, target_path: &Path) {
+        let (file_key, target_key) = (self.get_key(file_path), self.get_key(target_path));
         if let Ok(timestamp) = get_file_last_modified_timestamp(file_path) {
-            self.files_touch_time_record.insert(
-                file_path.to_path_buf(),
-                timestamp.to_string());
+            self.files_touch_time_record
+                .entry(file_key.clone())
+                .or_default()
+                .insert(target_key.clone(), DistributeRecord {
+                    source: timestamp.to_string(),
+                    target: get_file_last_modified_timestamp(target_path)
+                        .map(|timestamp| timestamp.to_string())
+                        .unwrap_or_default(),
+                });
+        }
+
+        if self.strategy == CacheStrategy::Hash {
+            if let Ok(hash) = get_file_hash(file_path, self.hash_algorithm) {
+                self.files_hash_record
+                    .entry(file_key)
+                    .or_default()
+                    .insert(target_key, DistributeRecord {
+                        source: hash,
+                        target: get_file_hash(target_path, self.hash_algorithm).unwrap_or_default(),
+                    });
+            }
         }
     }
 
-    pub fn is_file_outdated(&self, file_path: &Path) -> bool {
-        if let Some(distribute_time) = self.get_file_record(file_path) {
-            if let Ok(last_change) = get_file_last_modified_timestamp(file_path) {
-                return last_change > distribute_time;
+    /// 源文件自上次分发至目标文件后是否发生变化，或目标文件被删除、修改。未分发至该目标时视为过期。
+    ///
+    /// # Param
+    ///
+    /// - `file_path` - 源文件路径。
+    /// - `target_path` - 目标文件路径。
+    pub fn is_file_outdated(&self, file_path: &Path, target_path: &Path) -> bool {
+        if self.strategy == CacheStrategy::Hash {
+            if let Some(record) = self.files_hash_record
+                                      .get(&self.get_key(file_path))
+                                      .and_then(|targets| targets.get(&self.get_key(target_path))) {
+                if let Ok(hash) = get_file_hash(file_path, self.hash_algorithm) {
+                    let current_target = || get_file_hash(target_path, self.hash_algorithm);
+                    return hash != record.source || is_target_changed(&record.target, current_target);
+                }
+            }
+
+            return true;
+        }
+
+        if let Some(record) = self.get_file_record(file_path, target_path) {
+            if let (Ok(distribute_time), Ok(last_change)) = (record.source.parse::<u128>(),
+                                                             get_file_last_modified_timestamp(file_path)) {
+                let current_target = || get_file_last_modified_timestamp(target_path).map(|t| t.to_string());
+                return last_change > distribute_time || is_target_changed(&record.target, current_target);
             }
         }
 
         true
     }
 
+    /// 记录配置条目于 time 成功运行。
+    ///
+    /// # Param
+    ///
+    /// - `name` - 配置条目名称。
+    /// - `time` - 运行完成的时刻。
+    pub fn update_last_run(&mut self, name: &str, time: SystemTime) {
+        self.last_run.insert(name.to_string(), format_rfc3339(time));
+    }
+
+    /// 获取配置条目上次成功运行的 RFC 3339 时刻，未运行过时返回 None。
+    pub fn get_last_run(&self, name: &str) -> Option<&str> {
+        self.last_run.get(name).map(String::as_str)
+    }
+
     pub fn clear(path: Option<&Path>) -> std::io::Result<()> {
         let path = path.unwrap_or(Path::new(DEFAULT_DB_PATH));
         std::fs::remove_file(path)
     }
 
-    fn get_file_record(&self, file_path: &Path) -> Option<u128> {
+    /// 移除 root 下源文件的记录并保存，使其在下次运行时重新分发。
+    ///
+    /// # Param
+    ///
+    /// - `root` - 源文件的根路径，可以指向一个文件。
+    pub fn clear_under(&mut self, root: &Path) -> DistributorResult {
+        let root = self.get_key(root);
+        self.files_touch_time_record.retain(|file_path, _| !file_path.starts_with(&root));
+        self.files_hash_record.retain(|file_path, _| !file_path.starts_with(&root));
+
+        self.save(None)
+    }
+
+    /// 移除已不存在的文件的记录，返回移除的记录数量。
+    pub fn prune(&mut self) -> usize {
+        let count = self.files_touch_time_record.len();
+        let missing: HashSet<PathBuf> = self.files_touch_time_record
+                                            .keys()
+                                            .chain(self.files_hash_record.keys())
+                                            .filter(|file_path| !self.resolve_key(file_path).exists())
+                                            .cloned()
+                                            .collect();
+        self.files_touch_time_record.retain(|file_path, _| !missing.contains(file_path));
+        self.files_hash_record.retain(|file_path, _| !missing.contains(file_path));
+
+        count - self.files_touch_time_record.len()
+    }
+
+    fn get_file_record(&self, file_path: &Path, target_path: &Path) -> Option<&DistributeRecord> {
         self.files_touch_time_record
-            .get(file_path)
-            .map(|t| t.parse().unwrap())
+            .get(&self.get_key(file_path))
+            .and_then(|targets| targets.get(&self.get_key(target_path)))
     }
 
+    /// 缓存是否没有任何分发记录与运行记录。
     pub fn is_empty(&self) -> bool {
-        self.files_touch_time_record.is_empty()
+        self.files_touch_time_record.is_empty() && self.files_hash_record.is_empty() && self.last_run.is_empty()
+    }
+}
+
+/// 序列化路径记录，跳过无法以 UTF-8 表示的路径，这些文件将在下次运行时重新分发。
+fn serialize_utf8_path_map<S: serde::Serializer>(map: &HashMap<PathBuf, TargetRecord>,
+                                                 serializer: S) -> Result<S::Ok, S::Error> {
+    map.iter()
+       .filter_map(|(path, targets)| {
+           let targets: HashMap<_, _> = targets.iter()
+                                               .filter_map(|(target, record)| Some((target.to_str()?, record)))
+                                               .collect();
+           Some((path.to_str()?, targets))
+       })
+       .collect::<HashMap<_, _>>()
+       .serialize(serializer)
+}
+
+/// 获取缓存保存时使用的临时文件路径。
+fn get_temp_path(path: &Path) -> PathBuf {
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".tmp");
+
+    path.with_file_name(temp_name)
+}
+
+/// 获取路径相对 base 的部分，不在 base 下时返回原路径。
+fn strip_base(base: &Path, path: &Path) -> PathBuf {
+    path.strip_prefix(base).unwrap_or(path).to_path_buf()
+}
+
+/// 目标文件是否与分发后的记录不同。记录为空时不检查目标文件。
+///
+/// # Param
+///
+/// - `record` - 分发后目标文件的记录。
+/// - `current` - 获取目标文件当前的记录，目标文件不存在时返回错误。
+fn is_target_changed(record: &str, current: impl FnOnce() -> QueryMetaResult<String>) -> bool {
+    if record.is_empty() {
+        return false;
+    }
+
+    current().map(|current| current != record).unwrap_or(true)
+}
+
+/// # 缓存锁
+/// 以独占创建的锁文件防止多个运行同时写入缓存，释放时删除锁文件。
+#[derive(Debug)]
+pub struct CacheLock {
+    path: PathBuf,
+}
+
+impl CacheLock {
+    /// 获取缓存的锁，锁文件为缓存文件名加 `.lock` 后缀，内容为持有者的进程 id。
+    /// 锁已被持有时返回 `AlreadyExists` 错误。
+    ///
+    /// # Param
+    ///
+    /// - `cache_path` - 缓存路径。
+    pub fn acquire(cache_path: &Path) -> std::io::Result<CacheLock> {
+        let path = Self::get_path(cache_path);
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                std::fs::create_dir_all(parent)?;
+            }
+        }
+
+        let mut file = File::options().write(true).create_new(true).open(&path)?;
+        let _ = write!(file, "{}", std::process::id());
+
+        Ok(CacheLock { path })
+    }
+
+    /// 获取缓存对应的锁文件路径。
+    pub fn get_path(cache_path: &Path) -> PathBuf {
+        let mut lock_name = cache_path.file_name().unwrap_or_default().to_os_string();
+        lock_name.push(".lock");
+
+        cache_path.with_file_name(lock_name)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for CacheLock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
     }
 }
 
@@ -98,8 +540,334 @@ impl FileDistributorCache {
 /// # Param
 ///
 /// - `file_path` - 文件路径.
-fn get_file_last_modified_timestamp(file_path: &Path) -> QueryMetaResult<u128> {
+pub fn get_file_last_modified_timestamp(file_path: &Path) -> QueryMetaResult<u128> {
     let meta = std::fs::metadata(file_path)?;
     let result = meta.modified()?.duration_since(std::time::SystemTime::UNIX_EPOCH);
     Ok(result.map(|d| d.as_millis()).unwrap())
-}
\ No newline at end of file
+}
+/// 获取指定文件内容的哈希.
+///
+/// # Param
+///
+/// - `file_path` - 文件路径.
+/// - `algorithm` - 哈希算法.
+pub fn get_file_hash(file_path: &Path, algorithm: HashAlgorithm) -> QueryMetaResult<String> {
+    let mut file = File::open(file_path)?;
+    let mut hasher = algorithm.hasher();
+    std::io::copy(&mut file, &mut hasher)?;
+
+    Ok(hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::tempdir;
+
+    use super::*;
+
+    fn set_file_mtime(path: &Path, secs: u64) {
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_save_load_cache() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let target = temp_path.join("target.txt");
+        let cache_path = temp_path.join(".distributor/distributor_cache.db");
+
+        let mut cache = FileDistributorCache::default();
+        cache.set_strategy(CacheStrategy::Hash);
+        for i in 0..16 {
+            let file_path = temp_path.join(format!("template-{}.txt", i));
+            fs::write(&file_path, format!("content {}", i)).unwrap();
+            cache.update_file_record(&file_path, &target);
+        }
+        cache.save(Some(&cache_path)).unwrap();
+
+        let loaded = FileDistributorCache::load(Some(&cache_path));
+
+        assert_eq!(loaded.files_touch_time_record.len(), 16);
+        assert!(!loaded.is_file_outdated(&temp_path.join("template-0.txt"), &target));
+        assert_eq!(loaded.files_touch_time_record, cache.files_touch_time_record);
+        assert_eq!(loaded.files_hash_record, cache.files_hash_record);
+        assert_eq!(loaded.loaded_path, cache_path);
+    }
+
+    #[test]
+    fn test_load_cache_version() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let target = temp_path.join("target.txt");
+        let cache_path = temp_path.join("distributor_cache.db");
+        let file_path = temp_path.join("template.txt");
+        fs::write(&file_path, "content").unwrap();
+
+        let mut cache = FileDistributorCache::default();
+        cache.update_file_record(&file_path, &target);
+        cache.save(Some(&cache_path)).unwrap();
+        let saved = fs::read(&cache_path).unwrap();
+        assert!(saved.starts_with(BINCODE_MAGIC));
+
+        let loaded = FileDistributorCache::load(Some(&cache_path));
+        assert_eq!(loaded.files_touch_time_record, cache.files_touch_time_record);
+
+        println!("a cache of the layout before versioning resets the cache");
+        #[derive(Serialize)]
+        struct BaselineCache {
+            files_touch_time_record: HashMap<PathBuf, String>,
+            loaded_path: PathBuf,
+        }
+        let baseline = BaselineCache {
+            files_touch_time_record: HashMap::from([(file_path.clone(), "1700000000000".to_string())]),
+            loaded_path: cache_path.clone(),
+        };
+        fs::write(&cache_path, bincode::serialize(&baseline).unwrap()).unwrap();
+        assert!(FileDistributorCache::load(Some(&cache_path)).is_empty());
+
+        println!("an unknown version resets the cache");
+        let mut unknown = BINCODE_MAGIC.to_vec();
+        unknown.extend_from_slice(&(BINCODE_VERSION + 1).to_le_bytes());
+        unknown.extend_from_slice(&saved[BINCODE_MAGIC.len() + 4..]);
+        fs::write(&cache_path, unknown).unwrap();
+        assert!(FileDistributorCache::load(Some(&cache_path)).is_empty());
+
+        println!("a corrupted cache resets the cache");
+        fs::write(&cache_path, [0xffu8; 3]).unwrap();
+        assert!(FileDistributorCache::load(Some(&cache_path)).is_empty());
+    }
+
+    #[test]
+    fn test_save_load_json_cache() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let target = temp_path.join("target.txt");
+        let cache_path = temp_path.join(".distributor/distributor_cache.json");
+        let file_path = temp_path.join("template.txt");
+        fs::write(&file_path, "content").unwrap();
+
+        let mut cache = FileDistributorCache::default();
+        cache.set_strategy(CacheStrategy::Hash);
+        cache.update_file_record(&file_path, &target);
+        cache.save(Some(&cache_path)).unwrap();
+
+        let json: serde_json::Value = serde_json::from_slice(&fs::read(&cache_path).unwrap()).unwrap();
+        assert!(json["files_touch_time_record"][file_path.to_str().unwrap()].is_object());
+
+        let loaded = FileDistributorCache::load(Some(&cache_path));
+        assert_eq!(loaded.files_touch_time_record, cache.files_touch_time_record);
+        assert_eq!(loaded.files_hash_record, cache.files_hash_record);
+        assert_eq!(CacheFormat::from_path(&cache_path), Some(CacheFormat::Json));
+        assert_eq!(CacheFormat::Json.default_path(), Path::new(".distributor/distributor_cache.json"));
+    }
+
+    #[test]
+    fn test_save_interrupted() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let target = temp_path.join("target.txt");
+        let cache_path = temp_path.join(".distributor/distributor_cache.db");
+        let file_path = temp_path.join("template.txt");
+        fs::write(&file_path, "content").unwrap();
+
+        let mut cache = FileDistributorCache::default();
+        cache.update_file_record(&file_path, &target);
+        cache.save(Some(&cache_path)).unwrap();
+        assert!(!get_temp_path(&cache_path).exists());
+
+        println!("simulate a crash after writing the temp file but before renaming it");
+        fs::write(get_temp_path(&cache_path), "trunc").unwrap();
+
+        let loaded = FileDistributorCache::load(Some(&cache_path));
+        assert_eq!(loaded.files_touch_time_record, cache.files_touch_time_record);
+
+        println!("next save replaces the stale temp file");
+        loaded.save(None).unwrap();
+        assert!(!get_temp_path(&cache_path).exists());
+        assert_eq!(FileDistributorCache::load(Some(&cache_path)).files_touch_time_record, loaded.files_touch_time_record);
+    }
+
+    #[test]
+    fn test_cache_lock() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let cache_path = temp_path.join(".distributor/distributor_cache.db");
+
+        let lock = CacheLock::acquire(&cache_path).unwrap();
+        assert_eq!(lock.path(), temp_path.join(".distributor/distributor_cache.db.lock"));
+        assert_eq!(CacheLock::acquire(&cache_path).unwrap_err().kind(), std::io::ErrorKind::AlreadyExists);
+
+        println!("caches in the same directory use their own locks");
+        let other_lock = CacheLock::acquire(&temp_path.join(".distributor/other_cache.db")).unwrap();
+        assert_eq!(other_lock.path(), temp_path.join(".distributor/other_cache.db.lock"));
+
+        drop(lock);
+        assert!(!temp_path.join(".distributor/distributor_cache.db.lock").exists());
+        assert!(CacheLock::acquire(&cache_path).is_ok());
+    }
+
+    #[test]
+    fn test_clear_under() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let target = temp_path.join("target.txt");
+        let mut cache = FileDistributorCache::load(Some(&temp_path.join(".distributor/distributor_cache.db")));
+        let files = [
+            temp_path.join("resource/a.txt"),
+            temp_path.join("resource/sub/b.txt"),
+            temp_path.join("resource-other/c.txt"),
+            temp_path.join("other/d.txt"),
+        ];
+        for file_path in files.iter() {
+            fs::create_dir_all(file_path.parent().unwrap()).unwrap();
+            fs::write(file_path, "content").unwrap();
+            cache.update_file_record(file_path, &target);
+        }
+
+        cache.clear_under(&temp_path.join("resource")).unwrap();
+
+        assert!(cache.is_file_outdated(&files[0], &target));
+        assert!(cache.is_file_outdated(&files[1], &target));
+        assert!(!cache.is_file_outdated(&files[2], &target));
+        assert!(!cache.is_file_outdated(&files[3], &target));
+
+        let loaded = FileDistributorCache::load(Some(&temp_path.join(".distributor/distributor_cache.db")));
+        assert_eq!(loaded.files_touch_time_record, cache.files_touch_time_record);
+        assert_eq!(loaded.files_touch_time_record.len(), 2);
+    }
+
+    #[test]
+    fn test_prune() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let target = temp_path.join("target.txt");
+        let present = temp_path.join("present.txt");
+        let missing = temp_path.join("missing.txt");
+        fs::write(&present, "content").unwrap();
+        fs::write(&missing, "content").unwrap();
+
+        let mut cache = FileDistributorCache::default();
+        cache.set_strategy(CacheStrategy::Hash);
+        cache.update_file_record(&present, &target);
+        cache.update_file_record(&missing, &target);
+        fs::remove_file(&missing).unwrap();
+
+        assert_eq!(cache.prune(), 1);
+        assert_eq!(cache.files_touch_time_record.keys().collect::<Vec<_>>(), vec![&present]);
+        assert_eq!(cache.files_hash_record.keys().collect::<Vec<_>>(), vec![&present]);
+        assert_eq!(cache.prune(), 0);
+    }
+
+    #[test]
+    fn test_record_per_target() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let file_path = temp_path.join("template.txt");
+        let target_a = temp_path.join("a/template.txt");
+        let target_b = temp_path.join("b/template.txt");
+        fs::write(&file_path, "content").unwrap();
+
+        let mut cache = FileDistributorCache::default();
+        cache.update_file_record(&file_path, &target_a);
+
+        assert!(!cache.is_file_outdated(&file_path, &target_a));
+        assert!(cache.is_file_outdated(&file_path, &target_b));
+
+        cache.update_file_record(&file_path, &target_b);
+        assert!(!cache.is_file_outdated(&file_path, &target_b));
+        assert_eq!(cache.files_touch_time_record.len(), 1);
+    }
+
+    #[test]
+    fn test_relative_base() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let project = temp_path.join("project");
+        let file_path = project.join("resource/template.txt");
+        let outside = temp_path.join("outside.txt");
+        fs::create_dir_all(project.join("resource")).unwrap();
+        fs::create_dir_all(project.join("target")).unwrap();
+        fs::write(&file_path, "content").unwrap();
+        fs::write(project.join("target/template.txt"), "content").unwrap();
+        fs::write(&outside, "content").unwrap();
+
+        let mut cache = FileDistributorCache::default();
+        cache.update_file_record(&outside, &project.join("target/outside.txt"));
+        cache.set_relative_base(&project);
+        cache.update_file_record(&file_path, &project.join("target/template.txt"));
+        cache.save(Some(&project.join(".distributor/distributor_cache.db"))).unwrap();
+        assert!(cache.files_touch_time_record.contains_key(Path::new("resource/template.txt")));
+        assert!(cache.files_touch_time_record.contains_key(&outside));
+
+        println!("move the project directory");
+        let moved = temp_path.join("moved");
+        fs::rename(&project, &moved).unwrap();
+
+        let mut loaded = FileDistributorCache::load(Some(&moved.join(".distributor/distributor_cache.db")));
+        assert_eq!(loaded.path_mode, CachePathMode::Relative);
+        loaded.set_relative_base(&moved);
+        assert!(!loaded.is_file_outdated(&moved.join("resource/template.txt"), &moved.join("target/template.txt")));
+        assert!(loaded.is_file_outdated(&project.join("resource/template.txt"), &project.join("target/template.txt")));
+        assert_eq!(loaded.prune(), 0);
+    }
+
+    #[test]
+    fn test_hash_strategy_ignores_mtime() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let target = temp_path.join("target.txt");
+        let file_path = temp_path.join("template.txt");
+        fs::write(&file_path, "content").unwrap();
+        set_file_mtime(&file_path, 1_000_000);
+
+        let mut cache = FileDistributorCache::default();
+        cache.set_strategy(CacheStrategy::Hash);
+        cache.update_file_record(&file_path, &target);
+        assert!(!cache.is_file_outdated(&file_path, &target));
+
+        println!("rewrite same content with newer mtime");
+        fs::write(&file_path, "content").unwrap();
+        set_file_mtime(&file_path, 2_000_000);
+        assert!(!cache.is_file_outdated(&file_path, &target));
+
+        cache.set_strategy(CacheStrategy::Mtime);
+        assert!(cache.is_file_outdated(&file_path, &target));
+
+        println!("change content");
+        cache.set_strategy(CacheStrategy::Hash);
+        fs::write(&file_path, "changed").unwrap();
+        assert!(cache.is_file_outdated(&file_path, &target));
+    }
+
+    #[test]
+    fn test_get_file_hash() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let file_path = temp_path.join("abc.txt");
+        fs::write(&file_path, "abc").unwrap();
+
+        for (algorithm, expected) in [
+            (HashAlgorithm::Sha256, "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"),
+            (HashAlgorithm::Blake3, "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"),
+            (HashAlgorithm::Xxhash, "78af5f94892f3950"),
+        ] {
+            assert_eq!(get_file_hash(&file_path, algorithm).unwrap(), expected, "{:?}", algorithm);
+        }
+
+        println!("records hashed by another algorithm are outdated");
+        let target = temp_path.join("target.txt");
+        let mut cache = FileDistributorCache::default();
+        cache.set_strategy(CacheStrategy::Hash);
+        cache.update_file_record(&file_path, &target);
+        assert!(!cache.is_file_outdated(&file_path, &target));
+        cache.set_hash_algorithm(HashAlgorithm::Sha256);
+        assert!(cache.is_file_outdated(&file_path, &target));
+    }
+}
@@ -1,71 +1,249 @@
-use std::collections::{HashSet, VecDeque};
+use std::collections::{BTreeMap, HashSet, VecDeque};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::process::Command;
 
-use glob::glob;
 use serde::{Deserialize, Serialize};
+use toml_edit::{ArrayOfTables, DocumentMut, Item, Table, Value};
 
 #[derive(Debug)]
 pub enum DistributorConfigError {
     Existed,
     NotExist,
     InvalidGlob,
+    InvalidKey,
+    /// 键有效但值无法解析或不被接受。
+    InvalidValue,
+    /// 源文件数量超过上限 (数量, 上限)。
+    ExceedMaxFiles(usize, usize),
+    /// 源文件总字节数超过上限 (字节数, 上限)。
+    ExceedMaxTotalBytes(u64, u64),
+    /// 目标路径为 root 或位于 root 内。
+    TargetInsideRoot(PathBuf),
+    /// 多个根目录下存在相同相对路径的文件。
+    RootCollision(PathBuf),
+    /// 配置文件存在但无法解析。
+    ParseFailed(String),
+}
+
+impl std::fmt::Display for DistributorConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DistributorConfigError::Existed => write!(f, "already existed"),
+            DistributorConfigError::NotExist => write!(f, "not exist"),
+            DistributorConfigError::InvalidGlob => write!(f, "invalid glob"),
+            DistributorConfigError::InvalidKey => write!(f, "invalid key"),
+            DistributorConfigError::InvalidValue => write!(f, "invalid value"),
+            DistributorConfigError::ExceedMaxFiles(count, max) => {
+                write!(f, "{} source files exceed max_files {}", count, max)
+            }
+            DistributorConfigError::ExceedMaxTotalBytes(bytes, max) => {
+                write!(f, "{} bytes of source files exceed max_total_bytes {}", bytes, max)
+            }
+            DistributorConfigError::TargetInsideRoot(target) => {
+                write!(f, "target {:?} is the root or inside the root", target)
+            }
+            DistributorConfigError::RootCollision(relative) => {
+                write!(f, "{:?} exists in more than one root", relative)
+            }
+            DistributorConfigError::ParseFailed(message) => {
+                write!(f, "cannot parse config: {}", message)
+            }
+        }
+    }
 }
 
 type DistributorConfigResult = Result<(), DistributorConfigError>;
 
+/// root 下的 ignore 文件名。
+static IGNORE_FILE_NAME: &str = ".distributorignore";
+
 /// # Distributor 配置条目
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub struct DistributorItem {
     /// distributor name
     pub name: String,
 
     /// source path
-    /// 指向一个文件或目录。
+    /// 指向一个文件或目录。含 glob 元字符时于运行时展开，匹配的其他路径视为 roots。
     pub root: PathBuf,
 
+    /// extra source paths
+    /// 与 root 合并分发的其他源目录，文件以相对各自根目录的路径分发。仅在 root 指向目录时生效。
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roots: Vec<PathBuf>,
+
     /// ignore glob
     /// 当 root 指向一个 Directory 时，将会忽略匹配的文件。
     pub ignore: Vec<String>,
 
+    /// ignore file
+    /// 每行一条 ignore glob 的文件，与 ignore 合并，多个条目可引用同一文件。相对路径相对配置文件所在目录。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ignore_file: Option<PathBuf>,
+
+    /// 读取配置时由 ignore_file 解析得到的路径，不被保存。
+    #[serde(skip)]
+    pub resolved_ignore_file: Option<PathBuf>,
+
     /// destination paths
     pub to: Vec<PathBuf>,
+
+    /// destination file names
+    /// 当 root 指向一个 File 时，以 target 为键指定复制后的文件名。
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub rename: BTreeMap<PathBuf, String>,
+
+    /// max source file count
+    /// 源文件数量超过此值时中止分发。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_files: Option<usize>,
+
+    /// max source total bytes
+    /// 源文件总字节数超过此值时中止分发。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_total_bytes: Option<u64>,
+
+    /// max file size
+    /// 跳过大于此字节数的源文件。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_file_size: Option<u64>,
+
+    /// max directory depth
+    /// 遍历 root 的最大目录深度，为 0 时仅包含 root 下的直接文件。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_depth: Option<usize>,
+
+    /// enabled
+    /// 为 false 时 Run 将跳过此条目。
+    #[serde(default = "default_enabled", skip_serializing_if = "is_enabled")]
+    pub enabled: bool,
+
+    /// use gitignore
+    /// 为 true 时 root 下的 .gitignore 规则同样用于忽略文件，嵌套的 .gitignore 作用于其子目录。
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub use_gitignore: bool,
+
+    /// case-insensitive ignore
+    /// 为 true 时 ignore 规则匹配不区分大小写。仅影响 ignore 匹配，不影响路径本身的比较与复制。
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub case_insensitive_ignore: bool,
+
+    /// template variables
+    /// 以 `--template` 运行时替换文本文件中的 `{{key}}`。
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub variables: BTreeMap<String, String>,
+
+    /// template extensions
+    /// 需替换的文件扩展名，为空时使用默认扩展名。
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub template_extensions: Vec<String>,
+
+    /// pre-run command
+    /// 分发前在 root 下执行的命令，以非零状态退出时中止分发。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_run: Option<String>,
+
+    /// tags
+    /// 以 `run --tag` 运行时仅运行带有任一指定标签的条目。
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn is_enabled(enabled: &bool) -> bool {
+    *enabled
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+impl Default for DistributorItem {
+    fn default() -> Self {
+        DistributorItem {
+            name: String::new(),
+            root: PathBuf::new(),
+            roots: vec![],
+            ignore: vec![],
+            ignore_file: None,
+            resolved_ignore_file: None,
+            to: vec![],
+            rename: BTreeMap::new(),
+            max_files: None,
+            max_total_bytes: None,
+            max_file_size: None,
+            max_depth: None,
+            enabled: true,
+            use_gitignore: false,
+            case_insensitive_ignore: false,
+            variables: BTreeMap::new(),
+            template_extensions: vec![],
+            pre_run: None,
+            tags: vec![],
+        }
+    }
 }
 
 impl DistributorItem {
     /// 获取 DistributorItem 所有非根源文件。
     pub fn get_non_root_source_file(&self) -> Result<HashSet<PathBuf>, DistributorConfigError> {
+        self.get_non_root_source_file_with(&[])
+    }
+
+    /// 获取 DistributorItem 所有非根源文件，同时以 global 中的 ignore 过滤。
+    /// 无法读取的目录与目录项被跳过。
+    pub fn get_non_root_source_file_with(&self, global: &[String]) -> Result<HashSet<PathBuf>, DistributorConfigError> {
+        self.get_non_root_source_file_and_warnings(global).map(|(set, _)| set)
+    }
+
+    /// 获取 DistributorItem 所有非根源文件，同时以 global 中的 ignore 过滤。
+    /// 无法读取的目录与目录项被跳过，并作为警告返回。
+    pub fn get_non_root_source_file_and_warnings(&self, global: &[String])
+                                                 -> Result<(HashSet<PathBuf>, Vec<SourceWarning>), DistributorConfigError> {
         let mut set = HashSet::new();
-        let root_clone = self.root.clone();
+        let mut warnings = vec![];
         if self.root.is_file() {
-            return Ok(set);
+            return Ok((set, warnings));
         }
 
-        let mut candidates = VecDeque::new();
-        candidates.push_back(root_clone);
+        let mut candidates: VecDeque<(PathBuf, usize)> = self.get_roots().map(|root| (root.clone(), 0)).collect();
 
-        let ignores = self.ignore.iter()
-                          .map(|pattern| glob(
-                              &format!("{}/**/{}",
-                                       self.root.to_str().unwrap_or_default(),
-                                       pattern))
-                              .map_err(|_| DistributorConfigError::InvalidGlob))
-                          .collect::<Result<Vec<_>, _>>()?
-            .into_iter()
-            .flatten()
-            .map(|p| p.map(|p| p.to_path_buf()).unwrap())
-            .collect::<HashSet<_>>();
+        let ignores = self.get_ignore_globs(global)?;
+        // 经符号链接到达的目录可能是其祖先，已访问的目录将被跳过。
+        let mut visited = HashSet::new();
 
         while !candidates.is_empty() {
-            if let Some(candidate) = candidates.pop_front() {
+            if let Some((candidate, depth)) = candidates.pop_front() {
                 if candidate.is_dir() {
-                    for entry in fs::read_dir(candidate).unwrap() {
-                        let entry = entry.unwrap();
-                        let path = entry.path();
+                    if !visited.insert(candidate.canonicalize().unwrap_or_else(|_| candidate.clone())) {
+                        continue;
+                    }
+
+                    let entries = match fs::read_dir(&candidate) {
+                        Ok(entries) => entries,
+                        Err(error) => {
+                            warnings.push(SourceWarning { path: candidate, error });
+                            continue;
+                        }
+                    };
+                    for entry in entries {
+                        let path = match entry {
+                            Ok(entry) => entry.path(),
+                            Err(error) => {
+                                warnings.push(SourceWarning { path: candidate.clone(), error });
+                                continue;
+                            }
+                        };
 
                         if path.is_dir() {
-                            candidates.push_back(path);
-                        } else if !ignores.contains(path.as_path()) {
+                            if self.max_depth.is_none_or(|max_depth| depth < max_depth) {
+                                candidates.push_back((path, depth + 1));
+                            }
+                        } else if !self.is_matched_by(&ignores, &path) {
                             set.insert(path);
                         }
                     }
@@ -75,286 +253,2605 @@ impl DistributorItem {
             }
         }
 
-        Ok(set)
-    }
+        if self.use_gitignore {
+            let not_git_ignored = self.get_not_git_ignored_file();
+            set.retain(|path| not_git_ignored.contains(path));
+        }
 
-    /// 是否 DistributorItem 根指向单一文件。
-    pub fn is_point_to_file(&self) -> bool {
-        self.root.is_file()
+        if !self.roots.is_empty() {
+            let mut sources: Vec<&PathBuf> = set.iter().collect();
+            sources.sort();
+            let mut relatives = HashSet::new();
+            for source in sources {
+                let relative = self.get_relative_path(source).unwrap_or(source);
+                if !relatives.insert(relative) {
+                    return Err(DistributorConfigError::RootCollision(relative.to_path_buf()));
+                }
+            }
+        }
+
+        Ok((set, warnings))
     }
-}
 
-/// # Distributor 配置
-#[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
-pub struct DistributorConfiguration {
-    items: Vec<DistributorItem>,
-}
+    /// 获取 root 与 roots 下不含任何条目且未被忽略的目录，按路径排序，不包括根目录本身。
+    /// root 指向文件时返回空。
+    pub fn get_empty_source_dir(&self, global: &[String]) -> Vec<PathBuf> {
+        if self.root.is_file() {
+            return vec![];
+        }
 
-impl DistributorConfiguration {
-    pub fn read_from(path: &Path) -> Self {
-        match fs::read_to_string(path) {
-            Ok(config_str) => {
-                return toml::from_str(config_str.as_str()).unwrap_or_default();
+        let ignores = self.get_ignore_globs(global).unwrap_or_default();
+        let mut candidates: VecDeque<(PathBuf, usize)> = self.get_roots().map(|root| (root.clone(), 0)).collect();
+        let mut visited = HashSet::new();
+        let mut empty_dirs = vec![];
+        while let Some((candidate, depth)) = candidates.pop_front() {
+            if !visited.insert(candidate.canonicalize().unwrap_or_else(|_| candidate.clone())) {
+                continue;
             }
-            Err(_) => {
-                println!("config file not exist.");
+            let Ok(entries) = fs::read_dir(&candidate) else {
+                continue;
+            };
+
+            let mut is_empty = true;
+            for entry in entries.flatten() {
+                is_empty = false;
+                let path = entry.path();
+                if path.is_dir() && self.max_depth.is_none_or(|max_depth| depth < max_depth) {
+                    candidates.push_back((path, depth + 1));
+                }
+            }
+            if is_empty && depth > 0 && !self.is_matched_by(&ignores, &candidate) {
+                empty_dirs.push(candidate);
             }
         }
 
-        DistributorConfiguration::default()
+        empty_dirs.sort();
+        empty_dirs
     }
 
-    pub fn add_distributor(&mut self, name: &str, root: &Path) -> DistributorConfigResult {
-        if self.items
-               .iter_mut()
-               .any(|item| item.name == name) {
-            Err(DistributorConfigError::Existed)
-        } else {
-            self.items.push(DistributorItem {
-                name: name.to_string(),
-                root: root.to_path_buf(),
-                ignore: vec![],
-                to: vec![],
-            });
+    /// 获取 root 与 roots 下未被 .gitignore 忽略的文件。
+    fn get_not_git_ignored_file(&self) -> HashSet<PathBuf> {
+        let mut builder = ignore::WalkBuilder::new(&self.root);
+        self.roots.iter().for_each(|root| {
+            builder.add(root);
+        });
+        builder
+            .hidden(false)
+            .ignore(false)
+            .git_global(false)
+            .git_exclude(false)
+            .require_git(false)
+            .build()
+            .flatten()
+            .filter(|entry| entry.file_type().is_some_and(|file_type| file_type.is_file()))
+            .map(|entry| entry.into_path())
+            .collect()
+    }
 
-            Ok(())
+    /// 获取 global、ignore 与 root 下 .distributorignore 中的规则编译得到的 ignore 规则，按此顺序排列。
+    /// 规则以 `**/` 为前缀，匹配 root 下任意深度的路径，包括 root 下的直接文件。以 `/` 开头的规则仅匹配相对 root 的路径。
+    /// 每次调用都将读取 ignore 文件，检查多个路径时应获取一次后以 [`DistributorItem::is_matched_by`] 匹配。
+    /// 含有 `{a,b}` 的规则先被展开为多条规则。
+    pub fn get_ignore_globs(&self, global: &[String]) -> Result<Vec<IgnoreRule>, DistributorConfigError> {
+        self.get_ignore_patterns(global)
+            .iter()
+            .flat_map(|pattern| expand_braces(pattern))
+            .map(|pattern| IgnoreRule::new(&pattern))
+            .collect()
+    }
+
+    /// 获取位于另一 target 内的 target，以 (外层, 内层) 表示。词法规范化后比较，不访问文件系统。
+    pub fn get_nested_targets(&self) -> Vec<(PathBuf, PathBuf)> {
+        let normalized: Vec<PathBuf> = self.to.iter().map(|to| normalize_path(to)).collect();
+        let mut nested = vec![];
+        for (outer, normalized_outer) in self.to.iter().zip(normalized.iter()) {
+            for (inner, normalized_inner) in self.to.iter().zip(normalized.iter()) {
+                if normalized_inner != normalized_outer && normalized_inner.starts_with(normalized_outer) {
+                    nested.push((outer.clone(), inner.clone()));
+                }
+            }
         }
+
+        nested
     }
 
-    pub fn has_distributor(&self, name: &str) -> bool {
-        self.items.iter().any(|item| item.name == name)
+    /// 获取 root 与 roots。root 指向文件时 roots 不生效。
+    pub fn get_roots(&self) -> impl Iterator<Item = &PathBuf> {
+        let roots = if self.is_point_to_file() { &[][..] } else { &self.roots[..] };
+        std::iter::once(&self.root).chain(roots.iter())
     }
 
-    pub fn remove_distributor(&mut self, name: &str) -> DistributorConfigResult {
-        if let Some(index) = self.items.iter()
-                                 .position(|item| item.name == name) {
-            self.items.remove(index);
+    /// 获取路径相对其所在根目录的部分，依次尝试 root 与 roots。不在任何根目录下时返回 None。
+    pub fn get_relative_path<'a>(&self, path: &'a Path) -> Option<&'a Path> {
+        self.get_roots().find_map(|root| path.strip_prefix(root).ok())
+    }
 
-            return Ok(());
-        }
+    /// 路径相对其所在根目录的部分是否被 rules 忽略。不在任何根目录下的路径总是不被忽略。
+    /// 规则按顺序应用，最后一条匹配的规则决定结果。case_insensitive_ignore 时不区分大小写。
+    ///
+    /// # Param
+    ///
+    /// - `rules` - 由 [`DistributorItem::get_ignore_globs`] 获取的规则。
+    /// - `path` - 待检查的路径，无需存在。
+    pub fn is_matched_by(&self, rules: &[IgnoreRule], path: &Path) -> bool {
+        let Some(relative) = self.get_relative_path(path) else {
+            return false;
+        };
 
-        Err(DistributorConfigError::NotExist)
+        let options = glob::MatchOptions {
+            case_sensitive: !self.case_insensitive_ignore,
+            ..glob::MatchOptions::new()
+        };
+        rules.iter()
+             .rev()
+             .find(|rule| rule.pattern.matches_path_with(relative, options))
+             .is_some_and(|rule| !rule.negated)
     }
 
-    pub fn add_ignore(&mut self, name: &str, ignore_glob: &str) -> DistributorConfigResult {
-        if let Some(item) = self.items
-                                .iter_mut()
-                                .find(|item| item.name == name) {
-            if item.ignore.iter().any(|item| item == ignore_glob) {
-                return Err(DistributorConfigError::Existed);
+    /// 路径是否被 ignore、global 或 root 下 .distributorignore 匹配，路径无需存在。
+    /// 每次调用都将读取 ignore 文件。
+    pub fn is_ignored(&self, path: &Path, global: &[String]) -> bool {
+        self.get_ignore_globs(global)
+            .map(|globs| self.is_matched_by(&globs, path))
+            .unwrap_or(false)
+    }
+
+    /// 获取所有 ignore glob，依次为 global、ignore、ignore_file 与 root 下 .distributorignore 中的规则。
+    /// 后面的 `!` 规则可重新包含前面的规则忽略的路径。
+    fn get_ignore_patterns(&self, global: &[String]) -> Vec<String> {
+        let mut patterns: Vec<String> = global.iter().chain(self.ignore.iter()).cloned().collect();
+        if let Some(ignore_file) = self.resolved_ignore_file.as_ref().or(self.ignore_file.as_ref()) {
+            if let Ok(content) = fs::read_to_string(ignore_file) {
+                patterns.extend(parse_ignore_lines(&content));
             }
-            item.ignore.push(ignore_glob.to_string());
+        }
+        patterns.extend(self.get_ignore_file_patterns());
 
-            Ok(())
-        } else {
-            Err(DistributorConfigError::NotExist)
+        patterns
+    }
+
+    /// 读取 root 下的 .distributorignore。文件存在时其自身同样被忽略。
+    fn get_ignore_file_patterns(&self) -> Vec<String> {
+        let Ok(content) = fs::read_to_string(self.root.join(IGNORE_FILE_NAME)) else {
+            return vec![];
+        };
+
+        let mut patterns = vec![IGNORE_FILE_NAME.to_string()];
+        patterns.extend(parse_ignore_lines(&content));
+
+        patterns
+    }
+
+    /// 通过 `git status` 获取 DistributorItem 中已修改或未跟踪的源文件。
+    /// root 或 roots 中任一不在 git 仓库中时返回 None。
+    pub fn get_git_changed_source_file(&self, global: &[String]) -> Option<HashSet<PathBuf>> {
+        let ignores = self.get_ignore_globs(global).ok()?;
+        let mut set = HashSet::new();
+        for root in self.get_roots() {
+            set.extend(self.get_git_changed_file_under(root, &ignores)?);
         }
+
+        Some(set)
     }
 
-    pub fn remove_ignore(&mut self, name: &str, ignore_glob: &str) -> DistributorConfigResult {
-        if let Some(item) = self.items
-                                .iter_mut()
-                                .find(|item| item.name == name) {
-            if let Some(index) = item.ignore.iter().position(|item| item == ignore_glob) {
-                item.ignore.remove(index);
+    /// 通过 `git status` 获取根目录下已修改或未跟踪且未被 ignores 匹配的文件。
+    fn get_git_changed_file_under(&self, root: &Path, ignores: &[IgnoreRule]) -> Option<HashSet<PathBuf>> {
+        let work_dir = if root.is_file() { root.parent()? } else { root };
+        let work_dir = if work_dir.as_os_str().is_empty() { Path::new(".") } else { work_dir };
 
-                return Ok(());
+        let top_level = PathBuf::from(run_git(work_dir, &["rev-parse", "--show-toplevel"])?.trim());
+        let status = run_git(work_dir, &["status", "--porcelain", "-z", "-uall", "--", "."])?;
+        let canonical_root = root.canonicalize().ok()?;
+
+        let mut set = HashSet::new();
+        let mut entries = status.split('\0');
+        while let Some(entry) = entries.next() {
+            if entry.len() < 4 {
+                continue;
+            }
+            let (code, path) = entry.split_at(3);
+            if code.starts_with('R') || code.starts_with('C') {
+                // 重命名与复制条目后跟随原路径。
+                entries.next();
+            }
+
+            let Ok(changed) = top_level.join(path).canonicalize() else { continue; };
+            if let Ok(relative) = changed.strip_prefix(&canonical_root) {
+                let source = if relative.as_os_str().is_empty() {
+                    root.to_path_buf()
+                } else {
+                    root.join(relative)
+                };
+                let depth = relative.components().count().saturating_sub(1);
+                if self.max_depth.is_some_and(|max_depth| depth > max_depth) {
+                    continue;
+                }
+                if source.is_file() && !self.is_matched_by(ignores, &source) {
+                    set.insert(source);
+                }
             }
         }
 
-        Err(DistributorConfigError::NotExist)
+        Some(set)
     }
 
-    pub fn add_target(&mut self, name: &str, target: &Path) -> DistributorConfigResult {
-        if let Some(item) = self.items
-                                .iter_mut()
-                                .find(|item| item.name == name) {
-            if item.to.iter().any(|item| item == target) {
-                return Err(DistributorConfigError::Existed);
+    /// 检查源文件是否超过 max_files 与 max_total_bytes 限制。
+    pub fn check_source_limit(&self, sources: &HashSet<PathBuf>) -> DistributorConfigResult {
+        if let Some(max_files) = self.max_files {
+            if sources.len() > max_files {
+                return Err(DistributorConfigError::ExceedMaxFiles(sources.len(), max_files));
+            }
+        }
+
+        if let Some(max_total_bytes) = self.max_total_bytes {
+            let total_bytes = get_total_size(sources.iter());
+            if total_bytes > max_total_bytes {
+                return Err(DistributorConfigError::ExceedMaxTotalBytes(total_bytes, max_total_bytes));
             }
-            item.to.push(target.to_path_buf());
-        } else {
-            return Err(DistributorConfigError::NotExist);
         }
 
         Ok(())
     }
 
-    pub fn remove_target(&mut self, name: &str, target: &Path) -> DistributorConfigResult {
-        if let Some(item) = self.items
-                                .iter_mut()
-                                .find(|item| item.name == name) {
-            if let Some(index) = item.to.iter().position(|item| item == target) {
-                item.to.remove(index);
+    /// 以缩进的目录树渲染 root 下的文件，未被收集为源文件的文件标记为 `(excluded)`。
+    /// root 指向文件时仅包含该文件。
+    pub fn render_source_tree(&self, global: &[String]) -> Result<String, DistributorConfigError> {
+        if self.is_point_to_file() {
+            return Ok(format!("{}\n", self.root.display()));
+        }
 
-                return Ok(());
-            }
+        let sources = self.get_non_root_source_file_with(global)?;
+        let mut tree = String::new();
+        let mut visited = HashSet::new();
+        for root in self.get_roots() {
+            tree.push_str(&format!("{}/\n", root.display()));
+            render_tree_dir(root, &sources, 1, &mut visited, &mut tree);
         }
 
-        Err(DistributorConfigError::NotExist)
+        Ok(tree)
     }
 
-    pub fn save_to<P: AsRef<Path>>(&self, path: P) {
-        let config_str = toml::to_string(self).unwrap();
-        let path = Path::new(path.as_ref());
-
-        if path.is_file() || path.extension().is_some() {
-            if let Some(path_parent) = path.parent() {
-                if !path_parent.exists() { let _ = fs::create_dir_all(path_parent); }
-            }
-            fs::write(path, config_str).unwrap();
-        } else {
-            if !path.exists() { let _ = fs::create_dir_all(path); }
-            fs::write(path.join("distributor-config.toml"), config_str).unwrap();
+    /// 获取源文件的数量，ignore 与 global 匹配的文件不计入。不读取文件元数据。
+    /// root 指向文件时返回 1。
+    pub fn get_source_count(&self, global: &[String]) -> Result<usize, DistributorConfigError> {
+        if self.is_point_to_file() {
+            return Ok(1);
         }
+
+        Ok(self.get_non_root_source_file_with(global)?.len())
     }
 
-    pub fn iter(&self) -> std::slice::Iter<'_, DistributorItem> {
-        self.items.iter()
+    /// 获取源文件的总字节数，ignore 与 global 匹配的文件不计入。
+    /// root 指向文件时返回该文件的大小。
+    pub fn get_source_size(&self, global: &[String]) -> Result<u64, DistributorConfigError> {
+        if self.is_point_to_file() {
+            return Ok(get_total_size([&self.root]));
+        }
+
+        Ok(get_total_size(self.get_non_root_source_file_with(global)?.iter()))
     }
-}
 
-//region TTD
+    /// 获取指定 target 下的文件名。未指定或文件名无效时返回 None。
+    pub fn get_target_file_name(&self, target: &Path) -> Option<&str> {
+        self.rename
+            .get(target)
+            .map(|name| name.as_str())
+            .filter(|name| is_plain_file_name(name))
+    }
 
-#[cfg(test)]
-mod tests {
-    use std::env;
+    /// 是否带有 tags 中的任一标签。tags 为空时总是返回 true。
+    pub fn has_any_tag(&self, tags: &[String]) -> bool {
+        tags.is_empty() || self.tags.iter().any(|tag| tags.contains(tag))
+    }
 
-    use tempfile::tempdir;
+    /// 是否 DistributorItem 根指向单一文件。
+    pub fn is_point_to_file(&self) -> bool {
+        self.root.is_file()
+    }
 
-    use super::*;
+    /// 以名称与 root 创建 DistributorItem 构建器。
+    pub fn builder(name: &str, root: impl Into<PathBuf>) -> DistributorItemBuilder {
+        DistributorItemBuilder {
+            item: DistributorItem {
+                name: name.to_string(),
+                root: root.into(),
+                ..Default::default()
+            },
+        }
+    }
 
-    #[test]
-    fn test_save_load_config() {
-        let config_save_path = tempdir()
-            .unwrap()
-            .into_path()
-            .join("test-distributor-config.toml");
-        let config = DistributorConfiguration {
-            items: vec![
-                DistributorItem {
-                    name: "test".to_string(),
-                    root: PathBuf::from("resource/template.txt"),
-                    ignore: vec![],
-                    to: vec![PathBuf::from("test-target/config")],
-                },
-            ],
-        };
+    /// 获取 root、target 与 ignore 中环境变量展开后的条目，原条目保持不变。
+    /// root 与 target 开头的 `~` 同时展开为 home 目录。
+    /// root 与 roots 中的 glob 展开为匹配的路径，首个匹配作为 root，其余并入 roots。
+    pub fn expanded(&self) -> DistributorItem {
+        self.expanded_in(&PathEnv::process())
+    }
 
-        config.save_to(&config_save_path);
+    fn expanded_in(&self, env: &PathEnv) -> DistributorItem {
+        let mut roots = std::iter::once(&self.root)
+            .chain(self.roots.iter())
+            .flat_map(|root| expand_glob_path(env.expand_path(root)));
+        DistributorItem {
+            root: roots.next().unwrap_or_default(),
+            roots: roots.collect(),
+            ignore: self.ignore.iter().map(|glob| env.expand_env_vars(glob)).collect(),
+            to: self.to.iter().map(|to| env.expand_path(to)).collect(),
+            rename: self.rename
+                        .iter()
+                        .map(|(to, name)| (env.expand_path(to), name.clone()))
+                        .collect(),
+            ..self.clone()
+        }
+    }
+}
 
-        assert_eq!(
-            fs::read_to_string(&config_save_path).unwrap(),
-            toml::to_string(&config).unwrap(),
-            );
+/// # DistributorItem 构建器
+/// 重复的 ignore 与 target 将被忽略。
+pub struct DistributorItemBuilder {
+    item: DistributorItem,
+}
 
-        let config = DistributorConfiguration::read_from(&config_save_path);
+impl DistributorItemBuilder {
+    /// 添加 ignore glob。
+    pub fn ignore(mut self, ignore_glob: &str) -> Self {
+        if !self.item.ignore.iter().any(|glob| glob == ignore_glob) {
+            self.item.ignore.push(ignore_glob.to_string());
+        }
+        self
+    }
 
-        assert_eq!(
-            config,
-            DistributorConfiguration {
-                items: vec![
-                    DistributorItem {
-                        name: "test".to_string(),
-                        root: PathBuf::from("resource/template.txt"),
-                        ignore: vec![],
-                        to: vec![PathBuf::from("test-target/config")],
-                    },
-                ],
-            }
-        )
+    /// 添加目标路径。
+    pub fn target(mut self, target: impl Into<PathBuf>) -> Self {
+        let target = target.into();
+        if !self.item.to.contains(&target) {
+            self.item.to.push(target);
+        }
+        self
     }
 
-    #[test]
-    fn test_update_config_add() {
-        let mut config = DistributorConfiguration {
-            items: vec![
-                DistributorItem {
-                    name: "test".to_string(),
-                    root: PathBuf::from("resource"),
-                    ignore: vec![],
-                    to: vec![PathBuf::from("test-target/tar1")],
-                },
-            ],
-        };
+    pub fn build(self) -> DistributorItem {
+        self.item
+    }
+}
 
-        println!("add ignore & target to distributor");
-        let _ = config.add_ignore("test", "template.txt");
-        let _ = config.add_target("test", Path::new("test-target/tar2"));
+/// # 路径展开环境
+/// 提供环境变量与 home 目录。默认读取进程环境，测试中可注入固定值而不修改进程环境。
+struct PathEnv<'a> {
+    var: &'a dyn Fn(&str) -> Option<String>,
+    home: Option<PathBuf>,
+}
 
-        assert_eq!(
-            config,
-            DistributorConfiguration {
-                items: vec![
-                    DistributorItem {
-                        name: "test".to_string(),
-                        root: PathBuf::from("resource"),
-                        ignore: vec!["template.txt".to_string()],
-                        to: vec![
-                            PathBuf::new().join("test-target/tar1"),
-                            PathBuf::new().join("test-target/tar2"),
-                        ],
-                    },
-                ],
-            }
-        );
+impl PathEnv<'_> {
+    /// 读取进程环境变量与 home 目录。
+    fn process() -> PathEnv<'static> {
+        PathEnv {
+            var: &|name| std::env::var(name).ok(),
+            home: home::home_dir(),
+        }
+    }
+
+    fn expand_env_vars(&self, value: &str) -> String {
+        let mut expanded = String::with_capacity(value.len());
+        let mut rest = value;
+        while let Some(index) = rest.find(|c| c == '$' || (cfg!(windows) && c == '%')) {
+            expanded.push_str(&rest[..index]);
+            rest = &rest[index..];
+
+            let (name, len) = parse_env_var(rest);
+            match name {
+                Some(name) => match (self.var)(name) {
+                    Some(var) => expanded.push_str(&var),
+                    None => {
+                        tracing::warn!("environment variable {} is not defined.", name);
+                        expanded.push_str(&rest[..len]);
+                    }
+                },
+                None => expanded.push_str(&rest[..len]),
+            }
+            rest = &rest[len..];
+        }
+        expanded.push_str(rest);
+
+        expanded
+    }
+
+    fn expand_home(&self, path: &Path) -> PathBuf {
+        let mut components = path.components();
+        if components.next() != Some(std::path::Component::Normal("~".as_ref())) {
+            return path.to_path_buf();
+        }
+
+        match &self.home {
+            Some(home) => home.join(components.as_path()),
+            None => path.to_path_buf(),
+        }
+    }
+
+    fn expand_env_vars_in_path(&self, path: &Path) -> PathBuf {
+        match path.to_str() {
+            Some(value) if value.contains(['$', '%']) => PathBuf::from(self.expand_env_vars(value)),
+            _ => path.to_path_buf(),
+        }
+    }
+
+    fn expand_path(&self, path: &Path) -> PathBuf {
+        self.expand_home(&self.expand_env_vars_in_path(path))
+    }
+}
+
+/// 展开字符串中的环境变量 `$VAR` 与 `${VAR}`，Windows 下另支持 `%VAR%`。
+/// 未定义的变量保留原文并输出警告。
+pub fn expand_env_vars(value: &str) -> String {
+    PathEnv::process().expand_env_vars(value)
+}
+
+/// 解析以 `$` 或 `%` 开头的环境变量引用，返回变量名与引用的长度。
+/// 不构成引用时变量名为 None。
+fn parse_env_var(value: &str) -> (Option<&str>, usize) {
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+
+    if let Some(braced) = value.strip_prefix("${") {
+        return match braced.find('}') {
+            Some(end) if end > 0 => (Some(&braced[..end]), end + 3),
+            _ => (None, 1),
+        };
+    }
+    if let Some(plain) = value.strip_prefix('$') {
+        let end = plain.find(|c| !is_name_char(c)).unwrap_or(plain.len());
+        return if end > 0 { (Some(&plain[..end]), end + 1) } else { (None, 1) };
+    }
+    if let Some(percent) = value.strip_prefix('%') {
+        return match percent.find('%') {
+            Some(end) if end > 0 && percent[..end].chars().all(is_name_char) => (Some(&percent[..end]), end + 2),
+            _ => (None, 1),
+        };
+    }
+
+    (None, 1)
+}
+
+/// 展开路径中的环境变量与开头的 `~`。
+pub fn expand_path(path: &Path) -> PathBuf {
+    PathEnv::process().expand_path(path)
+}
+
+/// 展开含 glob 元字符的路径为匹配的路径，按路径排序。不含元字符、glob 无效或无匹配时返回原路径。
+fn expand_glob_path(path: PathBuf) -> Vec<PathBuf> {
+    let pattern = path.to_string_lossy();
+    if !pattern.contains(['*', '?', '[']) {
+        return vec![path];
+    }
+
+    let matched: Vec<PathBuf> = glob::glob(&pattern).map(|paths| paths.flatten().collect()).unwrap_or_default();
+    if matched.is_empty() {
+        return vec![path];
+    }
+
+    matched
+}
+
+/// 将作为首个路径组件的 `~` 展开为 home 目录，无法获取 home 目录时保持不变。
+pub fn expand_home(path: &Path) -> PathBuf {
+    PathEnv::process().expand_home(path)
+}
+
+/// 展开路径中的环境变量，非 UTF-8 路径保持不变。
+pub fn expand_env_vars_in_path(path: &Path) -> PathBuf {
+    PathEnv::process().expand_env_vars_in_path(path)
+}
+
+/// 展开 glob 中的 `{a,b}`，如 `*.{png,jpg}` 展开为 `*.png` 与 `*.jpg`，支持嵌套。
+/// 不含逗号的 `{a}`、未闭合的 `{` 与 `[...]` 中的括号按原样保留，不含括号的 glob 保持不变。
+pub fn expand_braces(pattern: &str) -> Vec<String> {
+    let Some((open, close, commas)) = find_brace_group(pattern) else {
+        return vec![pattern.to_string()];
+    };
+
+    let alternatives: Vec<String> = if commas.is_empty() {
+        expand_braces(&pattern[open + 1..close]).into_iter()
+                                                 .map(|inner| format!("{{{}}}", inner))
+                                                 .collect()
+    } else {
+        std::iter::once(open)
+            .chain(commas.iter().copied())
+            .zip(commas.iter().copied().chain(std::iter::once(close)))
+            .flat_map(|(start, end)| expand_braces(&pattern[start + 1..end]))
+            .collect()
+    };
+    let suffixes = expand_braces(&pattern[close + 1..]);
+
+    alternatives.iter()
+                .flat_map(|alternative| {
+                    suffixes.iter().map(move |suffix| format!("{}{}{}", &pattern[..open], alternative, suffix))
+                })
+                .collect()
+}
+
+/// 查找 glob 中首个闭合的 `{...}`，返回 `{` 与 `}` 的位置及其中顶层逗号的位置。
+fn find_brace_group(pattern: &str) -> Option<(usize, usize, Vec<usize>)> {
+    let bytes = pattern.as_bytes();
+    let mut open = None;
+    let mut depth = 0;
+    let mut commas = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'[' => i = find_class_end(bytes, i),
+            b'{' => {
+                if depth == 0 {
+                    open = Some(i);
+                }
+                depth += 1;
+            }
+            b',' if depth == 1 => commas.push(i),
+            b'}' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some((open?, i, commas));
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    None
+}
+
+/// 获取从 start 处的 `[` 开始的字符类的结束位置，未闭合时返回 start。
+fn find_class_end(bytes: &[u8], start: usize) -> usize {
+    let mut i = start + 1;
+    if bytes.get(i) == Some(&b'!') {
+        i += 1;
+    }
+    if bytes.get(i) == Some(&b']') {
+        i += 1;
+    }
+
+    bytes[i.min(bytes.len())..].iter()
+                               .position(|b| *b == b']')
+                               .map_or(start, |end| i + end)
+}
+
+/// # ignore 规则
+/// 以 `!` 开头的规则重新包含之前的规则所忽略的路径，如 `*.log` 之后的 `!keep.log`。
+#[derive(Debug, Clone)]
+pub struct IgnoreRule {
+    pub pattern: glob::Pattern,
+    pub negated: bool,
+}
+
+impl IgnoreRule {
+    /// 编译 ignore 规则，glob 以 `**/` 为前缀。
+    /// 以 `/` 开头的规则锚定于根目录，仅匹配相对根目录的路径，不添加前缀。
+    pub fn new(rule: &str) -> Result<Self, DistributorConfigError> {
+        let (glob, negated) = match rule.strip_prefix('!') {
+            Some(glob) => (glob, true),
+            None => (rule, false),
+        };
+        let glob = match glob.strip_prefix('/') {
+            Some(anchored) => anchored.to_string(),
+            None => format!("**/{}", glob),
+        };
+
+        Ok(IgnoreRule {
+            pattern: glob::Pattern::new(&glob).map_err(|_| DistributorConfigError::InvalidGlob)?,
+            negated,
+        })
+    }
+}
+
+/// # 源文件收集警告
+/// 收集源文件时无法读取而被跳过的目录。
+#[derive(Debug)]
+pub struct SourceWarning {
+    pub path: PathBuf,
+    pub error: std::io::Error,
+}
+
+impl std::fmt::Display for SourceWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "cannot read {:?}, skipped. {}", self.path, self.error)
+    }
+}
+
+/// # 配置检查问题
+#[derive(Debug, PartialEq)]
+pub enum ValidationIssue {
+    /// root 不存在 (name, root)。
+    RootNotExist(String, PathBuf),
+    /// ignore glob 无效 (name, glob)。global ignore 的 name 为空。
+    InvalidGlob(String, String),
+    /// target 与 root 相同 (name, target)。
+    TargetIsRoot(String, PathBuf),
+    /// target 位于 root 内 (name, target)。
+    TargetInsideRoot(String, PathBuf),
+    /// distributor 名称重复 (name)。
+    DuplicateName(String),
+    /// target 位于另一 target 内 (name, outer, inner)。
+    NestedTarget(String, PathBuf, PathBuf),
+}
+
+impl ValidationIssue {
+    /// 是否为错误级别的问题。root 不存在仅为警告，其可能在分发前才被生成。
+    /// target 嵌套仅为警告，文件将被重复复制。
+    pub fn is_error(&self) -> bool {
+        !matches!(self, ValidationIssue::RootNotExist(_, _) | ValidationIssue::NestedTarget(_, _, _))
+    }
+}
+
+impl std::fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValidationIssue::RootNotExist(name, root) => {
+                write!(f, "{}: root {:?} does not exist", name, root)
+            }
+            ValidationIssue::InvalidGlob(name, glob) if name.is_empty() => {
+                write!(f, "global ignore glob {:?} is invalid", glob)
+            }
+            ValidationIssue::InvalidGlob(name, glob) => {
+                write!(f, "{}: ignore glob {:?} is invalid", name, glob)
+            }
+            ValidationIssue::TargetIsRoot(name, target) => {
+                write!(f, "{}: target {:?} is the root", name, target)
+            }
+            ValidationIssue::TargetInsideRoot(name, target) => {
+                write!(f, "{}: target {:?} is inside the root", name, target)
+            }
+            ValidationIssue::DuplicateName(name) => {
+                write!(f, "{}: distributor name is duplicated", name)
+            }
+            ValidationIssue::NestedTarget(name, outer, inner) => {
+                write!(f, "{}: target {:?} is inside target {:?}", name, inner, outer)
+            }
+        }
+    }
+}
+
+/// # 路径关系
+#[derive(Debug, PartialEq)]
+pub enum PathRelation {
+    Same,
+    Inside,
+    Outside,
+}
+
+/// 获取 path 相对于 base 的关系。
+/// 两者均存在时比较规范化路径，否则按词法比较。
+pub fn get_path_relation(base: &Path, path: &Path) -> PathRelation {
+    let (base, path) = match (base.canonicalize(), path.canonicalize()) {
+        (Ok(base), Ok(path)) => (base, path),
+        _ => (normalize_path(base), normalize_path(path)),
+    };
+
+    if path == base {
+        PathRelation::Same
+    } else if path.starts_with(&base) {
+        PathRelation::Inside
+    } else {
+        PathRelation::Outside
+    }
+}
+
+/// 词法规范化路径，去除 `.` 并解析 `..`。
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                if !normalized.pop() {
+                    normalized.push("..");
+                }
+            }
+            other => normalized.push(other),
+        }
+    }
+
+    normalized
+}
+
+/// 解析 ignore 文件内容，跳过空行与 `#` 注释。
+/// 以 `/` 结尾的规则匹配目录下的所有文件，以 `/` 开头的规则保留前缀，锚定于根目录。
+fn parse_ignore_lines(content: &str) -> Vec<String> {
+    let mut patterns = vec![];
+    for line in content.lines().map(|line| line.trim()) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        match line.strip_suffix('/') {
+            Some(dir) => patterns.push(format!("{}/**/*", dir)),
+            None => patterns.push(line.to_string()),
+        }
+    }
+
+    patterns
+}
+
+/// 是否为不含路径分隔符的普通文件名。`.` 与 `..` 不被视为文件名。
+pub fn is_plain_file_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains(['/', '\\'])
+}
+
+/// 解析限制值，空字符串表示取消限制。
+fn parse_limit<T: std::str::FromStr>(value: &str) -> Result<Option<T>, DistributorConfigError> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+
+    value.parse().map(Some).map_err(|_| DistributorConfigError::InvalidValue)
+}
+
+/// 按名称顺序渲染目录下的子目录与文件，已访问的目录不再展开。
+fn render_tree_dir(dir: &Path, sources: &HashSet<PathBuf>, depth: usize, visited: &mut HashSet<PathBuf>, tree: &mut String) {
+    if !visited.insert(dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf())) {
+        return;
+    }
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    let mut paths: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).collect();
+    paths.sort();
+    let indent = "  ".repeat(depth);
+    for path in paths {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if path.is_dir() {
+            tree.push_str(&format!("{}{}/\n", indent, name));
+            render_tree_dir(&path, sources, depth + 1, visited, tree);
+        } else if sources.contains(&path) {
+            tree.push_str(&format!("{}{}\n", indent, name));
+        } else {
+            tree.push_str(&format!("{}{} (excluded)\n", indent, name));
+        }
+    }
+}
+
+/// 获取文件的总字节数，无法读取的文件不计入。
+fn get_total_size<'a>(files: impl IntoIterator<Item = &'a PathBuf>) -> u64 {
+    files.into_iter()
+         .filter_map(|file| fs::metadata(file).ok())
+         .map(|meta| meta.len())
+         .sum()
+}
+
+/// 在 work_dir 下执行 git 命令，失败时返回 None。
+fn run_git(work_dir: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(work_dir)
+        .args(args)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    String::from_utf8(output.stdout).ok()
+}
+
+/// # 配置文件格式
+#[derive(PartialEq, Debug, Default, Clone, Copy)]
+pub enum ConfigFormat {
+    #[default]
+    Toml,
+    Json,
+    Yaml,
+}
+
+impl ConfigFormat {
+    /// 由文件扩展名推断格式。未知扩展名返回 None。
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "toml" => Some(ConfigFormat::Toml),
+            "json" => Some(ConfigFormat::Json),
+            "yaml" | "yml" => Some(ConfigFormat::Yaml),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ConfigFormat::Toml => "toml",
+            ConfigFormat::Json => "json",
+            ConfigFormat::Yaml => "yaml",
+        }
+    }
+
+    fn deserialize(&self, config_str: &str) -> Result<DistributorConfiguration, DistributorConfigError> {
+        match self {
+            ConfigFormat::Toml => toml::from_str(config_str).map_err(|e| e.to_string()),
+            ConfigFormat::Json => serde_json::from_str(config_str).map_err(|e| e.to_string()),
+            ConfigFormat::Yaml => serde_yaml::from_str(config_str).map_err(|e| e.to_string()),
+        }.map_err(DistributorConfigError::ParseFailed)
+    }
+
+    fn serialize(&self, config: &DistributorConfiguration) -> String {
+        match self {
+            ConfigFormat::Toml => match &config.document {
+                Some(document) => {
+                    let new_document: DocumentMut = toml::to_string(config).unwrap().parse().unwrap();
+                    let mut document = document.clone();
+                    merge_table(document.as_table_mut(), new_document.as_table());
+                    document.to_string()
+                }
+                None => toml::to_string(config).unwrap(),
+            },
+            ConfigFormat::Json => serde_json::to_string_pretty(config).unwrap(),
+            ConfigFormat::Yaml => serde_yaml::to_string(config).unwrap(),
+        }
+    }
+}
+
+/// 将新文档的内容合并至原文档，保留原文档中未变化部分的注释与格式。
+/// 原文档中多余的键被移除，新增的键追加至表末尾。
+fn merge_table(table: &mut Table, new: &Table) {
+    table.retain(|key, _| new.contains_key(key));
+    for (key, new_item) in new.iter() {
+        match table.get_mut(key) {
+            Some(item) => merge_item(item, new_item),
+            None => {
+                table.insert(key, fresh_item(new_item));
+            }
+        }
+    }
+}
+
+fn merge_item(item: &mut Item, new: &Item) {
+    match (item, new) {
+        (Item::Table(table), Item::Table(new)) => merge_table(table, new),
+        (Item::ArrayOfTables(array), Item::ArrayOfTables(new)) => merge_array_of_tables(array, new),
+        (Item::Value(value), Item::Value(new)) => merge_value(value, new),
+        (item, new) => *item = fresh_item(new),
+    }
+}
+
+/// 以 name 匹配原有的表，未匹配的表视为新增，重命名的条目不保留原有注释。
+fn merge_array_of_tables(array: &mut ArrayOfTables, new: &ArrayOfTables) {
+    let name = |table: &Table| table.get("name").and_then(Item::as_str).map(str::to_string);
+    let mut old: Vec<Option<Table>> = array.iter().cloned().map(Some).collect();
+    let merged: Vec<Table> = new.iter()
+                                .map(|new_table| {
+                                    let new_name = name(new_table);
+                                    let mut table = old.iter_mut()
+                                                       .find(|table| {
+                                                           table.as_ref()
+                                                                .is_some_and(|table| new_name.is_some() && name(table) == new_name)
+                                                       })
+                                                       .and_then(Option::take)
+                                                       .unwrap_or_default();
+                                    merge_table(&mut table, new_table);
+                                    table
+                                })
+                                .collect();
+
+    array.clear();
+    for table in merged {
+        array.push(table);
+    }
+}
+
+fn merge_value(value: &mut Value, new: &Value) {
+    if is_same_value(value, new) {
+        return;
+    }
+
+    match (value, new) {
+        (Value::Array(array), Value::Array(new)) => {
+            let template = array.iter()
+                                .last()
+                                .map(|last| last.decor().clone())
+                                .filter(|decor| decor.prefix().and_then(|prefix| prefix.as_str()).is_some_and(|prefix| prefix.contains('\n')));
+            let mut old: Vec<Option<Value>> = array.iter().cloned().map(Some).collect();
+
+            array.clear();
+            for new_value in new.iter() {
+                let reused = old.iter_mut()
+                                .find(|value| value.as_ref().is_some_and(|value| is_same_value(value, new_value)))
+                                .and_then(Option::take);
+                match (reused, &template) {
+                    (Some(value), _) => array.push_formatted(value),
+                    (None, Some(decor)) => {
+                        let mut value = new_value.clone();
+                        *value.decor_mut() = decor.clone();
+                        array.push_formatted(value);
+                    }
+                    (None, None) => array.push(new_value.clone()),
+                }
+            }
+        }
+        (value, new) => {
+            let decor = value.decor().clone();
+            *value = new.clone();
+            *value.decor_mut() = decor;
+        }
+    }
+}
+
+/// 新增的表不沿用新文档中的位置，从而紧随其前一个表输出。
+fn fresh_item(new: &Item) -> Item {
+    match new {
+        Item::Table(new) => {
+            let mut table = Table::new();
+            merge_table(&mut table, new);
+            Item::Table(table)
+        }
+        Item::ArrayOfTables(new) => {
+            let mut array = ArrayOfTables::new();
+            merge_array_of_tables(&mut array, new);
+            Item::ArrayOfTables(array)
+        }
+        _ => new.clone(),
+    }
+}
+
+fn is_same_value(value: &Value, other: &Value) -> bool {
+    match (value, other) {
+        (Value::String(value), Value::String(other)) => value.value() == other.value(),
+        (Value::Integer(value), Value::Integer(other)) => value.value() == other.value(),
+        (Value::Float(value), Value::Float(other)) => value.value() == other.value(),
+        (Value::Boolean(value), Value::Boolean(other)) => value.value() == other.value(),
+        (Value::Datetime(value), Value::Datetime(other)) => value.value() == other.value(),
+        (Value::Array(value), Value::Array(other)) => {
+            value.len() == other.len() && value.iter().zip(other.iter()).all(|(value, other)| is_same_value(value, other))
+        }
+        (Value::InlineTable(value), Value::InlineTable(other)) => {
+            value.len() == other.len()
+                && value.iter().all(|(key, value)| other.get(key).is_some_and(|other| is_same_value(value, other)))
+        }
+        _ => false,
+    }
+}
+
+/// 带有注释的示例配置。
+static EXAMPLE_CONFIG: &str = r#"# each [[items]] is a distributor, `distributor run` runs them all.
+[[items]]
+# distributor name, used by other commands.
+name = "example"
+# source file or directory.
+root = "resource"
+# globs of source files to skip.
+ignore = ["*.tmp"]
+# target directories, source files are copied into each of them.
+to = [
+    "target/first",
+    "target/second",
+]
+"#;
+
+/// # Distributor 配置
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct DistributorConfiguration {
+    /// global ignore glob
+    /// 应用于所有 DistributorItem 的 ignore。
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    global_ignore: Vec<String>,
+
+    /// relative cache
+    /// 为 true 时缓存记录相对配置文件所在目录的路径，移动项目目录或共享缓存后仍然有效。
+    #[serde(default, skip_serializing_if = "is_false")]
+    relative_cache: bool,
+
+    /// default target
+    /// 未配置 target 的 DistributorItem 分发至此目录下与其同名的子目录。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    default_target: Option<PathBuf>,
+
+    /// cache path
+    /// 缓存文件路径，格式由扩展名决定。命令行的 --cache 优先。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cache_path: Option<PathBuf>,
+
+    items: Vec<DistributorItem>,
+
+    /// 读取时的文件格式，保存时沿用。
+    #[serde(skip)]
+    format: ConfigFormat,
+
+    /// 读取时的 toml 文档，保存时将修改合并其中，以保留注释与键顺序。
+    #[serde(skip)]
+    document: Option<DocumentMut>,
+}
+
+impl PartialEq for DistributorConfiguration {
+    /// 不比较读取时的 toml 文档。
+    fn eq(&self, other: &Self) -> bool {
+        self.global_ignore == other.global_ignore
+            && self.relative_cache == other.relative_cache
+            && self.default_target == other.default_target
+            && self.cache_path == other.cache_path
+            && self.items == other.items
+            && self.format == other.format
+    }
+}
+
+impl DistributorConfiguration {
+    /// 示例配置，包含一个带有 ignore 与两个 target 的 DistributorItem。
+    /// 以 toml 保存时保留示例中的注释。
+    pub fn example() -> Self {
+        let mut config = ConfigFormat::Toml.deserialize(EXAMPLE_CONFIG).unwrap();
+        config.document = EXAMPLE_CONFIG.parse().ok();
+        config
+    }
+
+    /// 读取配置文件，按扩展名选择 toml json 或 yaml，未知扩展名视为 toml。
+    /// 文件不存在时返回默认配置；文件无法解析时返回 [`DistributorConfigError::ParseFailed`]，
+    /// 避免随后的保存以默认配置覆盖用户的文件。
+    pub fn read_from(path: &Path) -> Result<Self, DistributorConfigError> {
+        let format = ConfigFormat::from_path(path).unwrap_or_default();
+        let mut config = match fs::read_to_string(path) {
+            Ok(config_str) => {
+                let mut config = format.deserialize(config_str.as_str())?;
+                if format == ConfigFormat::Toml {
+                    config.document = config_str.parse().ok();
+                }
+                config
+            }
+            Err(_) => {
+                tracing::warn!(path = ?path, "config file not exist.");
+                DistributorConfiguration::default()
+            }
+        };
+
+        config.format = format;
+        let config_dir = path.parent().unwrap_or(Path::new(""));
+        for item in config.items.iter_mut() {
+            item.resolved_ignore_file = item.ignore_file
+                                            .as_ref()
+                                            .map(|ignore_file| config_dir.join(expand_path(ignore_file)));
+        }
+        Ok(config)
+    }
+
+    pub fn add_distributor(&mut self, name: &str, root: &Path) -> DistributorConfigResult {
+        if self.items
+               .iter_mut()
+               .any(|item| item.name == name) {
+            Err(DistributorConfigError::Existed)
+        } else {
+            self.items.push(DistributorItem {
+                name: name.to_string(),
+                root: root.to_path_buf(),
+                ignore: vec![],
+                to: vec![],
+                ..Default::default()
+            });
+
+            Ok(())
+        }
+    }
+
+    pub fn get_distributor(&self, name: &str) -> Option<&DistributorItem> {
+        self.items.iter().find(|item| item.name == name)
+    }
+
+    pub fn has_distributor(&self, name: &str) -> bool {
+        self.items.iter().any(|item| item.name == name)
+    }
+
+    pub fn remove_distributor(&mut self, name: &str) -> DistributorConfigResult {
+        if let Some(index) = self.items.iter()
+                                 .position(|item| item.name == name) {
+            self.items.remove(index);
+
+            return Ok(());
+        }
+
+        Err(DistributorConfigError::NotExist)
+    }
+
+    pub fn rename_distributor(&mut self, old: &str, new: &str) -> DistributorConfigResult {
+        if self.has_distributor(new) {
+            return Err(DistributorConfigError::Existed);
+        }
+
+        if let Some(item) = self.items
+                                .iter_mut()
+                                .find(|item| item.name == old) {
+            item.name = new.to_string();
+
+            return Ok(());
+        }
+
+        Err(DistributorConfigError::NotExist)
+    }
+
+    /// 以新名称复制一个 distributor，root、ignore 与 target 均保持一致。
+    ///
+    /// # Param
+    ///
+    /// - `from` - 被复制的 distributor 名称。
+    /// - `to` - 新 distributor 名称。
+    pub fn clone_distributor(&mut self, from: &str, to: &str) -> DistributorConfigResult {
+        if self.has_distributor(to) {
+            return Err(DistributorConfigError::Existed);
+        }
+
+        if let Some(item) = self.get_distributor(from) {
+            let cloned = DistributorItem {
+                name: to.to_string(),
+                ..item.clone()
+            };
+            self.items.push(cloned);
+
+            return Ok(());
+        }
+
+        Err(DistributorConfigError::NotExist)
+    }
+
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> DistributorConfigResult {
+        if let Some(item) = self.items
+                                .iter_mut()
+                                .find(|item| item.name == name) {
+            item.enabled = enabled;
+
+            return Ok(());
+        }
+
+        Err(DistributorConfigError::NotExist)
+    }
+
+    pub fn add_tag(&mut self, name: &str, tag: &str) -> DistributorConfigResult {
+        let item = self.items
+                       .iter_mut()
+                       .find(|item| item.name == name)
+                       .ok_or(DistributorConfigError::NotExist)?;
+        if item.tags.iter().any(|item| item == tag) {
+            return Err(DistributorConfigError::Existed);
+        }
+        item.tags.push(tag.to_string());
+
+        Ok(())
+    }
+
+    pub fn remove_tag(&mut self, name: &str, tag: &str) -> DistributorConfigResult {
+        if let Some(item) = self.items
+                                .iter_mut()
+                                .find(|item| item.name == name) {
+            if let Some(index) = item.tags.iter().position(|item| item == tag) {
+                item.tags.remove(index);
+
+                return Ok(());
+            }
+        }
+
+        Err(DistributorConfigError::NotExist)
+    }
+
+    pub fn add_global_ignore(&mut self, ignore_glob: &str) -> DistributorConfigResult {
+        glob::Pattern::new(ignore_glob).map_err(|_| DistributorConfigError::InvalidGlob)?;
+        if self.global_ignore.iter().any(|item| item == ignore_glob) {
+            return Err(DistributorConfigError::Existed);
+        }
+        self.global_ignore.push(ignore_glob.to_string());
+
+        Ok(())
+    }
+
+    pub fn remove_global_ignore(&mut self, ignore_glob: &str) -> DistributorConfigResult {
+        if let Some(index) = self.global_ignore.iter().position(|item| item == ignore_glob) {
+            self.global_ignore.remove(index);
+
+            return Ok(());
+        }
+
+        Err(DistributorConfigError::NotExist)
+    }
+
+    pub fn global_ignore(&self) -> &[String] {
+        &self.global_ignore
+    }
+
+    pub fn relative_cache(&self) -> bool {
+        self.relative_cache
+    }
+
+    pub fn default_target(&self) -> Option<&Path> {
+        self.default_target.as_deref()
+    }
+
+    pub fn cache_path(&self) -> Option<&Path> {
+        self.cache_path.as_deref()
+    }
+
+    pub fn add_ignore(&mut self, name: &str, ignore_glob: &str) -> DistributorConfigResult {
+        if let Some(item) = self.items
+                                .iter_mut()
+                                .find(|item| item.name == name) {
+            if item.ignore.iter().any(|item| item == ignore_glob) {
+                return Err(DistributorConfigError::Existed);
+            }
+            item.ignore.push(ignore_glob.to_string());
+
+            Ok(())
+        } else {
+            Err(DistributorConfigError::NotExist)
+        }
+    }
+
+    /// 依次添加多个 ignore glob，已存在的 glob 将被跳过。
+    pub fn add_ignores(&mut self, name: &str, ignore_globs: &[String]) -> DistributorConfigResult {
+        for ignore_glob in ignore_globs {
+            match self.add_ignore(name, ignore_glob) {
+                Ok(_) | Err(DistributorConfigError::Existed) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn remove_ignore(&mut self, name: &str, ignore_glob: &str) -> DistributorConfigResult {
+        if let Some(item) = self.items
+                                .iter_mut()
+                                .find(|item| item.name == name) {
+            if let Some(index) = item.ignore.iter().position(|item| item == ignore_glob) {
+                item.ignore.remove(index);
+
+                return Ok(());
+            }
+        }
+
+        Err(DistributorConfigError::NotExist)
+    }
+
+    pub fn add_target(&mut self, name: &str, target: &Path) -> DistributorConfigResult {
+        if let Some(item) = self.items
+                                .iter_mut()
+                                .find(|item| item.name == name) {
+            if item.to.iter().any(|item| item == target) {
+                return Err(DistributorConfigError::Existed);
+            }
+            if item.get_roots().any(|root| get_path_relation(root, target) != PathRelation::Outside) {
+                return Err(DistributorConfigError::TargetInsideRoot(target.to_path_buf()));
+            }
+            item.to.push(target.to_path_buf());
+        } else {
+            return Err(DistributorConfigError::NotExist);
+        }
+
+        Ok(())
+    }
+
+    /// 依次添加多个目标路径，已存在的目标将被跳过。
+    /// 遇到其他错误时停止，此前的目标保持已添加。
+    pub fn add_targets(&mut self, name: &str, targets: &[PathBuf]) -> DistributorConfigResult {
+        for target in targets {
+            match self.add_target(name, target) {
+                Ok(_) | Err(DistributorConfigError::Existed) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn has_target(&self, name: &str, target: &Path) -> bool {
+        self.items
+            .iter()
+            .any(|item| item.name == name && item.to.iter().any(|to| to == target))
+    }
+
+    pub fn remove_target(&mut self, name: &str, target: &Path) -> DistributorConfigResult {
+        if let Some(item) = self.items
+                                .iter_mut()
+                                .find(|item| item.name == name) {
+            if let Some(index) = item.to.iter().position(|item| item == target) {
+                item.to.remove(index);
+                item.rename.remove(target);
+
+                return Ok(());
+            }
+        }
+
+        Err(DistributorConfigError::NotExist)
+    }
+
+    /// 将目标路径 from 原位替换为 to，保持其在目标中的顺序，from 的文件名设置同时迁移至 to。
+    pub fn move_target(&mut self, name: &str, from: &Path, to: &Path) -> DistributorConfigResult {
+        let item = self.items
+                       .iter_mut()
+                       .find(|item| item.name == name)
+                       .ok_or(DistributorConfigError::NotExist)?;
+        let index = item.to
+                        .iter()
+                        .position(|item| item == from)
+                        .ok_or(DistributorConfigError::NotExist)?;
+        if item.to.iter().any(|item| item == to) {
+            return Err(DistributorConfigError::Existed);
+        }
+        if item.get_roots().any(|root| get_path_relation(root, to) != PathRelation::Outside) {
+            return Err(DistributorConfigError::TargetInsideRoot(to.to_path_buf()));
+        }
+
+        item.to[index] = to.to_path_buf();
+        if let Some(file_name) = item.rename.remove(from) {
+            item.rename.insert(to.to_path_buf(), file_name);
+        }
+
+        Ok(())
+    }
+
+    pub fn set_target_rename(&mut self, name: &str, target: &Path, file_name: &str) -> DistributorConfigResult {
+        if let Some(item) = self.items
+                                .iter_mut()
+                                .find(|item| item.name == name) {
+            if !item.to.iter().any(|item| item == target) {
+                return Err(DistributorConfigError::NotExist);
+            }
+            if !is_plain_file_name(file_name) {
+                return Err(DistributorConfigError::InvalidValue);
+            }
+            item.rename.insert(target.to_path_buf(), file_name.to_string());
+
+            return Ok(());
+        }
+
+        Err(DistributorConfigError::NotExist)
+    }
+
+    /// 保存配置文件。已知扩展名决定格式，否则沿用读取时的格式。
+    pub fn save_to<P: AsRef<Path>>(&self, path: P) {
+        let path = self.resolve_save_path(path.as_ref());
+        let format = ConfigFormat::from_path(&path).unwrap_or(self.format);
+        let config_str = format.serialize(self);
+
+        if let Some(path_parent) = path.parent() {
+            if !path_parent.as_os_str().is_empty() && !path_parent.exists() {
+                let _ = fs::create_dir_all(path_parent);
+            }
+        }
+        fs::write(path, config_str).unwrap();
+    }
+
+    /// 获取 save_to 实际写入的文件路径。
+    /// path 为目录时，写入其中的 `distributor-config.<ext>`。
+    pub fn resolve_save_path(&self, path: &Path) -> PathBuf {
+        if path.is_file() || path.extension().is_some() {
+            path.to_path_buf()
+        } else {
+            path.join(format!("distributor-config.{}", self.format.extension()))
+        }
+    }
+
+    /// 按 `<name>.<field>` 获取配置值。
+    /// field 可为 `root` `roots` `ignore` `to` `max_files` `max_total_bytes` `max_file_size` `max_depth` `use_gitignore` `case_insensitive_ignore`。
+    pub fn get_value(&self, key: &str) -> Result<Vec<String>, DistributorConfigError> {
+        let (name, field) = key.rsplit_once('.').ok_or(DistributorConfigError::InvalidKey)?;
+        let item = self.items
+                       .iter()
+                       .find(|item| item.name == name)
+                       .ok_or(DistributorConfigError::NotExist)?;
+
+        match field {
+            "root" => Ok(vec![item.root.to_string_lossy().to_string()]),
+            "roots" => Ok(item.roots.iter().map(|p| p.to_string_lossy().to_string()).collect()),
+            "ignore" => Ok(item.ignore.clone()),
+            "to" => Ok(item.to.iter().map(|p| p.to_string_lossy().to_string()).collect()),
+            "max_files" => Ok(item.max_files.iter().map(|v| v.to_string()).collect()),
+            "max_total_bytes" => Ok(item.max_total_bytes.iter().map(|v| v.to_string()).collect()),
+            "max_file_size" => Ok(item.max_file_size.iter().map(|v| v.to_string()).collect()),
+            "max_depth" => Ok(item.max_depth.iter().map(|v| v.to_string()).collect()),
+            "use_gitignore" => Ok(vec![item.use_gitignore.to_string()]),
+            "case_insensitive_ignore" => Ok(vec![item.case_insensitive_ignore.to_string()]),
+            _ => Err(DistributorConfigError::InvalidKey),
+        }
+    }
+
+    /// 按 `<name>.<field>` 设置配置值。
+    /// `root` 与限制值将被替换，`roots` `ignore` 与 `to` 将追加新值。
+    pub fn set_value(&mut self, key: &str, value: &str) -> DistributorConfigResult {
+        let (name, field) = key.rsplit_once('.').ok_or(DistributorConfigError::InvalidKey)?;
+        if !self.has_distributor(name) {
+            return Err(DistributorConfigError::NotExist);
+        }
+
+        match field {
+            "root" => {
+                if value.is_empty() {
+                    return Err(DistributorConfigError::InvalidValue);
+                }
+                if let Some(item) = self.items.iter_mut().find(|item| item.name == name) {
+                    item.root = PathBuf::from(value);
+                }
+                Ok(())
+            }
+            "ignore" => {
+                glob::Pattern::new(value).map_err(|_| DistributorConfigError::InvalidGlob)?;
+                self.add_ignore(name, value)
+            }
+            "to" => {
+                if value.is_empty() {
+                    return Err(DistributorConfigError::InvalidValue);
+                }
+                self.add_target(name, Path::new(value))
+            }
+            "roots" => {
+                let item = self.items
+                               .iter_mut()
+                               .find(|item| item.name == name)
+                               .ok_or(DistributorConfigError::NotExist)?;
+                let root = PathBuf::from(value);
+                if value.is_empty() || root == item.root || item.roots.contains(&root) {
+                    return Err(DistributorConfigError::Existed);
+                }
+                item.roots.push(root);
+                Ok(())
+            }
+            "max_files" | "max_total_bytes" | "max_file_size" | "max_depth" => {
+                let item = self.items
+                               .iter_mut()
+                               .find(|item| item.name == name)
+                               .ok_or(DistributorConfigError::NotExist)?;
+                match field {
+                    "max_files" => item.max_files = parse_limit(value)?,
+                    "max_depth" => item.max_depth = parse_limit(value)?,
+                    "max_file_size" => item.max_file_size = parse_limit(value)?,
+                    _ => item.max_total_bytes = parse_limit(value)?,
+                }
+                Ok(())
+            }
+            "use_gitignore" | "case_insensitive_ignore" => {
+                let flag = value.parse().map_err(|_| DistributorConfigError::InvalidValue)?;
+                if let Some(item) = self.items.iter_mut().find(|item| item.name == name) {
+                    match field {
+                        "use_gitignore" => item.use_gitignore = flag,
+                        _ => item.case_insensitive_ignore = flag,
+                    }
+                }
+                Ok(())
+            }
+            _ => Err(DistributorConfigError::InvalidKey),
+        }
+    }
+
+    /// 检查配置：root 是否存在、ignore glob 是否有效、target 是否为 root 或位于 root 内、名称是否重复。
+    pub fn validate(&self) -> Vec<ValidationIssue> {
+        let mut issues = vec![];
+
+        for glob in self.global_ignore.iter() {
+            if glob::Pattern::new(glob).is_err() {
+                issues.push(ValidationIssue::InvalidGlob(String::new(), glob.clone()));
+            }
+        }
+
+        let mut names = HashSet::new();
+        for item in self.items.iter() {
+            if !names.insert(item.name.as_str()) {
+                issues.push(ValidationIssue::DuplicateName(item.name.clone()));
+            }
+
+            let item = &item.expanded();
+
+            for root in item.get_roots().filter(|root| !root.exists()) {
+                issues.push(ValidationIssue::RootNotExist(item.name.clone(), root.clone()));
+            }
+
+            for glob in item.ignore.iter() {
+                if glob::Pattern::new(glob).is_err() {
+                    issues.push(ValidationIssue::InvalidGlob(item.name.clone(), glob.clone()));
+                }
+            }
+
+            for target in item.to.iter() {
+                let relation = item.get_roots()
+                                   .map(|root| get_path_relation(root, target))
+                                   .find(|relation| *relation != PathRelation::Outside);
+                match relation {
+                    Some(PathRelation::Same) => {
+                        issues.push(ValidationIssue::TargetIsRoot(item.name.clone(), target.clone()));
+                    }
+                    Some(PathRelation::Inside) => {
+                        issues.push(ValidationIssue::TargetInsideRoot(item.name.clone(), target.clone()));
+                    }
+                    _ => {}
+                }
+            }
+
+            for (outer, inner) in item.get_nested_targets() {
+                issues.push(ValidationIssue::NestedTarget(item.name.clone(), outer, inner));
+            }
+        }
+
+        issues
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, DistributorItem> {
+        self.items.iter()
+    }
+
+    /// 获取带有 tags 中任一标签的条目，tags 为空时获取所有条目。
+    pub fn iter_tagged<'a>(&'a self, tags: &'a [String]) -> impl Iterator<Item = &'a DistributorItem> {
+        self.items.iter().filter(move |item| item.has_any_tag(tags))
+    }
+}
+
+//region TTD
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn test_save_load_config() {
+        let config_save_dir = tempdir().unwrap();
+        let config_save_path = config_save_dir.path().join("test-distributor-config.toml");
+        let config = DistributorConfiguration {
+            items: vec![
+                DistributorItem {
+                    name: "test".to_string(),
+                    root: PathBuf::from("resource/template.txt"),
+                    ignore: vec![],
+                    to: vec![PathBuf::from("test-target/config")],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        config.save_to(&config_save_path);
+
+        assert_eq!(
+            fs::read_to_string(&config_save_path).unwrap(),
+            toml::to_string(&config).unwrap(),
+            );
+
+        let config = DistributorConfiguration::read_from(&config_save_path).unwrap();
+
+        assert_eq!(
+            config,
+            DistributorConfiguration {
+                items: vec![
+                    DistributorItem {
+                        name: "test".to_string(),
+                        root: PathBuf::from("resource/template.txt"),
+                        ignore: vec![],
+                        to: vec![PathBuf::from("test-target/config")],
+                        ..Default::default()
+                    },
+                ],
+                ..Default::default()
+            }
+        )
+    }
+
+    #[test]
+    fn test_load_cache_path() {
+        let config_dir = tempdir().unwrap();
+        let config_path = config_dir.path().join("distributor-config.toml");
+        fs::write(&config_path, "cache_path = \"shared/cache.json\"\nitems = []\n").unwrap();
+
+        let config = DistributorConfiguration::read_from(&config_path).unwrap();
+        assert_eq!(config.cache_path(), Some(Path::new("shared/cache.json")));
+        assert_eq!(DistributorConfiguration::default().cache_path(), None);
+    }
+
+    #[test]
+    fn test_save_preserves_toml_comments() {
+        let config_dir = tempdir().unwrap();
+        let config_path = config_dir.path().join("distributor-config.toml");
+        fs::write(&config_path, r#"# shared resources
+relative_cache = true
+
+[[items]]
+# deployed to both sites
+name = "test"
+root = 'resource' # relative to config
+ignore = []
+to = [
+    "target/first",
+]
+"#).unwrap();
+
+        let mut config = DistributorConfiguration::read_from(&config_path).unwrap();
+        config.add_target("test", Path::new("target/second")).unwrap();
+        config.save_to(&config_path);
+
+        assert_eq!(fs::read_to_string(&config_path).unwrap(), r#"# shared resources
+relative_cache = true
+
+[[items]]
+# deployed to both sites
+name = "test"
+root = 'resource' # relative to config
+ignore = []
+to = [
+    "target/first",
+    "target/second",
+]
+"#);
+        let loaded = DistributorConfiguration::read_from(&config_path).unwrap();
+        assert_eq!(loaded, config);
+
+        println!("new and removed distributors");
+        config.add_distributor("other", Path::new("other")).unwrap();
+        config.remove_distributor("test").unwrap();
+        config.save_to(&config_path);
+        let saved = fs::read_to_string(&config_path).unwrap();
+        assert!(saved.starts_with("# shared resources\n"));
+        assert!(!saved.contains("deployed to both sites"));
+        assert_eq!(DistributorConfiguration::read_from(&config_path).unwrap(), config);
+    }
+
+    #[test]
+    fn test_save_load_config_json() {
+        let config_save_dir = tempdir().unwrap();
+        let config_save_path = config_save_dir.path().join("test-distributor-config.json");
+        let config = DistributorConfiguration {
+            items: vec![
+                DistributorItem {
+                    name: "test".to_string(),
+                    root: PathBuf::from("resource/template.txt"),
+                    ignore: vec!["*.md".to_string()],
+                    to: vec![PathBuf::from("test-target/config")],
+                    ..Default::default()
+                },
+            ],
+            format: ConfigFormat::Json,
+            ..Default::default()
+        };
+
+        config.save_to(&config_save_path);
+
+        assert_eq!(
+            fs::read_to_string(&config_save_path).unwrap(),
+            serde_json::to_string_pretty(&config).unwrap(),
+            );
+
+        let loaded = DistributorConfiguration::read_from(&config_save_path).unwrap();
+
+        assert_eq!(loaded, config);
+    }
+
+    #[test]
+    fn test_save_load_config_yaml() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let config = DistributorConfiguration {
+            items: vec![
+                DistributorItem {
+                    name: "test".to_string(),
+                    root: PathBuf::from("resource/template.txt"),
+                    ignore: vec!["*.md".to_string()],
+                    to: vec![PathBuf::from("test-target/config")],
+                    ..Default::default()
+                },
+            ],
+            format: ConfigFormat::Yaml,
+            ..Default::default()
+        };
+
+        for file_name in ["test-distributor-config.yaml", "test-distributor-config.yml"] {
+            let config_save_path = temp_path.join(file_name);
+            config.save_to(&config_save_path);
+
+            assert_eq!(
+                fs::read_to_string(&config_save_path).unwrap(),
+                serde_yaml::to_string(&config).unwrap(),
+                );
+
+            let loaded = DistributorConfiguration::read_from(&config_save_path).unwrap();
+
+            assert_eq!(loaded, config);
+        }
+
+        println!("save to directory keeps loaded format");
+        config.save_to(&temp_path);
+        assert!(temp_path.join("distributor-config.yaml").is_file());
+    }
+
+    #[test]
+    fn test_read_malformed_config() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+
+        println!("malformed yaml is reported and the file is kept");
+        let config_path = temp_path.join("distributor-config.yaml");
+        let malformed = "items:\n  - name: test\n    root: [resource\n";
+        fs::write(&config_path, malformed).unwrap();
+        assert!(matches!(
+            DistributorConfiguration::read_from(&config_path),
+            Err(DistributorConfigError::ParseFailed(_))
+        ));
+        assert_eq!(fs::read_to_string(&config_path).unwrap(), malformed);
+
+        println!("malformed json is reported");
+        let config_path = temp_path.join("distributor-config.json");
+        fs::write(&config_path, "{\"items\": [").unwrap();
+        assert!(matches!(
+            DistributorConfiguration::read_from(&config_path),
+            Err(DistributorConfigError::ParseFailed(_))
+        ));
+
+        println!("missing file reads as default");
+        let config = DistributorConfiguration::read_from(&temp_path.join("missing.yaml")).unwrap();
+        assert!(config.items.is_empty());
+        assert_eq!(config.format, ConfigFormat::Yaml);
+    }
+
+    #[test]
+    fn test_init_example_config() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+
+        let config = DistributorConfiguration::example();
+        let config_path = config.resolve_save_path(&temp_path);
+        config.save_to(&temp_path);
+
+        assert_eq!(config_path, temp_path.join("distributor-config.toml"));
+        let loaded = DistributorConfiguration::read_from(&config_path).unwrap();
+        assert_eq!(loaded, DistributorConfiguration::example());
+        assert_eq!(loaded.items[0].ignore.len(), 1);
+        assert_eq!(loaded.items[0].to.len(), 2);
+        assert_eq!(fs::read_to_string(&config_path).unwrap(), EXAMPLE_CONFIG);
+    }
+
+    #[test]
+    fn test_update_config_add() {
+        let mut config = DistributorConfiguration {
+            items: vec![
+                DistributorItem {
+                    name: "test".to_string(),
+                    root: PathBuf::from("resource"),
+                    ignore: vec![],
+                    to: vec![PathBuf::from("test-target/tar1")],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        println!("add ignore & target to distributor");
+        let _ = config.add_ignore("test", "template.txt");
+        let _ = config.add_target("test", Path::new("test-target/tar2"));
+
+        assert_eq!(
+            config,
+            DistributorConfiguration {
+                items: vec![
+                    DistributorItem {
+                        name: "test".to_string(),
+                        root: PathBuf::from("resource"),
+                        ignore: vec!["template.txt".to_string()],
+                        to: vec![
+                            PathBuf::new().join("test-target/tar1"),
+                            PathBuf::new().join("test-target/tar2"),
+                        ],
+                        ..Default::default()
+                    },
+                ],
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_add_multiple_targets_and_ignores() {
+        let mut config = DistributorConfiguration::default();
+        let _ = config.add_distributor("test", Path::new("resource"));
+        config.add_target("test", Path::new("tar1")).unwrap();
+
+        let targets = [PathBuf::from("tar1"), PathBuf::from("tar2"), PathBuf::from("tar3"), PathBuf::from("tar2")];
+        config.add_targets("test", &targets).unwrap();
+        assert_eq!(config.items[0].to, vec![PathBuf::from("tar1"), PathBuf::from("tar2"), PathBuf::from("tar3")]);
+
+        config.add_ignores("test", &["*.log".to_string(), "*.tmp".to_string(), "*.log".to_string()]).unwrap();
+        assert_eq!(config.items[0].ignore, vec!["*.log", "*.tmp"]);
+
+        println!("invalid target stops adding");
+        let result = config.add_targets("test", &[PathBuf::from("tar4"), PathBuf::from("resource/inner"), PathBuf::from("tar5")]);
+        assert!(matches!(result, Err(DistributorConfigError::TargetInsideRoot(_))));
+        assert_eq!(config.items[0].to.len(), 4);
+        assert!(matches!(config.add_targets("none", &targets), Err(DistributorConfigError::NotExist)));
+    }
+
+    #[test]
+    fn test_update_config_remove() {
+        let mut config = DistributorConfiguration {
+            items: vec![
+                DistributorItem {
+                    name: "test".to_string(),
+                    root: PathBuf::from("resource"),
+                    ignore: vec![
+                        "template.txt".to_string(),
+                        "template2.txt".to_string(),
+                    ],
+                    to: vec![
+                        PathBuf::new().join("test-target/tar1"),
+                        PathBuf::new().join("test-target/tar2"),
+                    ],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        println!("remove from distributor");
+        let _ = config.remove_ignore("test", "template2.txt");
+        let _ = config.remove_target("test", Path::new("test-target/tar2"));
+
+        assert_eq!(
+            config,
+            DistributorConfiguration {
+                items: vec![
+                    DistributorItem {
+                        name: "test".to_string(),
+                        root: PathBuf::from("resource"),
+                        ignore: vec!["template.txt".to_string()],
+                        to: vec![
+                            PathBuf::new().join("test-target/tar1"),
+                        ],
+                        ..Default::default()
+                    },
+                ],
+                ..Default::default()
+            }
+        );
+
+        println!("remove distributor");
+        let _ = config.remove_distributor("test");
+
+        assert_eq!(
+            config,
+            DistributorConfiguration {
+                items: vec![],
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_move_target() {
+        let mut config = DistributorConfiguration::default();
+        let _ = config.add_distributor("test", Path::new("resource"));
+        let targets = [PathBuf::from("tar1"), PathBuf::from("tar2"), PathBuf::from("tar3")];
+        config.add_targets("test", &targets).unwrap();
+        config.set_target_rename("test", Path::new("tar2"), "renamed.txt").unwrap();
+
+        config.move_target("test", Path::new("tar2"), Path::new("moved")).unwrap();
+        assert_eq!(config.items[0].to, vec![PathBuf::from("tar1"), PathBuf::from("moved"), PathBuf::from("tar3")]);
+        assert_eq!(config.items[0].get_target_file_name(Path::new("moved")), Some("renamed.txt"));
+        assert_eq!(config.items[0].get_target_file_name(Path::new("tar2")), None);
+
+        assert!(matches!(config.move_target("none", Path::new("tar1"), Path::new("x")), Err(DistributorConfigError::NotExist)));
+        assert!(matches!(config.move_target("test", Path::new("tar2"), Path::new("x")), Err(DistributorConfigError::NotExist)));
+        assert!(matches!(config.move_target("test", Path::new("tar1"), Path::new("tar3")), Err(DistributorConfigError::Existed)));
+    }
+
+    #[test]
+    fn test_set_target_rename() {
+        let mut config = DistributorConfiguration::default();
+        let _ = config.add_distributor("test", Path::new("resource/template.txt"));
+        config.add_target("test", Path::new("tar1")).unwrap();
+
+        config.set_target_rename("test", Path::new("tar1"), "renamed.txt").unwrap();
+        assert_eq!(config.items[0].get_target_file_name(Path::new("tar1")), Some("renamed.txt"));
+        assert!(matches!(config.set_target_rename("test", Path::new("tar2"), "a.txt"), Err(DistributorConfigError::NotExist)));
+
+        println!("names escaping the target are rejected");
+        for file_name in ["", ".", "..", "../escaped.txt", "sub/a.txt", "sub\\a.txt"] {
+            assert!(matches!(config.set_target_rename("test", Path::new("tar1"), file_name),
+                             Err(DistributorConfigError::InvalidValue)));
+        }
+        assert_eq!(config.items[0].get_target_file_name(Path::new("tar1")), Some("renamed.txt"));
+
+        config.items[0].rename.insert(PathBuf::from("tar1"), "../escaped.txt".to_string());
+        assert_eq!(config.items[0].get_target_file_name(Path::new("tar1")), None);
+    }
+
+    #[test]
+    fn test_rename_distributor() {
+        let mut config = DistributorConfiguration {
+            items: vec![
+                DistributorItem {
+                    name: "test".to_string(),
+                    root: PathBuf::from("resource"),
+                    ignore: vec!["template.txt".to_string()],
+                    to: vec![PathBuf::from("test-target/tar1")],
+                    ..Default::default()
+                },
+                DistributorItem {
+                    name: "other".to_string(),
+                    root: PathBuf::from("resource"),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        assert!(matches!(config.rename_distributor("none", "new"), Err(DistributorConfigError::NotExist)));
+        assert!(matches!(config.rename_distributor("test", "other"), Err(DistributorConfigError::Existed)));
+
+        config.rename_distributor("test", "renamed").unwrap();
+
+        assert!(!config.has_distributor("test"));
+        assert_eq!(
+            config.items[0],
+            DistributorItem {
+                name: "renamed".to_string(),
+                root: PathBuf::from("resource"),
+                ignore: vec!["template.txt".to_string()],
+                to: vec![PathBuf::from("test-target/tar1")],
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn test_clone_distributor() {
+        let mut config = DistributorConfiguration {
+            items: vec![
+                DistributorItem {
+                    name: "test".to_string(),
+                    root: PathBuf::from("resource"),
+                    ignore: vec!["template.txt".to_string()],
+                    to: vec![PathBuf::from("test-target/tar1"), PathBuf::from("test-target/tar2")],
+                    ..Default::default()
+                },
+                DistributorItem {
+                    name: "other".to_string(),
+                    root: PathBuf::from("resource"),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        assert!(matches!(config.clone_distributor("none", "new"), Err(DistributorConfigError::NotExist)));
+        assert!(matches!(config.clone_distributor("test", "other"), Err(DistributorConfigError::Existed)));
+
+        config.clone_distributor("test", "cloned").unwrap();
+
+        let source = config.get_distributor("test").unwrap();
+        let cloned = config.get_distributor("cloned").unwrap();
+        assert_eq!(cloned.name, "cloned");
+        assert_eq!(cloned.root, source.root);
+        assert_eq!(cloned.ignore, source.ignore);
+        assert_eq!(cloned.to, source.to);
+    }
+
+    #[test]
+    fn test_set_enabled() {
+        let mut config = DistributorConfiguration::default();
+        let _ = config.add_distributor("test", Path::new("resource"));
+        assert!(config.items[0].enabled);
+
+        config.set_enabled("test", false).unwrap();
+        assert!(!config.items[0].enabled);
+        assert!(matches!(config.set_enabled("none", false), Err(DistributorConfigError::NotExist)));
+
+        println!("enabled is omitted when true and defaults to true");
+        let config_str = toml::to_string(&config).unwrap();
+        assert!(config_str.contains("enabled = false"));
+        config.set_enabled("test", true).unwrap();
+        let config_str = toml::to_string(&config).unwrap();
+        assert!(!config_str.contains("enabled"));
+
+        let loaded: DistributorConfiguration = toml::from_str(&config_str).unwrap();
+        assert!(loaded.items[0].enabled);
+    }
+
+    #[test]
+    fn test_tags() {
+        let mut config = DistributorConfiguration::default();
+        let _ = config.add_distributor("web", Path::new("web"));
+        let _ = config.add_distributor("app", Path::new("app"));
+        let _ = config.add_distributor("data", Path::new("data"));
+        config.add_tag("web", "frontend").unwrap();
+        config.add_tag("app", "frontend").unwrap();
+        config.add_tag("app", "mobile").unwrap();
+        assert!(matches!(config.add_tag("web", "frontend"), Err(DistributorConfigError::Existed)));
+        assert!(matches!(config.add_tag("none", "frontend"), Err(DistributorConfigError::NotExist)));
+
+        let names = |tags: &[String]| config.iter_tagged(tags).map(|item| item.name.clone()).collect::<Vec<_>>();
+        assert_eq!(names(&["frontend".to_string()]), vec!["web", "app"]);
+        assert_eq!(names(&["mobile".to_string(), "none".to_string()]), vec!["app"]);
+        assert_eq!(names(&[]), vec!["web", "app", "data"]);
+
+        config.remove_tag("app", "frontend").unwrap();
+        assert!(matches!(config.remove_tag("app", "frontend"), Err(DistributorConfigError::NotExist)));
+        assert_eq!(config.iter_tagged(&["frontend".to_string()]).count(), 1);
+
+        let loaded: DistributorConfiguration = toml::from_str(&toml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(loaded.items[1].tags, vec!["mobile"]);
+        assert!(loaded.items[2].tags.is_empty());
     }
 
     #[test]
-    fn test_update_config_remove() {
+    fn test_config_get_set() {
         let mut config = DistributorConfiguration {
             items: vec![
                 DistributorItem {
                     name: "test".to_string(),
                     root: PathBuf::from("resource"),
-                    ignore: vec![
-                        "template.txt".to_string(),
-                        "template2.txt".to_string(),
-                    ],
-                    to: vec![
-                        PathBuf::new().join("test-target/tar1"),
-                        PathBuf::new().join("test-target/tar2"),
-                    ],
+                    ignore: vec!["template.txt".to_string()],
+                    to: vec![PathBuf::from("test-target/tar1")],
+                    ..Default::default()
                 },
             ],
+            ..Default::default()
         };
 
-        println!("remove from distributor");
-        let _ = config.remove_ignore("test", "template2.txt");
-        let _ = config.remove_target("test", Path::new("test-target/tar2"));
+        assert_eq!(config.get_value("test.root").unwrap(), vec!["resource".to_string()]);
+        assert!(matches!(config.get_value("test.unknown"), Err(DistributorConfigError::InvalidKey)));
+        assert!(matches!(config.get_value("none.root"), Err(DistributorConfigError::NotExist)));
 
+        config.set_value("test.root", "resource/sub-resource-dir-a").unwrap();
         assert_eq!(
-            config,
-            DistributorConfiguration {
-                items: vec![
-                    DistributorItem {
-                        name: "test".to_string(),
-                        root: PathBuf::from("resource"),
-                        ignore: vec!["template.txt".to_string()],
-                        to: vec![
-                            PathBuf::new().join("test-target/tar1"),
-                        ],
-                    },
-                ],
-            }
+            config.get_value("test.root").unwrap(),
+            vec!["resource/sub-resource-dir-a".to_string()],
         );
 
-        println!("remove distributor");
-        let _ = config.remove_distributor("test");
+        config.set_value("test.ignore", "*.md").unwrap();
+        assert_eq!(
+            config.get_value("test.ignore").unwrap(),
+            vec!["template.txt".to_string(), "*.md".to_string()],
+        );
+        assert!(matches!(config.set_value("test.ignore", "*.md"), Err(DistributorConfigError::Existed)));
+        assert!(matches!(config.set_value("test.ignore", "[*"), Err(DistributorConfigError::InvalidGlob)));
 
+        config.set_value("test.to", "test-target/tar2").unwrap();
         assert_eq!(
-            config,
-            DistributorConfiguration {
-                items: vec![],
-            }
+            config.get_value("test.to").unwrap(),
+            vec!["test-target/tar1".to_string(), "test-target/tar2".to_string()],
+        );
+        assert!(matches!(config.set_value("test.to", "test-target/tar2"), Err(DistributorConfigError::Existed)));
+
+        println!("valid key with invalid value");
+        assert!(matches!(config.set_value("test.root", ""), Err(DistributorConfigError::InvalidValue)));
+        assert!(matches!(config.set_value("test.to", ""), Err(DistributorConfigError::InvalidValue)));
+        assert!(matches!(config.set_value("test.max_files", "many"), Err(DistributorConfigError::InvalidValue)));
+        assert!(matches!(config.set_value("test.use_gitignore", "yes"), Err(DistributorConfigError::InvalidValue)));
+        assert!(matches!(config.set_value("test.unknown", "1"), Err(DistributorConfigError::InvalidKey)));
+    }
+
+    #[test]
+    fn test_get_git_changed_source() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let git = |args: &[&str]| {
+            Command::new("git")
+                .arg("-C")
+                .arg(&temp_path)
+                .args(["-c", "user.name=test", "-c", "user.email=test@test"])
+                .args(args)
+                .output()
+                .unwrap()
+        };
+
+        let root = temp_path.join("resource");
+        let _ = fs::create_dir(&root);
+        let _ = fs::write(root.join("template.txt"), "test1");
+        let _ = fs::write(root.join("template2.txt"), "test2");
+
+        git(&["init"]);
+        git(&["add", "."]);
+        git(&["commit", "-m", "init"]);
+
+        let _ = fs::write(root.join("template2.txt"), "modified");
+
+        let item = DistributorItem {
+            name: "test".to_string(),
+            root: root.clone(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            item.get_git_changed_source_file(&[]).unwrap(),
+            HashSet::from([root.join("template2.txt")]),
+        );
+
+        let not_repo_dir = tempdir().unwrap();
+        let not_repo = DistributorItem {
+            name: "test".to_string(),
+            root: not_repo_dir.path().to_path_buf(),
+            ..Default::default()
+        };
+        assert!(not_repo.get_git_changed_source_file(&[]).is_none());
+    }
+
+    #[test]
+    fn test_check_source_limit() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+
+        let root = temp_path.join("resource");
+        let _ = fs::create_dir(&root);
+        let _ = fs::write(root.join("template.txt"), "test1");
+        let _ = fs::write(root.join("template2.txt"), "test2");
+
+        let mut item = DistributorItem {
+            name: "test".to_string(),
+            root: root.clone(),
+            ..Default::default()
+        };
+        let sources = item.get_non_root_source_file().unwrap();
+        assert!(item.check_source_limit(&sources).is_ok());
+
+        item.max_files = Some(1);
+        assert!(matches!(
+            item.check_source_limit(&sources),
+            Err(DistributorConfigError::ExceedMaxFiles(2, 1))
+        ));
+
+        item.max_files = Some(2);
+        item.max_total_bytes = Some(9);
+        assert!(matches!(
+            item.check_source_limit(&sources),
+            Err(DistributorConfigError::ExceedMaxTotalBytes(10, 9))
+        ));
+
+        item.max_total_bytes = Some(10);
+        assert!(item.check_source_limit(&sources).is_ok());
+    }
+
+    #[test]
+    fn test_global_ignore() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+
+        let mut config = DistributorConfiguration::default();
+        for name in ["a", "b"] {
+            let root = temp_path.join(name);
+            let _ = fs::create_dir(&root);
+            let _ = fs::write(root.join("template.txt"), "test");
+            let _ = fs::write(root.join("Thumbs.db"), "thumbs");
+            let _ = config.add_distributor(name, &root);
+        }
+
+        config.add_global_ignore("Thumbs.db").unwrap();
+        assert!(matches!(config.add_global_ignore("Thumbs.db"), Err(DistributorConfigError::Existed)));
+
+        for item in config.iter() {
+            assert_eq!(
+                item.get_non_root_source_file_with(config.global_ignore()).unwrap(),
+                HashSet::from([item.root.join("template.txt")]),
+            );
+            assert_eq!(item.get_non_root_source_file().unwrap().len(), 2);
+        }
+
+        config.remove_global_ignore("Thumbs.db").unwrap();
+        assert!(config.global_ignore().is_empty());
+        assert!(matches!(config.remove_global_ignore("Thumbs.db"), Err(DistributorConfigError::NotExist)));
+    }
+
+    #[test]
+    fn test_add_target_inside_root() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let root = temp_path.join("resource");
+        let _ = fs::create_dir(&root);
+
+        let mut config = DistributorConfiguration::default();
+        let _ = config.add_distributor("test", &root);
+
+        assert!(matches!(
+            config.add_target("test", &root),
+            Err(DistributorConfigError::TargetInsideRoot(_))
+        ));
+        assert!(matches!(
+            config.add_target("test", &root.join("out")),
+            Err(DistributorConfigError::TargetInsideRoot(_))
+        ));
+        assert!(matches!(
+            config.add_target("test", &temp_path.join("./resource/../resource/out")),
+            Err(DistributorConfigError::TargetInsideRoot(_))
+        ));
+        assert!(config.add_target("test", &temp_path.join("resource-out")).is_ok());
+        assert_eq!(config.get_distributor("test").unwrap().to, vec![temp_path.join("resource-out")]);
+    }
+
+    #[test]
+    fn test_get_source_with_gitignore() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let root = temp_path.join("resource");
+        fs::create_dir_all(root.join("target")).unwrap();
+        fs::create_dir_all(root.join("sub/cache")).unwrap();
+        fs::write(root.join(".gitignore"), "target/\n").unwrap();
+        fs::write(root.join("sub/.gitignore"), "cache/\n*.tmp\n").unwrap();
+        fs::write(root.join("a.txt"), "a").unwrap();
+        fs::write(root.join("target/b.txt"), "b").unwrap();
+        fs::write(root.join("sub/c.txt"), "c").unwrap();
+        fs::write(root.join("sub/d.tmp"), "d").unwrap();
+        fs::write(root.join("sub/cache/e.txt"), "e").unwrap();
+        fs::write(root.join("f.tmp"), "f").unwrap();
+
+        let mut item = DistributorItem {
+            name: "test".to_string(),
+            root: root.clone(),
+            ignore: vec![".gitignore".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(item.get_non_root_source_file().unwrap().len(), 6);
+
+        item.use_gitignore = true;
+        let source = item.get_non_root_source_file().unwrap();
+
+        assert_eq!(source, HashSet::from([root.join("a.txt"), root.join("sub/c.txt"), root.join("f.tmp")]));
+    }
+
+    #[test]
+    fn test_get_source_with_ignore_file() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let root = temp_path.join("resource");
+        fs::create_dir_all(root.join("build")).unwrap();
+        fs::write(root.join(".distributorignore"), "# comment\n\n*.log\nbuild/\n").unwrap();
+        fs::write(root.join("a.txt"), "a").unwrap();
+        fs::write(root.join("b.log"), "b").unwrap();
+        fs::write(root.join("c.bak"), "c").unwrap();
+        fs::write(root.join("build/d.txt"), "d").unwrap();
+
+        let item = DistributorItem {
+            name: "test".to_string(),
+            root: root.clone(),
+            ignore: vec!["*.bak".to_string()],
+            ..Default::default()
+        };
+        let source = item.get_non_root_source_file().unwrap();
+
+        assert_eq!(source, HashSet::from([root.join("a.txt")]));
+        assert!(item.is_ignored(&root.join("sub/e.log"), &[]));
+        assert!(!item.is_ignored(&root.join("sub/e.txt"), &[]));
+    }
+
+    #[test]
+    fn test_ignore_anchored_rule() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let root = temp_path.join("resource");
+        fs::create_dir_all(root.join("build")).unwrap();
+        fs::create_dir_all(root.join("sub/build")).unwrap();
+        fs::write(root.join(".distributorignore"), "/build/\n/a.txt\n").unwrap();
+        for file in ["a.txt", "build/b.txt", "sub/a.txt", "sub/build/c.txt"] {
+            fs::write(root.join(file), file).unwrap();
+        }
+
+        let item = DistributorItem {
+            name: "test".to_string(),
+            root: root.clone(),
+            ignore: vec!["/sub/build/*".to_string()],
+            ..Default::default()
+        };
+
+        assert_eq!(item.get_non_root_source_file().unwrap(), HashSet::from([root.join("sub/a.txt")]));
+
+        println!("unanchored rules match at any depth");
+        let item = DistributorItem { ignore: vec!["build/*".to_string()], ..item };
+        fs::remove_file(root.join(".distributorignore")).unwrap();
+        assert_eq!(item.get_non_root_source_file().unwrap(), HashSet::from([root.join("a.txt"), root.join("sub/a.txt")]));
+    }
+
+    #[test]
+    fn test_ignore_with_special_root() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let root = temp_path.join("resource [1]");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("sub/a.txt"), "a").unwrap();
+        fs::write(root.join("sub/b.log"), "b").unwrap();
+
+        let item = DistributorItem {
+            name: "test".to_string(),
+            root: root.clone(),
+            ignore: vec!["*.log".to_string()],
+            ..Default::default()
+        };
+
+        assert_eq!(item.get_non_root_source_file().unwrap(), HashSet::from([root.join("sub/a.txt")]));
+        assert!(item.is_ignored(&root.join("sub/c.log"), &[]));
+    }
+
+    #[test]
+    fn test_ignore_root_level_and_nested_file() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let root = temp_path.join("resource");
+        fs::create_dir_all(root.join("a/b/c/d")).unwrap();
+        fs::write(root.join("secret.txt"), "secret").unwrap();
+        fs::write(root.join("a/b/c/d/secret.txt"), "secret").unwrap();
+        fs::write(root.join("a/b/c/d/public.txt"), "public").unwrap();
+        fs::write(root.join("public.txt"), "public").unwrap();
+
+        let item = DistributorItem {
+            name: "test".to_string(),
+            root: root.clone(),
+            ignore: vec!["secret.txt".to_string()],
+            ..Default::default()
+        };
+
+        assert_eq!(item.get_non_root_source_file().unwrap(),
+                   HashSet::from([root.join("public.txt"), root.join("a/b/c/d/public.txt")]));
+        assert!(item.is_ignored(&root.join("secret.txt"), &[]));
+        assert!(item.is_ignored(&root.join("a/b/c/d/secret.txt"), &[]));
+        assert!(!item.is_ignored(&root.join("a/b/c/d/not-secret.txt"), &[]));
+        assert!(!item.is_ignored(&temp_path.join("secret.txt"), &[]));
+    }
+
+    #[test]
+    fn test_ignore_from_ignore_file() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let root = temp_path.join("resource");
+        let config_dir = temp_path.join("config");
+        fs::create_dir_all(root.join("build")).unwrap();
+        fs::create_dir_all(&config_dir).unwrap();
+        for file in ["a.txt", "b.log", "keep.log", "build/out.bin"] {
+            fs::write(root.join(file), file).unwrap();
+        }
+        fs::write(config_dir.join("shared.ignore"), "# shared\n*.log\n!keep.log\n\nbuild/\n").unwrap();
+        let config_path = config_dir.join("distributor-config.toml");
+        fs::write(&config_path, format!(
+            "[[items]]\nname = \"test\"\nroot = {:?}\nignore = []\nignore_file = \"shared.ignore\"\nto = []\n",
+            root.to_string_lossy())).unwrap();
+
+        let config = DistributorConfiguration::read_from(&config_path).unwrap();
+        let item = config.get_distributor("test").unwrap();
+        assert_eq!(item.ignore_file, Some(PathBuf::from("shared.ignore")));
+        assert_eq!(item.get_non_root_source_file().unwrap(), HashSet::from([root.join("a.txt"), root.join("keep.log")]));
+
+        println!("the resolved path is not saved");
+        config.save_to(&config_path);
+        assert!(fs::read_to_string(&config_path).unwrap().contains("ignore_file = \"shared.ignore\""));
+    }
+
+    #[test]
+    fn test_case_insensitive_ignore() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let root = temp_path.join("resource");
+        fs::create_dir_all(root.join("Sub")).unwrap();
+        fs::write(root.join("image.png"), "png").unwrap();
+        fs::write(root.join("Sub/Photo.Png"), "png").unwrap();
+        fs::write(root.join("a.txt"), "a").unwrap();
+
+        let mut item = DistributorItem::builder("test", &root).ignore("*.PNG").build();
+        assert_eq!(item.get_non_root_source_file().unwrap(),
+                   HashSet::from([root.join("image.png"), root.join("Sub/Photo.Png"), root.join("a.txt")]));
+
+        item.case_insensitive_ignore = true;
+        assert_eq!(item.get_non_root_source_file().unwrap(), HashSet::from([root.join("a.txt")]));
+        assert!(item.is_ignored(&root.join("Sub/Photo.Png"), &[]));
+    }
+
+    #[test]
+    fn test_ignore_with_negation() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let root = temp_path.join("resource");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        for file in ["a.log", "keep.log", "sub/keep.log", "sub/b.log", "c.txt"] {
+            fs::write(root.join(file), file).unwrap();
+        }
+
+        let item = DistributorItem::builder("test", &root).ignore("*.log").ignore("!keep.log").build();
+        assert_eq!(item.get_non_root_source_file().unwrap(),
+                   HashSet::from([root.join("keep.log"), root.join("sub/keep.log"), root.join("c.txt")]));
+
+        println!("rules apply in order, a later rule excludes again");
+        let item = DistributorItem::builder("test", &root).ignore("!keep.log").ignore("*.log").build();
+        assert_eq!(item.get_non_root_source_file().unwrap(), HashSet::from([root.join("c.txt")]));
+
+        println!("item rules re-include files excluded by global rules");
+        let item = DistributorItem::builder("test", &root).ignore("!sub/keep.log").build();
+        assert_eq!(item.get_non_root_source_file_with(&["*.log".to_string()]).unwrap(),
+                   HashSet::from([root.join("sub/keep.log"), root.join("c.txt")]));
+        assert!(item.is_ignored(&root.join("keep.log"), &["*.log".to_string()]));
+    }
+
+    #[test]
+    fn test_ignore_with_braces() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let root = temp_path.join("resource");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        for file in ["a.txt", "sub/b.md", "c.png", "keep.md"] {
+            fs::write(root.join(file), file).unwrap();
+        }
+
+        let item = DistributorItem::builder("test", &root).ignore("*.{txt,md}").ignore("!{keep}.md").build();
+        assert_eq!(item.get_non_root_source_file().unwrap(), HashSet::from([root.join("c.png")]));
+
+        let item = DistributorItem::builder("test", &root).ignore("*.{txt,md}").ignore("!{keep,x}.md").build();
+        assert_eq!(item.get_non_root_source_file().unwrap(),
+                   HashSet::from([root.join("c.png"), root.join("keep.md")]));
+    }
+
+    #[test]
+    fn test_expand_braces() {
+        assert_eq!(expand_braces("*.log"), vec!["*.log"]);
+        assert_eq!(expand_braces("*.{png,jpg}"), vec!["*.png", "*.jpg"]);
+        assert_eq!(expand_braces("!{a,b}/*.{x,y}"), vec!["!a/*.x", "!a/*.y", "!b/*.x", "!b/*.y"]);
+        assert_eq!(expand_braces("a{b,c{d,e}}f"), vec!["abf", "acdf", "acef"]);
+        assert_eq!(expand_braces("{,pre-}name"), vec!["name", "pre-name"]);
+
+        println!("braces without a comma, unclosed braces and braces in a class are kept");
+        assert_eq!(expand_braces("{a}.{b,c}"), vec!["{a}.b", "{a}.c"]);
+        assert_eq!(expand_braces("{a,b"), vec!["{a,b"]);
+        assert_eq!(expand_braces("[{,}].txt"), vec!["[{,}].txt"]);
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn test_ignore_with_backslash_root() {
+        let item = DistributorItem {
+            name: "test".to_string(),
+            root: PathBuf::from(r"C:\Users\test\resource"),
+            ignore: vec!["*.log".to_string()],
+            ..Default::default()
+        };
+
+        assert!(item.is_ignored(Path::new(r"C:\Users\test\resource\sub\a.log"), &[]));
+        assert!(!item.is_ignored(Path::new(r"C:\Users\test\resource\sub\a.txt"), &[]));
+    }
+
+    #[test]
+    fn test_item_builder() {
+        let item = DistributorItem::builder("test", "resource")
+            .ignore("template.txt")
+            .ignore("template.txt")
+            .target("test-target/tar1")
+            .target(PathBuf::from("test-target/tar2"))
+            .target("test-target/tar1")
+            .build();
+
+        assert_eq!(
+            item,
+            DistributorItem {
+                name: "test".to_string(),
+                root: PathBuf::from("resource"),
+                ignore: vec!["template.txt".to_string()],
+                to: vec![PathBuf::from("test-target/tar1"), PathBuf::from("test-target/tar2")],
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn test_expand_env_vars() {
+        let var = |name: &str| (name == "BUILD_DIR").then(|| "build".to_string());
+        let env = PathEnv { var: &var, home: None };
+
+        assert_eq!(env.expand_env_vars("$BUILD_DIR/out"), "build/out");
+        assert_eq!(env.expand_env_vars("${BUILD_DIR}out"), "buildout");
+        assert_eq!(env.expand_env_vars("a/$UNDEFINED/b"), "a/$UNDEFINED/b");
+        assert_eq!(env.expand_env_vars("${UNDEFINED}"), "${UNDEFINED}");
+        assert_eq!(env.expand_env_vars("cost$ 5$"), "cost$ 5$");
+        #[cfg(windows)]
+        assert_eq!(env.expand_env_vars("%BUILD_DIR%/out"), "build/out");
+
+        let item = DistributorItem {
+            name: "test".to_string(),
+            root: PathBuf::from("$BUILD_DIR/resource"),
+            ignore: vec!["${BUILD_DIR}/*.log".to_string()],
+            to: vec![PathBuf::from("$BUILD_DIR/target")],
+            rename: BTreeMap::from([(PathBuf::from("$BUILD_DIR/target"), "a.txt".to_string())]),
+            ..Default::default()
+        };
+        let expanded = item.expanded_in(&env);
+
+        assert_eq!(expanded.root, PathBuf::from("build/resource"));
+        assert_eq!(expanded.ignore, vec!["build/*.log".to_string()]);
+        assert_eq!(expanded.to, vec![PathBuf::from("build/target")]);
+        assert_eq!(expanded.get_target_file_name(Path::new("build/target")), Some("a.txt"));
+        assert_eq!(item.root, PathBuf::from("$BUILD_DIR/resource"));
+    }
+
+    #[test]
+    fn test_expand_home() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let env = PathEnv { var: &|_| None, home: Some(temp_path.clone()) };
+
+        let item = DistributorItem {
+            name: "test".to_string(),
+            root: PathBuf::from("resource"),
+            to: vec![PathBuf::from("~/deploy"), PathBuf::from("a/~/deploy"), PathBuf::from("~deploy")],
+            ..Default::default()
+        };
+        let expanded = item.expanded_in(&env);
+
+        assert_eq!(expanded.to[0], temp_path.join("deploy"));
+        assert_eq!(expanded.to[1], PathBuf::from("a/~/deploy"));
+        assert_eq!(expanded.to[2], PathBuf::from("~deploy"));
+        assert_eq!(env.expand_home(Path::new("~")), temp_path);
+        assert_eq!(PathEnv { var: &|_| None, home: None }.expand_home(Path::new("~/deploy")), PathBuf::from("~/deploy"));
+    }
+
+    #[test]
+    fn test_validate() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let root = temp_path.join("resource");
+        let _ = fs::create_dir(&root);
+
+        let config = DistributorConfiguration {
+            global_ignore: vec!["[".to_string()],
+            items: vec![
+                DistributorItem {
+                    name: "test".to_string(),
+                    root: root.clone(),
+                    ignore: vec!["[*".to_string()],
+                    to: vec![
+                        root.clone(),
+                        root.join("out"),
+                        temp_path.join("resource/./../resource/sub/out"),
+                        temp_path.join("target"),
+                    ],
+                    ..Default::default()
+                },
+                DistributorItem {
+                    name: "test".to_string(),
+                    root: temp_path.join("not-exist"),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        let issues = config.validate();
+
+        assert_eq!(
+            issues,
+            vec![
+                ValidationIssue::InvalidGlob(String::new(), "[".to_string()),
+                ValidationIssue::InvalidGlob("test".to_string(), "[*".to_string()),
+                ValidationIssue::TargetIsRoot("test".to_string(), root.clone()),
+                ValidationIssue::TargetInsideRoot("test".to_string(), root.join("out")),
+                ValidationIssue::TargetInsideRoot(
+                    "test".to_string(),
+                    temp_path.join("resource/./../resource/sub/out")),
+                ValidationIssue::NestedTarget("test".to_string(), root.clone(), root.join("out")),
+                ValidationIssue::NestedTarget(
+                    "test".to_string(),
+                    root.clone(),
+                    temp_path.join("resource/./../resource/sub/out")),
+                ValidationIssue::DuplicateName("test".to_string()),
+                ValidationIssue::RootNotExist("test".to_string(), temp_path.join("not-exist")),
+            ]
         );
+        assert!(!issues.last().unwrap().is_error());
+        assert!(issues.first().unwrap().is_error());
+    }
+
+    #[test]
+    fn test_validate_nested_targets() {
+        let config = DistributorConfiguration {
+            items: vec![
+                DistributorItem::builder("test", "resource")
+                    .target("out")
+                    .target("./out/sub/../sub")
+                    .target("output")
+                    .target("other/out")
+                    .build(),
+            ],
+            ..Default::default()
+        };
+
+        let issues: Vec<_> = config.validate()
+                                   .into_iter()
+                                   .filter(|issue| matches!(issue, ValidationIssue::NestedTarget(_, _, _)))
+                                   .collect();
+        assert_eq!(issues, vec![
+            ValidationIssue::NestedTarget("test".to_string(), PathBuf::from("out"), PathBuf::from("./out/sub/../sub")),
+        ]);
+        assert!(!issues[0].is_error());
+        assert_eq!(issues[0].to_string(), r#"test: target "./out/sub/../sub" is inside target "out""#);
     }
 
     #[test]
+    #[allow(clippy::get_first)]
     fn test_get_source() {
-        let temp_path = tempdir()
-            .unwrap()
-            .into_path();
+        let temp_dir = tempdir()
+            .unwrap();
+        let temp_path = temp_dir.path();
 
         let origin_current_dir = env::current_dir().unwrap();
         let _ = env::set_current_dir(temp_path);
@@ -372,8 +2869,10 @@ mod tests {
                         "template.txt".to_string(),
                     ],
                     to: vec![],
+                    ..Default::default()
                 },
             ],
+            ..Default::default()
         };
 
         let res = config.items.get(0)
@@ -385,6 +2884,210 @@ mod tests {
 
         let _ = env::set_current_dir(origin_current_dir);
     }
+
+    #[test]
+    fn test_get_source_with_absolute_root() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+
+        let root = temp_path.join("resource");
+        let _ = fs::create_dir(&root);
+        let _ = fs::write(root.join("template.txt"), "test1");
+        let _ = fs::write(root.join("template2.txt"), "test2");
+
+        let config = DistributorConfiguration {
+            items: vec![
+                DistributorItem {
+                    name: "test".to_string(),
+                    root: root.clone(),
+                    ignore: vec![
+                        "template.txt".to_string(),
+                    ],
+                    to: vec![],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        let res = config.items.first()
+                        .unwrap()
+                        .get_non_root_source_file()
+                        .unwrap();
+
+        assert_eq!(res, HashSet::from([root.join("template2.txt")]));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_get_source_with_symlink_loop() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let root = temp_path.join("resource");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("sub/a.txt"), "a").unwrap();
+        std::os::unix::fs::symlink(&root, root.join("sub/loop")).unwrap();
+
+        let item = DistributorItem::builder("test", &root).build();
+        assert_eq!(item.get_non_root_source_file().unwrap(), HashSet::from([root.join("sub/a.txt")]));
+    }
+
+    #[test]
+    fn test_render_source_tree() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let root = temp_path.join("resource");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("a.txt"), "a").unwrap();
+        fs::write(root.join("sub/b.txt"), "b").unwrap();
+        fs::write(root.join("sub/c.log"), "c").unwrap();
+
+        let item = DistributorItem::builder("test", &root).ignore("*.log").build();
+        let tree = item.render_source_tree(&[]).unwrap();
+        assert_eq!(tree, format!("{}/\n  a.txt\n  sub/\n    b.txt\n    c.log (excluded)\n", root.display()));
+
+        let file_item = DistributorItem::builder("file", root.join("a.txt")).build();
+        assert_eq!(file_item.render_source_tree(&[]).unwrap(), format!("{}\n", root.join("a.txt").display()));
+    }
+
+    #[test]
+    fn test_get_source_with_multiple_roots() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let root_a = temp_path.join("resource-a");
+        let root_b = temp_path.join("resource-b");
+        fs::create_dir_all(&root_a).unwrap();
+        fs::create_dir_all(root_b.join("sub")).unwrap();
+        fs::write(root_a.join("a.txt"), "a").unwrap();
+        fs::write(root_b.join("sub/b.txt"), "b").unwrap();
+        fs::write(root_b.join("b.log"), "b").unwrap();
+
+        let mut item = DistributorItem::builder("test", &root_a).ignore("*.log").build();
+        item.roots.push(root_b.clone());
+        assert_eq!(item.get_non_root_source_file().unwrap(),
+                   HashSet::from([root_a.join("a.txt"), root_b.join("sub/b.txt")]));
+        assert_eq!(item.get_relative_path(&root_b.join("sub/b.txt")), Some(Path::new("sub/b.txt")));
+
+        fs::write(root_b.join("a.txt"), "collision").unwrap();
+        assert!(matches!(item.get_non_root_source_file(),
+                         Err(DistributorConfigError::RootCollision(p)) if p == Path::new("a.txt")));
+    }
+
+    #[test]
+    fn test_get_source_with_glob_root() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let build = temp_path.join("build");
+        fs::create_dir_all(build.join("a/dist/sub")).unwrap();
+        fs::create_dir_all(build.join("b/dist")).unwrap();
+        fs::create_dir_all(build.join("c")).unwrap();
+        fs::write(build.join("a/dist/sub/a.txt"), "a").unwrap();
+        fs::write(build.join("b/dist/b.txt"), "b").unwrap();
+        fs::write(build.join("c/c.txt"), "c").unwrap();
+
+        let item = DistributorItem::builder("test", build.join("*/dist")).build();
+        assert_eq!(item.root, build.join("*/dist"));
+
+        let expanded = item.expanded();
+        assert_eq!(expanded.root, build.join("a/dist"));
+        assert_eq!(expanded.roots, vec![build.join("b/dist")]);
+        assert_eq!(expanded.get_non_root_source_file().unwrap(),
+                   HashSet::from([build.join("a/dist/sub/a.txt"), build.join("b/dist/b.txt")]));
+        assert_eq!(expanded.get_relative_path(&build.join("b/dist/b.txt")), Some(Path::new("b.txt")));
+
+        let unmatched = DistributorItem::builder("test", build.join("*/missing")).build().expanded();
+        assert_eq!(unmatched.root, build.join("*/missing"));
+        assert!(unmatched.roots.is_empty());
+    }
+
+    #[test]
+    fn test_get_source_count() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let root = temp_path.join("resource");
+        fs::create_dir_all(root.join("sub/deep")).unwrap();
+        for file in ["a.txt", "b.log", "sub/c.txt", "sub/deep/d.txt"] {
+            fs::write(root.join(file), file).unwrap();
+        }
+
+        let item = DistributorItem::builder("test", &root).ignore("*.log").build();
+        assert_eq!(item.get_source_count(&[]).unwrap(), 3);
+        assert_eq!(item.get_source_count(&["sub/deep/*".to_string()]).unwrap(), 2);
+
+        let file_item = DistributorItem::builder("file", root.join("b.log")).build();
+        assert_eq!(file_item.get_source_count(&[]).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_get_source_size() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let root = temp_path.join("resource");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("a.txt"), "a".repeat(100)).unwrap();
+        fs::write(root.join("sub/b.txt"), "b".repeat(2048)).unwrap();
+        fs::write(root.join("sub/c.log"), "c".repeat(10)).unwrap();
+
+        let item = DistributorItem::builder("test", &root).ignore("*.log").build();
+        assert_eq!(item.get_source_size(&[]).unwrap(), 2148);
+        assert_eq!(item.get_source_size(&["a.txt".to_string()]).unwrap(), 2048);
+
+        let file_item = DistributorItem::builder("file", root.join("sub/c.log")).build();
+        assert_eq!(file_item.get_source_size(&[]).unwrap(), 10);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_get_source_with_unreadable_dir() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let root = temp_path.join("resource");
+        let locked = root.join("locked");
+        fs::create_dir_all(&locked).unwrap();
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("a.txt"), "a").unwrap();
+        fs::write(root.join("sub/b.txt"), "b").unwrap();
+        fs::write(locked.join("c.txt"), "c").unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+        if fs::read_dir(&locked).is_ok() {
+            println!("permissions are not enforced for this user, skipped");
+            fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+            return;
+        }
+
+        let item = DistributorItem::builder("test", &root).build();
+        let (sources, warnings) = item.get_non_root_source_file_and_warnings(&[]).unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+
+        assert_eq!(sources, HashSet::from([root.join("a.txt"), root.join("sub/b.txt")]));
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].path, locked);
+        assert_eq!(warnings[0].error.kind(), std::io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn test_get_source_with_max_depth() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let root = temp_path.join("resource");
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::write(root.join("zero.txt"), "0").unwrap();
+        fs::write(root.join("a/one.txt"), "1").unwrap();
+        fs::write(root.join("a/b/two.txt"), "2").unwrap();
+
+        let mut item = DistributorItem::builder("test", &root).build();
+        item.max_depth = Some(1);
+        assert_eq!(item.get_non_root_source_file().unwrap(),
+                   HashSet::from([root.join("zero.txt"), root.join("a/one.txt")]));
+
+        item.max_depth = Some(0);
+        assert_eq!(item.get_non_root_source_file().unwrap(), HashSet::from([root.join("zero.txt")]));
+
+        item.max_depth = None;
+        assert_eq!(item.get_non_root_source_file().unwrap().len(), 3);
+    }
 }
 
 //endregion ⠄⠄⠄⠄⠄⠄⠄⠄⠄⠄⠄⠄⠄⠄⠄⠐⠒⠒⠒⠒⠚⠛⣿⡟⠄⠄⢠⠄⠄⠄⡄⠄⠄⣠⡶⠶⣶⠶⠶⠂⣠⣶⣶⠂⠄⣸⡿⠄⠄⢀⣿⠇⠄⣰⡿⣠⡾⠋⠄⣼⡟⠄⣠⡾⠋⣾⠏⠄⢰⣿⠁⠄⠄⣾⡏⠄⠠⠿⠿⠋⠠⠶⠶⠿⠶⠾⠋⠄⠽⠟⠄⠄⠄⠃⠄⠄⣼⣿⣤⡤⠤⠤⠤⠤⠄⠄⠄⠄⠄⠄⠄⠄⠄⠄⠄⠄⠄⠄⠄⠄
@@ -0,0 +1,118 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::distributor::{format_rfc3339, DistributorResult, RunSummary};
+
+/// 运行记录的默认路径。
+pub static DEFAULT_HISTORY_PATH: &str = ".distributor/history.jsonl";
+
+/// # 运行记录
+/// 一次 Run 的结果汇总，以 json 行追加至运行记录文件。
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct HistoryEntry {
+    /// 运行完成的 RFC 3339 时刻。
+    pub timestamp: String,
+    /// 本次运行的配置条目名称。
+    pub distributors: Vec<String>,
+    pub summary: RunSummary,
+    pub errors: Vec<String>,
+}
+
+impl HistoryEntry {
+    /// 由本次运行的结果创建记录。
+    ///
+    /// # Param
+    ///
+    /// - `distributors` - 本次运行的配置条目名称。
+    /// - `results` - 本次运行的所有结果。
+    pub fn new(distributors: Vec<String>, results: &[DistributorResult]) -> Self {
+        HistoryEntry {
+            timestamp: format_rfc3339(SystemTime::now()),
+            distributors,
+            summary: RunSummary::from_results(results),
+            errors: results.iter()
+                           .filter_map(|result| result.as_ref().err())
+                           .map(|e| e.to_string())
+                           .collect(),
+        }
+    }
+}
+
+/// 将记录追加至运行记录文件末尾，文件不存在时创建。
+///
+/// # Param
+///
+/// - `path` - 运行记录文件路径。
+/// - `entry` - 待追加的记录。
+pub fn append_history(path: &Path, entry: &HistoryEntry) -> std::io::Result<()> {
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    let line = serde_json::to_string(entry)?;
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+
+    writeln!(file, "{}", line)
+}
+
+/// 读取运行记录，按时间先后排列，无法解析的行被忽略。文件不存在时返回空记录。
+///
+/// # Param
+///
+/// - `path` - 运行记录文件路径。
+/// - `limit` - 仅获取最近的若干条记录，None 时获取全部。
+pub fn read_history(path: &Path, limit: Option<usize>) -> std::io::Result<Vec<HistoryEntry>> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+    let mut entries: Vec<HistoryEntry> = content.lines()
+                                                .filter_map(|line| serde_json::from_str(line).ok())
+                                                .collect();
+    if let Some(limit) = limit {
+        entries.drain(..entries.len().saturating_sub(limit));
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::distributor::{new_test_distributor, RunOptions, Verbosity};
+    use crate::distributor_config::DistributorItem;
+
+    #[test]
+    fn test_history_per_run() {
+        let (temp_dir, root, target, mut distributor) = new_test_distributor(Verbosity::Quiet);
+        let temp_path = temp_dir.path().to_path_buf();
+        let history_path = temp_path.join(".distributor/history.jsonl");
+        fs::write(root.join("a.txt"), "a").unwrap();
+
+        let item = DistributorItem::builder("test", &root).target(&target).build();
+        for _ in 0..2 {
+            let results = distributor.do_copy(&item, &RunOptions::default());
+            append_history(&history_path, &HistoryEntry::new(vec![item.name.clone()], &results)).unwrap();
+        }
+
+        assert_eq!(fs::read_to_string(&history_path).unwrap().lines().count(), 2);
+        let entries = read_history(&history_path, None).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].distributors, vec!["test".to_string()]);
+        assert_eq!(entries[0].summary.copied, 1);
+        assert_eq!(entries[1].summary.copied, 0);
+        assert_eq!(entries[1].summary.up_to_date, 1);
+        assert!(entries.iter().all(|entry| entry.errors.is_empty()));
+
+        println!("limit keeps the most recent entries");
+        let recent = read_history(&history_path, Some(1)).unwrap();
+        assert_eq!(recent, entries[1..]);
+        assert!(read_history(&temp_path.join("missing.jsonl"), None).unwrap().is_empty());
+    }
+}
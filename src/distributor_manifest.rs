@@ -0,0 +1,158 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::distributor_cache_db::{HashAlgorithm, Hasher};
+use crate::distributor_config::DistributorItem;
+
+/// # 清单条目
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct ManifestEntry {
+    pub algorithm: HashAlgorithm,
+    pub hash: String,
+    pub size: u64,
+}
+
+/// # 校验清单
+/// 记录本次运行写入的目标文件的哈希与大小，在所有复制线程间共享。
+/// 仅记录实际写入的本地文件，dry run 与远程目标不被记录。
+#[derive(Debug, Default)]
+pub struct Manifest {
+    records: Mutex<BTreeMap<PathBuf, ManifestEntry>>,
+}
+
+impl Manifest {
+    /// 记录写入的目标文件。
+    pub fn record(&self, target: &Path, entry: ManifestEntry) {
+        self.records.lock().unwrap().insert(target.to_path_buf(), entry);
+    }
+
+    /// 按目标目录分组，以相对目标目录的路径为键获取记录。
+    /// 不在任何目标目录下的文件（如 root 指向文件且 target 为文件路径）以其所在目录分组。
+    ///
+    /// # Param
+    ///
+    /// - `items` - 本次运行的配置条目。
+    pub fn get_grouped(&self, items: &[&DistributorItem]) -> BTreeMap<String, BTreeMap<String, ManifestEntry>> {
+        let records = self.records.lock().unwrap();
+        let targets: Vec<PathBuf> = items.iter().flat_map(|item| item.expanded().to).collect();
+        let mut grouped: BTreeMap<String, BTreeMap<String, ManifestEntry>> = BTreeMap::new();
+        for (target, entry) in records.iter() {
+            let to = targets.iter()
+                            .filter(|to| target.starts_with(to) && target != *to)
+                            .max_by_key(|to| to.components().count())
+                            .cloned()
+                            .unwrap_or_else(|| target.parent().map(Path::to_path_buf).unwrap_or_default());
+            let relative = target.strip_prefix(&to).unwrap_or(target);
+            let relative: Vec<_> = relative.components()
+                                           .map(|component| component.as_os_str().to_string_lossy())
+                                           .collect();
+
+            grouped.entry(to.to_string_lossy().to_string())
+                   .or_default()
+                   .insert(relative.join("/"), entry.clone());
+        }
+
+        grouped
+    }
+
+    /// 以 json 写入清单。
+    ///
+    /// # Param
+    ///
+    /// - `path` - 清单文件路径。
+    /// - `items` - 本次运行的配置条目。
+    pub fn save(&self, path: &Path, items: &[&DistributorItem]) -> std::io::Result<()> {
+        let content = serde_json::to_string_pretty(&self.get_grouped(items))?;
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        std::fs::write(path, content)
+    }
+}
+
+/// # 哈希写入器
+/// 写入时同时计算内容的哈希与大小，未指定算法时直接写入。
+pub struct HashingWriter<W: Write> {
+    inner: W,
+    hasher: Option<(HashAlgorithm, Hasher)>,
+    size: u64,
+}
+
+impl<W: Write> HashingWriter<W> {
+    pub fn new(inner: W, algorithm: Option<HashAlgorithm>) -> Self {
+        HashingWriter {
+            inner,
+            hasher: algorithm.map(|algorithm| (algorithm, algorithm.hasher())),
+            size: 0,
+        }
+    }
+
+    /// 获取已写入内容的清单条目，未指定算法时返回 None。
+    pub fn finish(self) -> Option<ManifestEntry> {
+        let (algorithm, hasher) = self.hasher?;
+
+        Some(ManifestEntry {
+            algorithm,
+            hash: hasher.finish(),
+            size: self.size,
+        })
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        if let Some((_, hasher)) = self.hasher.as_mut() {
+            hasher.write_all(&buf[..written])?;
+            self.size += written as u64;
+        }
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::distributor::{new_test_distributor, RunOptions, Verbosity};
+    use crate::distributor_cache_db::get_file_hash;
+
+    #[test]
+    fn test_manifest_lists_copied_files() {
+        let (temp_dir, root, target, mut distributor) = new_test_distributor(Verbosity::default());
+        let temp_path = temp_dir.path().to_path_buf();
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("a.txt"), "a").unwrap();
+        fs::write(root.join("sub/b.txt"), "bb").unwrap();
+        let item = DistributorItem::builder("test", &root).target(&target).build();
+        let manifest = Arc::new(Manifest::default());
+        let options = RunOptions { manifest: Some(manifest.clone()), atomic: true, ..Default::default() };
+        distributor.do_copy(&item, &options);
+
+        let manifest_path = temp_path.join("manifest.json");
+        manifest.save(&manifest_path, &[&item]).unwrap();
+        let saved: BTreeMap<String, BTreeMap<String, ManifestEntry>> =
+            serde_json::from_str(&fs::read_to_string(&manifest_path).unwrap()).unwrap();
+
+        let entries = &saved[&target.to_string_lossy().to_string()];
+        assert_eq!(entries.len(), 2);
+        for (relative, size) in [("a.txt", 1), ("sub/b.txt", 2)] {
+            let entry = &entries[relative];
+            assert_eq!(entry.size, size);
+            assert_eq!(entry.algorithm, HashAlgorithm::Blake3);
+            assert_eq!(entry.hash, get_file_hash(&target.join(relative), HashAlgorithm::Blake3).unwrap());
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// # 行读取器
+/// 覆盖确认的输入来源。
+pub trait LineReader: Send {
+    /// 读取一行，输入结束或读取失败时返回 None。
+    fn read_line(&mut self) -> Option<String>;
+}
+
+/// # 标准输入行读取器
+pub struct StdinLineReader;
+
+impl LineReader for StdinLineReader {
+    fn read_line(&mut self) -> Option<String> {
+        let mut line = String::new();
+        match std::io::stdin().lock().read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line),
+        }
+    }
+}
+
+/// # 覆盖确认
+/// 覆盖已存在且内容不同的目标文件前询问 `[y/N/a]`。
+/// 回答 a 后不再询问，此后的目标均被覆盖。在所有复制线程间共享，同一时刻仅有一个询问。
+pub struct OverwritePrompt {
+    state: Mutex<PromptState>,
+}
+
+struct PromptState {
+    reader: Box<dyn LineReader>,
+    all: bool,
+}
+
+impl OverwritePrompt {
+    pub fn new(reader: impl LineReader + 'static) -> Self {
+        OverwritePrompt {
+            state: Mutex::new(PromptState {
+                reader: Box::new(reader),
+                all: false,
+            }),
+        }
+    }
+
+    /// 询问是否覆盖目标文件，询问输出至 stderr。输入结束时视为否。
+    ///
+    /// # Param
+    ///
+    /// - `target_file_path` - 已存在的目标文件的路径。
+    pub fn confirm(&self, target_file_path: &Path) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.all {
+            return true;
+        }
+
+        eprint!("overwrite {:?}? [y/N/a] ", target_file_path);
+        let _ = std::io::stderr().flush();
+        let answer = state.reader.read_line().unwrap_or_default();
+        match answer.trim().to_lowercase().as_str() {
+            "y" | "yes" => true,
+            "a" | "all" => {
+                state.all = true;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Debug for OverwritePrompt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OverwritePrompt").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::fs;
+    use std::sync::Arc;
+
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::distributor::{distribute_file, DistributorResultType, RunOptions};
+
+    struct FakeLineReader(VecDeque<&'static str>);
+
+    impl LineReader for FakeLineReader {
+        fn read_line(&mut self) -> Option<String> {
+            self.0.pop_front().map(|line| format!("{}\n", line))
+        }
+    }
+
+    #[test]
+    fn test_overwrite_prompt() {
+        let temp_dir = tempdir().unwrap();
+        let temp_path = temp_dir.path().to_path_buf();
+        let source = temp_path.join("source.txt");
+        fs::write(&source, "source").unwrap();
+        let targets: Vec<_> = (0..5).map(|i| temp_path.join(format!("target_{}.txt", i))).collect();
+        targets.iter().for_each(|target| fs::write(target, "target").unwrap());
+
+        let prompt = OverwritePrompt::new(FakeLineReader(VecDeque::from(["y", "", "n", "a"])));
+        let options = RunOptions { prompt: Some(Arc::new(prompt)), ..Default::default() };
+        let results: Vec<_> = targets.iter()
+                                     .map(|target| distribute_file(&source, target, &options))
+                                     .collect();
+
+        println!("the last target is overwritten without asking after all");
+        let expected = ["source", "target", "target", "source", "source"];
+        for ((target, result), content) in targets.iter().zip(results.iter()).zip(expected) {
+            assert_eq!(fs::read_to_string(target).unwrap(), content);
+            assert_eq!(matches!(result, Ok(DistributorResultType::Skipped(_, _))), content == "target");
+        }
+
+        println!("end of input is no");
+        let prompt = OverwritePrompt::new(FakeLineReader(VecDeque::new()));
+        assert!(!prompt.confirm(&targets[0]));
+    }
+}
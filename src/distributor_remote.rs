@@ -0,0 +1,308 @@
+#[cfg(feature = "sftp")]
+use std::collections::HashMap;
+use std::path::Path;
+#[cfg(feature = "sftp")]
+use std::sync::{Arc, Mutex};
+
+use crate::distributor::{DistributorError, DistributorResult, RunOptions};
+
+/// 远程目标前缀。
+static SFTP_PREFIX: &str = "sftp://";
+
+/// 默认 SSH 端口。
+static DEFAULT_SSH_PORT: u16 = 22;
+
+/// # SFTP 目标
+#[derive(Debug, PartialEq)]
+pub struct SftpTarget {
+    /// 用户名，未指定时使用当前用户。
+    pub user: Option<String>,
+    pub host: String,
+    pub port: u16,
+    /// 远程文件的绝对路径。
+    pub path: String,
+}
+
+impl SftpTarget {
+    /// 解析 `sftp://[user@]host[:port]:/path` 或 `sftp://[user@]host[:port]/path` 形式的目标。
+    ///
+    /// # Param
+    ///
+    /// - `target` - 目标路径。
+    pub fn parse(target: &Path) -> Result<SftpTarget, DistributorError> {
+        let invalid = || DistributorError::InvalidRemoteTarget(target.to_string_lossy().to_string());
+
+        let rest = target.to_str()
+                         .and_then(|target| target.strip_prefix(SFTP_PREFIX))
+                         .ok_or_else(invalid)?;
+        let (authority, path) = rest.split_at(rest.find(['/', '\\']).ok_or_else(invalid)?);
+        let authority = authority.strip_suffix(':').unwrap_or(authority);
+        let (user, host_port) = match authority.rsplit_once('@') {
+            Some((user, host_port)) => (Some(user.to_string()), host_port),
+            None => (None, authority),
+        };
+        let (host, port) = match host_port.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(|_| invalid())?),
+            None => (host_port, DEFAULT_SSH_PORT),
+        };
+        if host.is_empty() || user.as_deref() == Some("") {
+            return Err(invalid());
+        }
+
+        Ok(SftpTarget {
+            user,
+            host: host.to_string(),
+            port,
+            path: path.replace('\\', "/"),
+        })
+    }
+
+    /// 获取用户名，未指定时使用环境变量 `USER` 或 `USERNAME`。
+    pub fn get_user(&self) -> Option<String> {
+        self.user
+            .clone()
+            .or_else(|| std::env::var("USER").ok())
+            .or_else(|| std::env::var("USERNAME").ok())
+    }
+}
+
+/// # SFTP 连接池
+/// 同一用户、主机与端口的远程目标复用一个已登录的连接，在所有复制线程间共享。
+/// 同一连接同一时刻仅由一个线程使用，出错后丢弃，下次使用时重新建立。
+#[derive(Default)]
+pub struct SftpSessions {
+    #[cfg(feature = "sftp")]
+    sessions: Mutex<HashMap<(String, String, u16), Arc<Mutex<Option<SftpSession>>>>>,
+}
+
+impl std::fmt::Debug for SftpSessions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SftpSessions").finish_non_exhaustive()
+    }
+}
+
+/// # SFTP 连接
+#[cfg(feature = "sftp")]
+struct SftpSession {
+    /// SFTP 通道依赖的会话，随连接一同释放。
+    _session: ssh2::Session,
+    sftp: ssh2::Sftp,
+}
+
+#[cfg(feature = "sftp")]
+impl SftpSession {
+    /// 连接目标主机并登录，打开 SFTP 通道。
+    ///
+    /// # Param
+    ///
+    /// - `target` - 远程目标。
+    /// - `user` - 用户名。
+    /// - `identity` - SSH 私钥路径，None 时使用 SSH agent。
+    fn connect(target: &SftpTarget, user: &str, identity: Option<&Path>) -> Result<SftpSession, DistributorError> {
+        use std::net::TcpStream;
+
+        let remote_error = |e: ssh2::Error| DistributorError::RemoteError(format!("{}: {}", target.host, e));
+        let mut session = ssh2::Session::new().map_err(remote_error)?;
+        session.set_tcp_stream(TcpStream::connect((target.host.as_str(), target.port))?);
+        session.handshake().map_err(remote_error)?;
+        match identity {
+            Some(identity) => session.userauth_pubkey_file(user, None, identity, None),
+            None => session.userauth_agent(user),
+        }.map_err(remote_error)?;
+        let sftp = session.sftp().map_err(remote_error)?;
+
+        Ok(SftpSession { _session: session, sftp })
+    }
+}
+
+#[cfg(feature = "sftp")]
+impl SftpSessions {
+    /// 以目标主机的连接执行 run，连接不存在时建立连接。run 返回错误时丢弃连接。
+    ///
+    /// # Param
+    ///
+    /// - `target` - 远程目标。
+    /// - `user` - 用户名。
+    /// - `identity` - SSH 私钥路径，None 时使用 SSH agent。
+    /// - `run` - 使用 SFTP 通道的操作。
+    fn with_sftp<T>(&self,
+                    target: &SftpTarget,
+                    user: &str,
+                    identity: Option<&Path>,
+                    run: impl FnOnce(&ssh2::Sftp) -> Result<T, DistributorError>) -> Result<T, DistributorError> {
+        let entry = self.sessions
+                        .lock()
+                        .unwrap()
+                        .entry((user.to_string(), target.host.clone(), target.port))
+                        .or_default()
+                        .clone();
+        let mut slot = entry.lock().unwrap();
+        let session = match slot.take() {
+            Some(session) => session,
+            None => SftpSession::connect(target, user, identity)?,
+        };
+
+        let result = run(&session.sftp);
+        if result.is_ok() {
+            *slot = Some(session);
+        }
+        result
+    }
+}
+
+/// 目标是否为远程目标。
+pub fn is_remote_target(target: &Path) -> bool {
+    target.to_str().is_some_and(|target| target.starts_with(SFTP_PREFIX))
+}
+
+/// 分发源文件至远程目标，经由 `options.sftp_sessions` 复用同一主机的连接。
+/// 远程文件大小与修改时间均与源文件相同时返回 Same，上传后远程文件的修改时间将被设为源文件的修改时间。
+///
+/// # Param
+///
+/// - `source_file_path` - 源文件的路径。
+/// - `target_file_path` - `sftp://` 形式的目标文件路径。
+/// - `options` - 运行选项。
+#[cfg(feature = "sftp")]
+pub fn distribute_remote_file(source_file_path: &Path,
+                              target_file_path: &Path,
+                              options: &RunOptions) -> DistributorResult {
+    let target = SftpTarget::parse(target_file_path)?;
+    let user = target.get_user()
+                     .ok_or_else(|| DistributorError::RemoteError(format!("{}: user is unknown", target.host)))?;
+
+    options.sftp_sessions.with_sftp(&target, &user, options.identity.as_deref(), |sftp| {
+        upload_file(sftp, &target, source_file_path, target_file_path, options)
+    })
+}
+
+/// 经由 SFTP 通道上传源文件，远程文件与源文件相同时返回 Same。
+///
+/// # Param
+///
+/// - `sftp` - SFTP 通道。
+/// - `target` - 远程目标。
+/// - `source_file_path` - 源文件的路径。
+/// - `target_file_path` - `sftp://` 形式的目标文件路径。
+/// - `options` - 运行选项。
+#[cfg(feature = "sftp")]
+fn upload_file(sftp: &ssh2::Sftp,
+               target: &SftpTarget,
+               source_file_path: &Path,
+               target_file_path: &Path,
+               options: &RunOptions) -> DistributorResult {
+    use crate::distributor::DistributorResultType::{Copied, Same};
+
+    let remote_error = |e: ssh2::Error| DistributorError::RemoteError(format!("{}: {}", target.host, e));
+    let meta = std::fs::metadata(source_file_path)?;
+    let modified = meta.modified()?
+                       .duration_since(std::time::UNIX_EPOCH)
+                       .map(|d| d.as_secs())
+                       .unwrap_or_default();
+    let remote_path = Path::new(&target.path);
+    let source_str = source_file_path.to_path_buf();
+    let target_str = target_file_path.to_path_buf();
+
+    if let Ok(stat) = sftp.stat(remote_path) {
+        if stat.size == Some(meta.len()) && stat.mtime == Some(modified) {
+            return Ok(Same(source_str, target_str));
+        }
+    }
+    if options.dry_run {
+        return Ok(Copied(source_str, target_str));
+    }
+
+    let mut parents: Vec<&Path> = remote_path.ancestors()
+                                             .skip(1)
+                                             .take_while(|parent| sftp.stat(parent).is_err())
+                                             .collect();
+    parents.reverse();
+    for parent in parents {
+        sftp.mkdir(parent, 0o755).map_err(remote_error)?;
+    }
+
+    let mut reader = std::io::BufReader::new(std::fs::File::open(source_file_path)?);
+    let mut writer = sftp.create(remote_path).map_err(remote_error)?;
+    std::io::copy(&mut reader,
+                  &mut crate::distributor_throttle::ThrottledWriter::new(&mut writer, options.limit.as_deref()))?;
+    drop(writer);
+    sftp.setstat(remote_path, ssh2::FileStat {
+        size: None,
+        uid: None,
+        gid: None,
+        perm: None,
+        atime: Some(modified),
+        mtime: Some(modified),
+    }).map_err(remote_error)?;
+
+    Ok(Copied(source_str, target_str))
+}
+
+/// 分发源文件至远程目标。未启用 `sftp` feature 时总是返回错误。
+#[cfg(not(feature = "sftp"))]
+pub fn distribute_remote_file(_source_file_path: &Path,
+                              target_file_path: &Path,
+                              _options: &RunOptions) -> DistributorResult {
+    SftpTarget::parse(target_file_path)?;
+
+    Err(DistributorError::RemoteError("sftp targets require the `sftp` feature".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_sftp_target() {
+        assert_eq!(
+            SftpTarget::parse(Path::new("sftp://deploy@example.com:/srv/www/index.html")).unwrap(),
+            SftpTarget {
+                user: Some("deploy".to_string()),
+                host: "example.com".to_string(),
+                port: 22,
+                path: "/srv/www/index.html".to_string(),
+            }
+        );
+        assert_eq!(
+            SftpTarget::parse(Path::new("sftp://example.com:2222/srv/www")).unwrap(),
+            SftpTarget {
+                user: None,
+                host: "example.com".to_string(),
+                port: 2222,
+                path: "/srv/www".to_string(),
+            }
+        );
+        assert_eq!(
+            SftpTarget::parse(&PathBuf::from("sftp://deploy@example.com:/srv").join("a.txt")).unwrap().path,
+            "/srv/a.txt"
+        );
+
+        assert!(SftpTarget::parse(Path::new("sftp://example.com")).is_err());
+        assert!(SftpTarget::parse(Path::new("sftp://:/srv")).is_err());
+        assert!(SftpTarget::parse(Path::new("sftp://@example.com:/srv")).is_err());
+        assert!(SftpTarget::parse(Path::new("sftp://example.com:port/srv")).is_err());
+        assert!(SftpTarget::parse(Path::new("test-target/srv")).is_err());
+
+        assert!(is_remote_target(Path::new("sftp://example.com:/srv")));
+        assert!(!is_remote_target(Path::new("test-target/sftp")));
+    }
+
+    /// 需启用 `sftp` feature，并以 `DISTRIBUTOR_SFTP_TEST_TARGET` 指定可通过 SSH agent 登录的目标目录。
+    #[test]
+    #[ignore]
+    #[cfg(feature = "sftp")]
+    fn test_distribute_remote_file() {
+        let target = PathBuf::from(std::env::var("DISTRIBUTOR_SFTP_TEST_TARGET").unwrap()).join("template.txt");
+        let source = Path::new("resource/template.txt");
+
+        let options = RunOptions::default();
+        let result = distribute_remote_file(source, &target, &options);
+        assert!(matches!(result, Ok(crate::distributor::DistributorResultType::Copied(_, _))));
+
+        println!("the second file reuses the connection");
+        let result = distribute_remote_file(source, &target, &options);
+        assert!(matches!(result, Ok(crate::distributor::DistributorResultType::Same(_, _))));
+    }
+}
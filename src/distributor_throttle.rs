@@ -0,0 +1,119 @@
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// # 写入速率限制器
+/// 令牌桶，以 bytes_per_sec 的速率补充令牌，最多积累 1 秒的令牌。
+/// 在同一进程的所有复制线程间共享，限制的是进程的总写入速率而非每个目标的速率。
+#[derive(Debug)]
+pub struct RateLimiter {
+    bytes_per_sec: u64,
+    state: Mutex<BucketState>,
+}
+
+#[derive(Debug)]
+struct BucketState {
+    tokens: f64,
+    last: Instant,
+}
+
+impl RateLimiter {
+    /// 创建速率限制器。
+    ///
+    /// # Param
+    ///
+    /// - `bytes_per_sec` - 每秒允许写入的字节数，为 0 时视为 1。
+    pub fn new(bytes_per_sec: u64) -> Self {
+        let bytes_per_sec = bytes_per_sec.max(1);
+        RateLimiter {
+            bytes_per_sec,
+            state: Mutex::new(BucketState {
+                tokens: bytes_per_sec as f64,
+                last: Instant::now(),
+            }),
+        }
+    }
+
+    pub fn bytes_per_sec(&self) -> u64 {
+        self.bytes_per_sec
+    }
+
+    /// 消耗 bytes 个令牌，令牌不足时阻塞至补足。
+    /// 令牌可以为负，超过桶容量的写入由后续写入等待偿还。
+    pub fn acquire(&self, bytes: usize) {
+        let rate = self.bytes_per_sec as f64;
+        let wait = {
+            let mut state = self.state.lock().unwrap();
+            let now = Instant::now();
+            let elapsed = now.duration_since(state.last).as_secs_f64();
+            state.tokens = (state.tokens + elapsed * rate).min(rate) - bytes as f64;
+            state.last = now;
+
+            if state.tokens < 0.0 {
+                Duration::from_secs_f64(-state.tokens / rate)
+            } else {
+                Duration::ZERO
+            }
+        };
+
+        if !wait.is_zero() {
+            std::thread::sleep(wait);
+        }
+    }
+}
+
+/// # 限速写入器
+/// 每次写入后向限制器申请已写入的字节数，未指定限制器时直接写入。
+pub struct ThrottledWriter<'a, W: Write> {
+    inner: W,
+    limiter: Option<&'a RateLimiter>,
+}
+
+impl<'a, W: Write> ThrottledWriter<'a, W> {
+    pub fn new(inner: W, limiter: Option<&'a RateLimiter>) -> Self {
+        ThrottledWriter { inner, limiter }
+    }
+}
+
+impl<W: Write> Write for ThrottledWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        if let Some(limiter) = self.limiter {
+            limiter.acquire(written);
+        }
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    #[test]
+    fn test_throttled_writer() {
+        let data = vec![0u8; 64 * 1024];
+
+        println!("the first second is covered by the initial tokens");
+        let limiter = RateLimiter::new(32 * 1024);
+        let start = Instant::now();
+        let mut writer = ThrottledWriter::new(Vec::new(), Some(&limiter));
+        writer.write_all(&data).unwrap();
+        let elapsed = start.elapsed();
+
+        assert_eq!(writer.inner.len(), data.len());
+        assert!(elapsed >= Duration::from_millis(900), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(5), "{:?}", elapsed);
+
+        let start = Instant::now();
+        ThrottledWriter::new(Vec::new(), None).write_all(&data).unwrap();
+        assert!(start.elapsed() < Duration::from_millis(500));
+    }
+}
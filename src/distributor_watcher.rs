@@ -0,0 +1,187 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::Duration;
+
+use notify::{EventKind, RecursiveMode, Watcher};
+
+use crate::distributor::{Distributor, RunOptions};
+use crate::distributor_config::DistributorItem;
+
+/// 事件防抖时长。
+static DEBOUNCE_DURATION: Duration = Duration::from_millis(300);
+
+#[derive(Debug)]
+pub enum DistributorWatchError {
+    NotifyError(notify::Error),
+    CtrlcError(ctrlc::Error),
+}
+
+impl From<notify::Error> for DistributorWatchError {
+    fn from(e: notify::Error) -> Self {
+        DistributorWatchError::NotifyError(e)
+    }
+}
+
+impl From<ctrlc::Error> for DistributorWatchError {
+    fn from(e: ctrlc::Error) -> Self {
+        DistributorWatchError::CtrlcError(e)
+    }
+}
+
+impl std::fmt::Display for DistributorWatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DistributorWatchError::NotifyError(e) => write!(f, "watch error: {}", e),
+            DistributorWatchError::CtrlcError(e) => write!(f, "ctrl-c handler error: {}", e),
+        }
+    }
+}
+
+/// 监听所有条目的 root，源文件变化时重新分发对应条目。
+/// 按下 Ctrl-C 后返回。
+///
+/// # Param
+///
+/// - `distributor` - 分发器。
+/// - `items` - 待监听的配置条目。
+/// - `options` - 运行选项。
+pub fn watch(distributor: &mut Distributor,
+             items: &[&DistributorItem],
+             options: &RunOptions) -> Result<(), DistributorWatchError> {
+    let running = Arc::new(AtomicBool::new(true));
+    let running_handler = running.clone();
+    ctrlc::set_handler(move || running_handler.store(false, Ordering::SeqCst))?;
+
+    let (tx, rx) = channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+
+    let expanded: Vec<DistributorItem> = items.iter().map(|item| item.expanded()).collect();
+    let mut roots = Vec::new();
+    for item in expanded.iter() {
+        for item_root in item.get_roots() {
+            let Ok(root) = item_root.canonicalize() else {
+                tracing::warn!(distributor = %item.name, root = ?item_root, "[Watch] root not exist, skipped.");
+                continue;
+            };
+            watcher.watch(&root, RecursiveMode::Recursive)?;
+            roots.push((item, root));
+        }
+    }
+
+    tracing::info!("[Watch] watching {} roots. press Ctrl-C to exit.", roots.len());
+
+    while running.load(Ordering::SeqCst) {
+        let mut changed: Vec<PathBuf> = Vec::new();
+        match rx.recv_timeout(DEBOUNCE_DURATION) {
+            Ok(event) => collect_event_path(event, &mut changed),
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        while let Ok(event) = rx.recv_timeout(DEBOUNCE_DURATION) {
+            collect_event_path(event, &mut changed);
+        }
+
+        for item in get_affected_items(&roots, &changed, &options.global_ignore) {
+            tracing::info!(distributor = %item.name, "[Watch] distribute.");
+            distributor.do_copy_and_print(item, options);
+        }
+    }
+
+    tracing::info!("[Watch] exit.");
+    Ok(())
+}
+
+/// 收集变化路径。读取等访问事件将被忽略，以免分发自身触发新的分发。
+fn collect_event_path(event: notify::Result<notify::Event>, changed: &mut Vec<PathBuf>) {
+    match event {
+        Ok(event) => {
+            if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)) {
+                changed.extend(event.paths);
+            }
+        }
+        Err(e) => tracing::error!("[Watch] {}", e),
+    }
+}
+
+/// 获取受变化路径影响的条目，被 ignore 匹配的路径不会触发分发。
+/// 每个根目录的 ignore 规则仅读取一次。
+///
+/// # Param
+///
+/// - `roots` - 条目与其各个根目录的绝对路径，同一条目可出现多次。
+/// - `changed` - 变化的绝对路径。
+/// - `global` - 应用于所有条目的 ignore。
+fn get_affected_items<'a>(roots: &[(&'a DistributorItem, PathBuf)],
+                          changed: &[PathBuf],
+                          global: &[String]) -> Vec<&'a DistributorItem> {
+    roots.iter()
+         .filter(|(item, root)| {
+             let relatives: Vec<&Path> = changed.iter().filter_map(|path| path.strip_prefix(root).ok()).collect();
+             if relatives.is_empty() {
+                 return false;
+             }
+
+             let ignores = item.get_ignore_globs(global).unwrap_or_default();
+             relatives.iter().any(|relative| !item.is_matched_by(&ignores, &join_relative(&item.root, relative)))
+         })
+         .map(|(item, _)| *item)
+         .fold(Vec::new(), |mut items, item| {
+             if !items.iter().any(|added| std::ptr::eq(*added, item)) {
+                 items.push(item);
+             }
+             items
+         })
+}
+
+fn join_relative(root: &Path, relative: &Path) -> PathBuf {
+    if relative.as_os_str().is_empty() {
+        root.to_path_buf()
+    } else {
+        root.join(relative)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_affected_items() {
+        let item_a = DistributorItem {
+            name: "a".to_string(),
+            root: PathBuf::from("resource/sub-resource-dir-a"),
+            ignore: vec!["*.log".to_string()],
+            ..Default::default()
+        };
+        let item_b = DistributorItem {
+            name: "b".to_string(),
+            root: PathBuf::from("resource/sub-resource-dir-b"),
+            ..Default::default()
+        };
+        let roots = vec![
+            (&item_a, PathBuf::from("/abs/resource/sub-resource-dir-a")),
+            (&item_b, PathBuf::from("/abs/resource/sub-resource-dir-b")),
+        ];
+
+        let affected = get_affected_items(
+            &roots,
+            &[PathBuf::from("/abs/resource/sub-resource-dir-a/template-a.txt")],
+            &[]);
+        assert_eq!(affected, vec![&item_a]);
+
+        println!("ignored change triggers nothing");
+        let affected = get_affected_items(
+            &roots,
+            &[PathBuf::from("/abs/resource/sub-resource-dir-a/debug.log")],
+            &[]);
+        assert!(affected.is_empty());
+
+        let affected = get_affected_items(
+            &roots,
+            &[PathBuf::from("/abs/resource/sub-resource-dir-b/Thumbs.db")],
+            &["Thumbs.db".to_string()]);
+        assert!(affected.is_empty());
+    }
+}
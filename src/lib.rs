@@ -0,0 +1,42 @@
+//! # Distributor
+//!
+//! 将 root 下的文件分发至多个 target，并以缓存跳过未变化的文件。
+//!
+//! ```
+//! use distributor::{Distributor, DistributorItem, FileDistributorCache, RunOptions, Verbosity};
+//!
+//! let temp_path = tempfile::tempdir().unwrap();
+//! let root = temp_path.path().join("resource");
+//! std::fs::create_dir_all(&root).unwrap();
+//! std::fs::write(root.join("template.txt"), "test").unwrap();
+//!
+//! let item = DistributorItem::builder("resource", &root)
+//!     .target(temp_path.path().join("target"))
+//!     .build();
+//! let mut distributor = Distributor::with_cache(FileDistributorCache::load(Some(&temp_path.path().join("cache.db"))),
+//!                                               Verbosity::Quiet);
+//! let results = distributor.do_copy(&item, &RunOptions { verbosity: Verbosity::Quiet, ..Default::default() });
+//!
+//! assert!(results.iter().all(|result| result.is_ok()));
+//! assert!(temp_path.path().join("target/template.txt").is_file());
+//! ```
+
+pub mod distributor;
+pub mod distributor_archive;
+pub mod distributor_cache_db;
+pub mod distributor_config;
+pub mod distributor_history;
+pub mod distributor_manifest;
+pub mod distributor_prompt;
+pub mod distributor_remote;
+pub mod distributor_throttle;
+pub mod distributor_watcher;
+
+pub use distributor::{
+    ConflictStrategy, Distributor, DistributorError, DistributorResult, DistributorResultRecord, DistributorResultType, LinkMode,
+    RunOptions, RunSummary, Template, Verbosity,
+};
+pub use distributor_cache_db::{CacheStrategy, FileDistributorCache, HashAlgorithm};
+pub use distributor_config::{
+    DistributorConfigError, DistributorConfiguration, DistributorItem, DistributorItemBuilder, ValidationIssue,
+};
@@ -1,14 +1,20 @@
 use std::borrow::Cow;
 use std::env;
+use std::io::IsTerminal;
 use std::path::{Path, PathBuf};
 
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand};
+use clap_complete::engine::{ArgValueCandidates, CompletionCandidate};
+use clap_complete::env::Shells;
+use clap_complete::CompleteEnv;
 
-use crate::distributor_config::DistributorConfiguration;
-
-mod distributor;
-mod distributor_config;
-mod distributor_cache_db;
+use ::distributor::distributor_cache_db::{CacheFormat, CacheLock, CacheStrategy, FileDistributorCache, HashAlgorithm};
+use ::distributor::distributor_config::{self, DistributorConfiguration, DistributorItem};
+use ::distributor::distributor_history::{self, HistoryEntry};
+use ::distributor::distributor_manifest::Manifest;
+use ::distributor::distributor_prompt::{OverwritePrompt, StdinLineReader};
+use ::distributor::distributor_throttle::RateLimiter;
+use ::distributor::{distributor, distributor_watcher};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -23,58 +29,395 @@ struct Cli {
     /// do not reset working directory to the directory of the executable.
     #[arg(short, long)]
     no_reset_working_directory: bool,
+
+    /// show more output, -v for unchanged files, -vv for per-file timing.
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// print errors only.
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// cache file path, overrides cache_path in config. its format follows the extension.
+    #[arg(long, global = true)]
+    cache: Option<PathBuf>,
+
+    /// cache file format, json is human-readable.
+    #[arg(long, value_enum, global = true, default_value_t = CacheFormat::Bincode)]
+    cache_format: CacheFormat,
 }
 
 #[derive(Subcommand)]
 enum Commands {
+    /// create a starter config.
+    Init {
+        /// overwrite existing config.
+        #[arg(short, long)]
+        force: bool,
+    },
     /// add distributor.
     Add {
         /// distributor name.
+        #[arg(add = ArgValueCandidates::new(get_distributor_names))]
         name: String,
         /// source root path.
         #[arg(short, long)]
         root: Option<PathBuf>,
-        /// target path.
-        #[arg(short, long)]
-        target: Option<PathBuf>,
+        /// target paths. nothing is saved if any target is rejected.
+        #[arg(short, long, num_args = 1..)]
+        target: Vec<PathBuf>,
+        /// file name in targets, only for file root.
+        #[arg(long, requires = "target")]
+        rename: Option<String>,
     },
     /// add ignore glob of source.
     Ignore {
         /// distributor name.
+        #[arg(add = ArgValueCandidates::new(get_distributor_names))]
         name: String,
-        /// ignore glob path.
+        /// ignore glob paths.
+        #[arg(short, long, num_args = 1.., required = true)]
+        glob: Vec<String>,
+    },
+    /// add or remove tag of distributor.
+    Tag {
+        /// distributor name.
+        #[arg(add = ArgValueCandidates::new(get_distributor_names))]
+        name: String,
+        /// tag name.
+        tag: String,
+        /// remove the tag instead of adding it.
         #[arg(short, long)]
+        remove: bool,
+    },
+    /// add or remove ignore glob applied to all distributors.
+    GlobalIgnore {
+        /// ignore glob path.
         glob: String,
+        /// remove the glob instead of adding it.
+        #[arg(short, long)]
+        remove: bool,
     },
     /// remove target of source.
     /// if no target is provided, remove them all.
     Remove {
         /// distributor name.
+        #[arg(add = ArgValueCandidates::new(get_distributor_names))]
         name: String,
         /// target path.
         #[arg(short, long)]
         target: Option<PathBuf>,
     },
-    /// print config.
-    List,
+    /// change target path of distributor, keeping its order.
+    MoveTarget {
+        /// distributor name.
+        #[arg(add = ArgValueCandidates::new(get_distributor_names))]
+        name: String,
+        /// current target path.
+        from: PathBuf,
+        /// new target path.
+        to: PathBuf,
+    },
+    /// enable distributor.
+    Enable {
+        /// distributor name.
+        #[arg(add = ArgValueCandidates::new(get_distributor_names))]
+        name: String,
+    },
+    /// disable distributor, it will be skipped by run.
+    Disable {
+        /// distributor name.
+        #[arg(add = ArgValueCandidates::new(get_distributor_names))]
+        name: String,
+    },
+    /// rename distributor.
+    Rename {
+        /// current distributor name.
+        #[arg(add = ArgValueCandidates::new(get_distributor_names))]
+        old: String,
+        /// new distributor name.
+        new: String,
+    },
+    /// duplicate distributor under a new name.
+    Clone {
+        /// source distributor name.
+        #[arg(add = ArgValueCandidates::new(get_distributor_names))]
+        from: String,
+        /// new distributor name.
+        to: String,
+    },
+    /// print distributors, or details of one distributor.
+    List {
+        /// distributor name.
+        #[arg(add = ArgValueCandidates::new(get_distributor_names))]
+        name: Option<String>,
+    },
+    /// check config health.
+    Validate,
+    /// print outdated and missing files without copying.
+    Status {
+        /// distributor name, all distributors if not provided.
+        #[arg(add = ArgValueCandidates::new(get_distributor_names))]
+        name: Option<String>,
+    },
+    /// print source files of distributor as a tree, ignored files are marked as excluded.
+    Tree {
+        /// distributor name.
+        #[arg(add = ArgValueCandidates::new(get_distributor_names))]
+        name: String,
+    },
+    /// print number of source files.
+    Count {
+        /// distributor name, all distributors if not provided.
+        #[arg(add = ArgValueCandidates::new(get_distributor_names))]
+        name: Option<String>,
+    },
+    /// print total size of source files.
+    Size {
+        /// distributor name, all distributors if not provided.
+        #[arg(add = ArgValueCandidates::new(get_distributor_names))]
+        name: Option<String>,
+    },
+    /// compare targets with sources byte by byte without modifying anything.
+    Verify {
+        /// distributor name, all distributors if not provided.
+        #[arg(add = ArgValueCandidates::new(get_distributor_names))]
+        name: Option<String>,
+        /// compare text files with `{{key}}` replaced, as run --template writes them.
+        #[arg(long)]
+        template: bool,
+    },
+    /// copy files changed in a target back into the source root.
+    Pull {
+        /// distributor name.
+        #[arg(add = ArgValueCandidates::new(get_distributor_names))]
+        name: String,
+        /// target path to pull from.
+        target: PathBuf,
+        /// report what would be pulled without writing files.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// get or set config value by `<name>.<field>`.
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
     /// clear cache.
-    Clear,
+    Clear {
+        /// distributor name, all distributors if not provided.
+        #[arg(add = ArgValueCandidates::new(get_distributor_names))]
+        name: Option<String>,
+    },
+    /// remove cache entries of files that no longer exist.
+    Prune,
+    /// print recent runs recorded in the history.
+    History {
+        /// number of most recent runs to print, all runs if not provided.
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    /// print shell completion script to stdout, distributor names are completed from the default config.
+    Completions {
+        /// target shell.
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+    },
     /// run distributor.
     Run {
+        /// distributor name, all distributors if not provided.
+        #[arg(add = ArgValueCandidates::new(get_distributor_names))]
+        name: Option<String>,
+
+        /// only run distributors with any of the tags.
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+
         /// force run copy.
         #[arg(short, long)]
         force: bool,
 
-        /// silence output.
+        /// silence output, same as --quiet.
         #[arg(short, long)]
         silence: bool,
+
+        /// ignore cache, copy only when source is newer than target.
+        #[arg(long)]
+        mtime_only: bool,
+
+        /// ignore cache, copy only files changed or untracked in git.
+        #[arg(long)]
+        changed_only: bool,
+
+        /// report what would be copied without writing files or cache.
+        #[arg(long)]
+        dry_run: bool,
+
+        /// number of copy threads, defaults to the number of CPUs.
+        #[arg(long, default_value_t = 0)]
+        threads: usize,
+
+        /// detect outdated files by content hash instead of modified time, hashed by blake3 unless given like --hash=sha256.
+        /// the algorithm is also used by --manifest.
+        #[arg(long, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "blake3")]
+        hash: Option<HashAlgorithm>,
+
+        /// delete source files after they are copied to every target.
+        #[arg(long = "move")]
+        move_source: bool,
+
+        /// link targets to sources instead of copying.
+        #[arg(long, value_enum)]
+        link: Option<distributor::LinkMode>,
+
+        /// delete target files that no longer exist in source.
+        #[arg(long)]
+        mirror: bool,
+
+        /// keep permissions and modified time of source files.
+        #[arg(long)]
+        preserve: bool,
+
+        /// keep running and distribute again when source files change.
+        #[arg(short, long)]
+        watch: bool,
+
+        /// replace `{{key}}` in text files with variables of the distributor.
+        #[arg(long)]
+        template: bool,
+
+        /// private key for sftp:// targets, ssh agent is used if not provided.
+        #[arg(long)]
+        identity: Option<PathBuf>,
+
+        /// write each target to a temp file first and rename it into place.
+        #[arg(long)]
+        atomic: bool,
+
+        /// how to handle an existing target that differs from its source.
+        #[arg(long, value_enum, default_value_t = distributor::ConflictStrategy::Overwrite)]
+        on_conflict: distributor::ConflictStrategy,
+
+        /// ask before overwriting an existing target that differs, skip when stdin is not a terminal.
+        #[arg(short, long)]
+        interactive: bool,
+
+        /// create empty source directories in targets.
+        #[arg(long)]
+        include_empty_dirs: bool,
+
+        /// stop the run at the first failed copy.
+        #[arg(long)]
+        fail_fast: bool,
+
+        /// number of distributors to run at the same time, results are printed per distributor.
+        #[arg(long, default_value_t = 1, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        jobs: usize,
+
+        /// do not append this run to the history.
+        #[arg(long)]
+        no_history: bool,
+
+        /// buffer size for copying and comparing files like 65536 or 1MB, defaults to 64KB.
+        #[arg(long, value_parser = distributor::parse_buffer_size)]
+        buffer_size: Option<std::num::NonZeroUsize>,
+
+        /// skip source files larger than a size like 4096, 100KB or 1.5MB.
+        #[arg(long, value_parser = distributor::parse_size)]
+        max_file_size: Option<u64>,
+
+        /// only distribute files modified after an rfc3339 time or a duration ago like 30m or 2h.
+        #[arg(long, value_parser = distributor::parse_since)]
+        since: Option<std::time::SystemTime>,
+
+        /// put files directly in targets without the directory structure of sources.
+        #[arg(long)]
+        flatten: bool,
+
+        /// write hash and size of copied files to a json manifest.
+        #[arg(long)]
+        manifest: Option<PathBuf>,
+
+        /// max write speed in bytes per second, shared by all targets of the process.
+        #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+        limit: Option<u64>,
+
+        /// output format of results.
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
     },
 }
 
+#[derive(Clone, Copy, PartialEq, clap::ValueEnum)]
+enum OutputFormat {
+    /// human-readable lines.
+    Text,
+    /// a json object of results and their summary.
+    Json,
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// print value of `<name>.<field>`,
+    /// field is one of root, roots, ignore, to, max_files, max_total_bytes, max_file_size, max_depth, use_gitignore, case_insensitive_ignore.
+    Get {
+        /// config key.
+        key: String,
+    },
+    /// set value of `<name>.<field>`.
+    /// root, limits and flags will be replaced, roots, ignore and to will be appended.
+    Set {
+        /// config key.
+        key: String,
+        /// config value.
+        value: String,
+    },
+}
+
+/// 补全时获取默认配置中的 distributor 名称。
+/// 补全无法获知 `-c`，因此读取可执行文件所在目录下的默认配置，与未指定 `-c` 时的运行一致。
+fn get_distributor_names() -> Vec<CompletionCandidate> {
+    let Some(config_path) = env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|exe_dir| exe_dir.join("distributor-config.toml"))) else {
+        return vec![];
+    };
+
+    DistributorConfiguration::read_from(&config_path)
+        .unwrap_or_default()
+        .iter()
+        .map(|item| CompletionCandidate::new(&item.name))
+        .collect()
+}
+
 fn main() {
+    CompleteEnv::with_factory(Cli::command).complete();
     let cli = Cli::parse();
 
-    show_welcome();
+    if let Some(Commands::Completions { shell }) = cli.command {
+        let command = Cli::command();
+        let bin_name = command.get_name();
+        let completer = env::current_exe().map(|exe| exe.to_string_lossy().to_string())
+                                          .unwrap_or_else(|_| bin_name.to_string());
+        let registered = Shells::builtins()
+            .completer(&shell.to_string())
+            .map(|env_completer| {
+                env_completer.write_registration("COMPLETE", bin_name, bin_name, &completer, &mut std::io::stdout())
+            });
+        if !matches!(registered, Some(Ok(_))) {
+            eprintln!("write completion script for {} failed.", shell);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let json_output = matches!(cli.command, Some(Commands::Run { format: OutputFormat::Json, .. }));
+    let verbosity = distributor::Verbosity::from_flags(cli.verbose, cli.quiet);
+    let silence = matches!(cli.command, Some(Commands::Run { silence: true, .. }));
+    init_tracing(if json_output || silence { distributor::Verbosity::Quiet } else { verbosity });
+    if !json_output && verbosity > distributor::Verbosity::Quiet {
+        show_welcome();
+    }
 
     if !cli.no_reset_working_directory {
         set_exe_path_as_current();
@@ -88,35 +431,90 @@ fn main() {
         Cow::Borrowed(Path::new("distributor-config.toml"))
     };
 
-    config = DistributorConfiguration::read_from(config_path.as_ref());
+    config = match DistributorConfiguration::read_from(config_path.as_ref()) {
+        Ok(config) => config,
+        // init 不读取已有配置，允许以 --force 覆盖无法解析的配置文件。
+        Err(_) if matches!(cli.command, Some(Commands::Init { .. })) => DistributorConfiguration::default(),
+        Err(e) => {
+            tracing::error!(path = ?config_path, "read config failed. {}.", e);
+            std::process::exit(1);
+        }
+    };
+    let cache_path = cli.cache
+                        .or_else(|| config.cache_path().map(distributor_config::expand_path))
+                        .unwrap_or_else(|| cli.cache_format.default_path());
+    let cache_base = config.relative_cache().then(|| get_config_dir(config_path.as_ref()));
     if let Some(command) = cli.command {
         match command {
-            Commands::Add { name, root, target } => {
+            Commands::Init { force } => {
+                let example = DistributorConfiguration::example();
+                let path = example.resolve_save_path(config_path.as_ref());
+                if path.exists() && !force {
+                    println!("config {:?} already exists. use --force to overwrite.", path);
+                    return;
+                }
+
+                example.save_to(&path);
+                println!("config created at {:?}.", path);
+            }
+            Commands::Add { name, root, target, rename } => {
                 if !config.has_distributor(name.as_str()) {
                     if let Some(root) = root {
                         let result = config.add_distributor(name.as_str(),
                                                             root.as_path());
                         if let Err(e) = result {
-                            println!("add distributor failed. {:?}", e);
-                            return;
+                            tracing::error!("add distributor failed. {:?}", e);
+                            std::process::exit(1);
                         }
                     } else {
-                        println!("add distributor failed. root path is required.");
-                        return;
+                        tracing::error!("add distributor failed. root path is required.");
+                        std::process::exit(1);
                     }
                 }
 
-                if let Some(t) = target {
-                    config.add_target(&name, &t).expect("add target failed.");
+                // 任一目标被拒绝时不保存，已接受的目标同样不会写入配置。
+                if let Err(e) = config.add_targets(&name, &target) {
+                    tracing::error!("add target failed. {}. nothing is saved.", e);
+                    std::process::exit(1);
+                }
+                if let Some(file_name) = rename {
+                    for t in target.iter() {
+                        if let Err(e) = config.set_target_rename(&name, t, &file_name) {
+                            tracing::error!("set rename failed. {}.", e);
+                            std::process::exit(1);
+                        }
+                    }
                 }
 
                 config.save_to(config_path.as_ref());
             }
             Commands::Ignore { name, glob } => {
-                if config.add_ignore(&name, glob.as_str()).is_ok() {
+                if config.add_ignores(&name, &glob).is_ok() {
                     config.save_to(config_path.as_ref());
                 }
             }
+            Commands::Tag { name, tag, remove } => {
+                let result = if remove {
+                    config.remove_tag(&name, &tag)
+                } else {
+                    config.add_tag(&name, &tag)
+                };
+                match result {
+                    Ok(_) => config.save_to(config_path.as_ref()),
+                    Err(e) => tracing::error!("update tag failed. {:?}", e),
+                }
+            }
+            Commands::GlobalIgnore { glob, remove } => {
+                let result = if remove {
+                    config.remove_global_ignore(glob.as_str())
+                } else {
+                    config.add_global_ignore(glob.as_str())
+                };
+                match result {
+                    Ok(_) => config.save_to(config_path.as_ref()),
+                    Err(e) => tracing::error!("update global ignore failed. {:?}", e),
+                }
+            }
             Commands::Remove { name, target } => {
                 if let Some(t) = target {
                     if config.remove_target(&name, t.as_path()).is_ok() {
@@ -126,28 +524,472 @@ fn main() {
                     config.save_to(config_path);
                 }
             }
-            Commands::List {} => {
-                println!("{:#?}", config);
+            Commands::MoveTarget { name, from, to } => {
+                match config.move_target(&name, &from, &to) {
+                    Ok(_) => config.save_to(config_path.as_ref()),
+                    Err(e) => tracing::error!("move target failed. {:?}", e),
+                }
+            }
+            Commands::Enable { name } => {
+                if config.set_enabled(&name, true).is_ok() {
+                    config.save_to(config_path.as_ref());
+                }
+            }
+            Commands::Disable { name } => {
+                if config.set_enabled(&name, false).is_ok() {
+                    config.save_to(config_path.as_ref());
+                }
+            }
+            Commands::Rename { old, new } => {
+                match config.rename_distributor(&old, &new) {
+                    Ok(_) => config.save_to(config_path.as_ref()),
+                    Err(e) => tracing::error!("rename distributor failed. {:?}", e),
+                }
+            }
+            Commands::Clone { from, to } => {
+                match config.clone_distributor(&from, &to) {
+                    Ok(_) => config.save_to(config_path.as_ref()),
+                    Err(e) => tracing::error!("clone distributor failed. {:?}", e),
+                }
+            }
+            Commands::List { name } => {
+                let db_cache = load_cache(&cache_path, cache_base.as_deref());
+                if let Some(name) = name {
+                    if let Some(item) = config.get_distributor(&name) {
+                        print_distributor(item);
+                        println!("last run: {}", db_cache.get_last_run(&item.name).unwrap_or("never"));
+                    } else {
+                        println!("no such distributor: {}.", name);
+                        std::process::exit(1);
+                    }
+                } else {
+                    config.iter().for_each(|item| {
+                        println!("{} {:?}{}{}",
+                                 item.name,
+                                 item.root,
+                                 if item.enabled { "" } else { " (disabled)" },
+                                 db_cache.get_last_run(&item.name)
+                                         .map(|last_run| format!(" last run {}", last_run))
+                                         .unwrap_or_default());
+                    });
+                }
+            }
+            Commands::Config { action } => {
+                match action {
+                    ConfigAction::Get { key } => {
+                        match config.get_value(&key) {
+                            Ok(values) => values.iter().for_each(|v| println!("{}", v)),
+                            Err(e) => {
+                                tracing::error!("get config failed. {:?}", e);
+                                std::process::exit(1);
+                            }
+                        }
+                    }
+                    ConfigAction::Set { key, value } => {
+                        match config.set_value(&key, &value) {
+                            Ok(_) => config.save_to(config_path.as_ref()),
+                            Err(e) => {
+                                tracing::error!("set config failed. {:?}", e);
+                                std::process::exit(1);
+                            }
+                        }
+                    }
+                }
+            }
+            Commands::Run { name, tags, force, silence, mtime_only, changed_only, dry_run, threads, hash, move_source, link, mirror, preserve, watch, template, identity, atomic, on_conflict, interactive, include_empty_dirs, fail_fast, jobs, no_history, buffer_size, max_file_size, since, flatten, limit, manifest, format } => {
+                let piped = interactive && !std::io::stdin().is_terminal();
+                if piped {
+                    tracing::warn!("stdin is not a terminal, existing targets that differ will be skipped.");
+                }
+                let options = distributor::RunOptions {
+                    force,
+                    verbosity: if silence || format == OutputFormat::Json {
+                        distributor::Verbosity::Quiet
+                    } else {
+                        verbosity
+                    },
+                    mtime_only,
+                    changed_only,
+                    dry_run,
+                    threads,
+                    move_source,
+                    link,
+                    mirror,
+                    preserve,
+                    global_ignore: config.global_ignore()
+                                         .iter()
+                                         .map(|glob| distributor_config::expand_env_vars(glob))
+                                         .collect(),
+                    default_target: config.default_target().map(Path::to_path_buf),
+                    template: template.then(distributor::Template::default),
+                    identity,
+                    atomic,
+                    on_conflict: if piped { distributor::ConflictStrategy::Skip } else { on_conflict },
+                    prompt: (interactive && !piped).then(|| std::sync::Arc::new(OverwritePrompt::new(StdinLineReader))),
+                    fail_fast,
+                    include_empty_dirs,
+                    max_file_size,
+                    since,
+                    flatten,
+                    limit: limit.map(|limit| std::sync::Arc::new(RateLimiter::new(limit))),
+                    manifest: manifest.as_ref().map(|_| std::sync::Arc::new(Manifest::default())),
+                    hash_algorithm: hash.unwrap_or_default(),
+                    buffer_size,
+                    sftp_sessions: Default::default(),
+                    on_result: None,
+                };
+                let items: Vec<_> = config.iter_tagged(&tags)
+                                          .filter(|config_item| name.as_ref().is_none_or(|name| &config_item.name == name))
+                                          .filter(|config_item| config_item.enabled)
+                                          .collect();
+                if let Some(name) = name.as_ref().filter(|name| !config.has_distributor(name)) {
+                    println!("no such distributor: {}.", name);
+                    std::process::exit(1);
+                }
+                let mut results = Vec::new();
+                {
+                    let _lock = acquire_cache_lock(&cache_path);
+                    let db_cache = load_cache(&cache_path, cache_base.as_deref());
+                    let mut distributor = distributor::Distributor::with_cache(db_cache, options.verbosity);
+                    if let Some(algorithm) = hash {
+                        distributor.db_cache.set_strategy(CacheStrategy::Hash);
+                        distributor.db_cache.set_hash_algorithm(algorithm);
+                    }
+                    if jobs > 1 {
+                        for item_results in distributor.do_copy_items(&items, &options, jobs) {
+                            distributor::log_results(&item_results);
+                            results.extend(item_results);
+                        }
+                    } else {
+                        for config_item in items.iter() {
+                            let item_results = distributor.do_copy(config_item, &options);
+                            distributor::log_results(&item_results);
+                            results.extend(item_results);
+                            if fail_fast && results.iter().any(|result| result.is_err()) {
+                                break;
+                            }
+                        }
+                    }
+
+                    if watch && !(fail_fast && results.iter().any(|result| result.is_err())) {
+                        if let Err(e) = distributor_watcher::watch(&mut distributor, &items, &options) {
+                            tracing::error!("watch failed. {}", e);
+                        }
+                    }
+                }
+
+                if let (Some(path), Some(recorded)) = (&manifest, &options.manifest) {
+                    if let Err(e) = recorded.save(path, &items) {
+                        tracing::error!("write manifest failed. {}.", e);
+                    }
+                }
+
+                if !no_history && !dry_run {
+                    let entry = HistoryEntry::new(items.iter().map(|item| item.name.clone()).collect(), &results);
+                    if let Err(e) = distributor_history::append_history(Path::new(distributor_history::DEFAULT_HISTORY_PATH),
+                                                                        &entry) {
+                        tracing::error!("write history failed. {}.", e);
+                    }
+                }
+
+                if format == OutputFormat::Json {
+                    let records: Vec<distributor::DistributorResultRecord> = results.iter()
+                                                                                    .map(|result| result.into())
+                                                                                    .collect();
+                    let report = serde_json::json!({
+                        "results": records,
+                        "summary": distributor::RunSummary::from_results(&results),
+                    });
+                    println!("{}", report);
+                } else if options.verbosity > distributor::Verbosity::Quiet {
+                    println!("{}.", distributor::RunSummary::from_results(&results));
+                    for e in results.iter().filter_map(|result| result.as_ref().err()) {
+                        println!("[Failed] {}", e);
+                    }
+                }
+
+                if results.iter().any(|result| result.is_err()) {
+                    std::process::exit(1);
+                }
+            }
+            Commands::Validate => {
+                let issues = config.validate();
+                for issue in issues.iter() {
+                    println!("[{}] {}", if issue.is_error() { "Error" } else { "Warning" }, issue);
+                }
+                if issues.is_empty() {
+                    println!("config is valid.");
+                }
+                if issues.iter().any(|issue| issue.is_error()) {
+                    std::process::exit(1);
+                }
+            }
+            Commands::Status { name } => {
+                let db_cache = load_cache(&cache_path, cache_base.as_deref());
+                let items: Vec<_> = config.iter()
+                                          .filter(|item| name.as_ref().is_none_or(|name| &item.name == name))
+                                          .collect();
+                if items.is_empty() {
+                    println!("no such distributor.");
+                }
+                for item in items {
+                    println!("[{}] last run: {}", item.name, db_cache.get_last_run(&item.name).unwrap_or("never"));
+                    for status in distributor::get_item_status(item, &db_cache, config.global_ignore()) {
+                        println!("  {:?}: {} outdated, {} missing",
+                                 status.target,
+                                 status.outdated.len(),
+                                 status.missing.len());
+                        status.outdated.iter().for_each(|f| println!("    [Outdated]{:?}", f));
+                        status.missing.iter().for_each(|f| println!("    [Missing]{:?}", f));
+                    }
+                }
+            }
+            Commands::Verify { name, template } => {
+                let items: Vec<_> = config.iter()
+                                          .filter(|item| name.as_ref().is_none_or(|name| &item.name == name))
+                                          .collect();
+                if items.is_empty() {
+                    println!("no such distributor.");
+                    std::process::exit(1);
+                }
+                let template = template.then(distributor::Template::default);
+                let mut failed = false;
+                for item in items {
+                    println!("[{}]", item.name);
+                    for verification in distributor::verify_item(item, config.global_ignore(), template.as_ref()) {
+                        println!("  {:?}: {} mismatched, {} missing",
+                                 verification.target,
+                                 verification.mismatched.len(),
+                                 verification.missing.len());
+                        verification.mismatched.iter().for_each(|f| println!("    [Mismatched]{:?}", f));
+                        verification.missing.iter().for_each(|f| println!("    [Missing]{:?}", f));
+                        failed |= !verification.is_ok();
+                    }
+                }
+                if failed {
+                    std::process::exit(1);
+                }
             }
-            Commands::Run { force, silence } => {
-                let mut distributor = distributor::Distributor::new();
-                config.iter().for_each(|config_item| {
-                    distributor.do_copy(config_item, force, !silence);
+            Commands::Pull { name, target, dry_run } => {
+                let Some(item) = config.iter().find(|item| item.name == name) else {
+                    println!("no such distributor: {}.", name);
+                    std::process::exit(1);
+                };
+                let options = distributor::RunOptions {
+                    verbosity,
+                    dry_run,
+                    global_ignore: config.global_ignore()
+                                         .iter()
+                                         .map(|glob| distributor_config::expand_env_vars(glob))
+                                         .collect(),
+                    ..Default::default()
+                };
+                let results = distributor::pull_item(item, &target, &options);
+                distributor::log_results(&results);
+                if results.iter().any(|result| result.is_err()) {
+                    std::process::exit(1);
+                }
+            }
+            Commands::Tree { name } => {
+                let Some(item) = config.get_distributor(&name) else {
+                    println!("no such distributor: {}.", name);
+                    std::process::exit(1);
+                };
+                match item.expanded().render_source_tree(config.global_ignore()) {
+                    Ok(tree) => print!("{}", tree),
+                    Err(e) => tracing::error!("render tree failed. {}.", e),
+                }
+            }
+            Commands::Count { name } => {
+                let items: Vec<_> = config.iter()
+                                          .filter(|item| name.as_ref().is_none_or(|name| &item.name == name))
+                                          .collect();
+                if items.is_empty() {
+                    println!("no such distributor.");
+                }
+                let mut total = 0;
+                for item in items.iter() {
+                    match item.expanded().get_source_count(config.global_ignore()) {
+                        Ok(count) => {
+                            total += count;
+                            println!("{}: {}", item.name, count);
+                        }
+                        Err(e) => tracing::error!("{}: count failed. {}.", item.name, e),
+                    }
+                }
+                if items.len() > 1 {
+                    println!("total: {}", total);
+                }
+            }
+            Commands::Size { name } => {
+                let items: Vec<_> = config.iter()
+                                          .filter(|item| name.as_ref().is_none_or(|name| &item.name == name))
+                                          .collect();
+                if items.is_empty() {
+                    println!("no such distributor.");
+                }
+                let mut total = 0;
+                for item in items.iter() {
+                    match item.expanded().get_source_size(config.global_ignore()) {
+                        Ok(size) => {
+                            total += size;
+                            println!("{}: {}", item.name, indicatif::HumanBytes(size));
+                        }
+                        Err(e) => tracing::error!("{}: get size failed. {}.", item.name, e),
+                    }
+                }
+                if items.len() > 1 {
+                    println!("total: {}", indicatif::HumanBytes(total));
+                }
+            }
+            Commands::History { limit } => {
+                match distributor_history::read_history(Path::new(distributor_history::DEFAULT_HISTORY_PATH), limit) {
+                    Ok(entries) => {
+                        if entries.is_empty() {
+                            println!("no history.");
+                        }
+                        for entry in entries.iter() {
+                            println!("{} [{}] {}.", entry.timestamp, entry.distributors.join(", "), entry.summary);
+                            entry.errors.iter().for_each(|e| println!("  [Failed] {}", e));
+                        }
+                    }
+                    Err(e) => tracing::error!("read history failed. {}.", e),
+                }
+            }
+            Commands::Completions { .. } => {}
+            Commands::Prune => {
+                let _lock = acquire_cache_lock(&cache_path);
+                let db_cache = load_cache(&cache_path, cache_base.as_deref());
+                let mut distributor = distributor::Distributor::with_cache(db_cache, verbosity);
+                let count = distributor.db_cache.prune();
+                if let Err(e) = distributor.db_cache.save(None) {
+                    tracing::error!("save cache failed. {}.", e);
+                }
+                println!("{} cache entries pruned.", count);
+            }
+            Commands::Clear { name } => {
+                let roots = name.map(|name| match config.get_distributor(&name) {
+                    Some(item) => item.expanded().get_roots().cloned().collect::<Vec<_>>(),
+                    None => {
+                        println!("no such distributor: {}.", name);
+                        std::process::exit(1);
+                    }
                 });
+
+                let _lock = acquire_cache_lock(&cache_path);
+                let db_cache = load_cache(&cache_path, cache_base.as_deref());
+                let mut distributor = distributor::Distributor::with_cache(db_cache, verbosity);
+                if let Some(roots) = roots {
+                    for root in roots.iter() {
+                        if let Err(e) = distributor.db_cache.clear_under(root) {
+                            tracing::error!("clear cache failed. {}.", e);
+                        }
+                    }
+                } else {
+                    distributor.clear_cache()
+                }
             }
-            Commands::Clear => {
-                let mut distributor = distributor::Distributor::new();
-                distributor.clear_cache()
+        }
+    }
+
+    /// 获取缓存锁，已有其他运行持有锁时退出。
+    fn acquire_cache_lock(cache_path: &Path) -> CacheLock {
+        match CacheLock::acquire(cache_path) {
+            Ok(lock) => lock,
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                tracing::error!("another run in progress. remove {:?} if it is not.", CacheLock::get_path(cache_path));
+                std::process::exit(1);
+            }
+            Err(e) => {
+                tracing::error!("acquire cache lock failed. {}.", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    /// 加载缓存。指定 base 时缓存记录相对 base 的路径。
+    fn load_cache(cache_path: &Path, base: Option<&Path>) -> FileDistributorCache {
+        let mut db_cache = FileDistributorCache::load(Some(cache_path));
+        if let Some(base) = base {
+            db_cache.set_relative_base(base);
+        }
+
+        db_cache
+    }
+
+    /// 获取配置文件所在目录的绝对路径。
+    fn get_config_dir(config_path: &Path) -> PathBuf {
+        let current = std::env::current_dir().unwrap_or_default();
+        match config_path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => current.join(parent),
+            _ => current,
+        }
+    }
+
+    fn print_distributor(item: &DistributorItem) {
+        println!("name: {}", item.name);
+        println!("root: {:?}", item.root);
+        item.roots.iter().for_each(|root| println!("  + {:?}", root));
+        println!("enabled: {}", item.enabled);
+        println!("ignore:");
+        item.ignore.iter().for_each(|glob| println!("  - {}", glob));
+        if let Some(ignore_file) = &item.ignore_file {
+            println!("  + {:?}", ignore_file);
+        }
+        println!("to:");
+        item.to.iter().for_each(|to| {
+            match item.get_target_file_name(to) {
+                Some(file_name) => println!("  - {:?} as {}", to, file_name),
+                None => println!("  - {:?}", to),
             }
+        });
+        if let Some(max_files) = item.max_files {
+            println!("max_files: {}", max_files);
+        }
+        if let Some(max_total_bytes) = item.max_total_bytes {
+            println!("max_total_bytes: {}", max_total_bytes);
+        }
+        if let Some(max_depth) = item.max_depth {
+            println!("max_depth: {}", max_depth);
+        }
+        if let Some(pre_run) = &item.pre_run {
+            println!("pre_run: {}", pre_run);
         }
+        if item.use_gitignore {
+            println!("use_gitignore: true");
+        }
+        if item.case_insensitive_ignore {
+            println!("case_insensitive_ignore: true");
+        }
+        if !item.tags.is_empty() {
+            println!("tags: {}", item.tags.join(", "));
+        }
+        if !item.variables.is_empty() {
+            println!("variables:");
+            item.variables.iter().for_each(|(key, value)| println!("  {} = {}", key, value));
+        }
+    }
+
+    /// 初始化输出至 stderr 的 tracing 事件。设置 RUST_LOG 时以其为准，否则按输出详细程度过滤。
+    fn init_tracing(verbosity: distributor::Verbosity) {
+        let filter = tracing_subscriber::EnvFilter::builder()
+            .with_default_directive(verbosity.level_filter().into())
+            .from_env_lossy();
+        tracing_subscriber::fmt()
+            .with_env_filter(filter)
+            .with_writer(std::io::stderr)
+            .with_ansi(std::io::stderr().is_terminal())
+            .with_target(false)
+            .without_time()
+            .init();
     }
 
     fn set_exe_path_as_current() {
-        println!("reset working directory.");
+        tracing::info!("reset working directory.");
         if let Ok(exe_path) = env::current_exe() {
             if let Some(exe_dir) = exe_path.parent() {
-                let _ = env::set_current_dir(&exe_dir);
-                println!("Current directory: {:?}", env::current_dir().unwrap());
+                let _ = env::set_current_dir(exe_dir);
+                tracing::info!("Current directory: {:?}", env::current_dir().unwrap());
             }
         }
     }